@@ -0,0 +1,411 @@
+use serde::{Deserialize, Serialize};
+
+use super::tempo::TempoSource;
+
+/// How much one source is trusted, and for how long.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SourcePolicy {
+    pub source: TempoSource,
+    /// Updates below this confidence never take charge.
+    pub min_confidence: f64,
+    /// A source that has not reported for this long is ignored. `None` for
+    /// operator sources that hold until replaced.
+    pub stale_after_ms: Option<u64>,
+    /// How long a source must stay live before it can take over from a
+    /// lower-priority one, so a brief glitch does not grab the clock.
+    pub takeover_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ArbiterPolicy {
+    /// Sources in priority order, highest first. Unlisted sources are ignored.
+    pub sources: Vec<SourcePolicy>,
+    /// When set, the leading tempo is blended with other live sources that
+    /// agree within this many BPM, weighted by confidence.
+    pub blend_tolerance_bpm: Option<f64>,
+}
+
+impl Default for ArbiterPolicy {
+    fn default() -> Self {
+        let source = |source, min_confidence, stale_after_ms, takeover_ms| SourcePolicy {
+            source,
+            min_confidence,
+            stale_after_ms,
+            takeover_ms,
+        };
+        Self {
+            sources: vec![
+                source(TempoSource::MidiClock, 0.5, Some(2_500), 1_000),
+                source(TempoSource::Link, 0.5, Some(3_000), 1_000),
+                source(TempoSource::Tap, 0.0, None, 0),
+                source(TempoSource::Manual, 0.0, None, 0),
+                source(TempoSource::Auto, 0.3, Some(5_000), 2_000),
+            ],
+            blend_tolerance_bpm: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CandidateState {
+    Leading,
+    /// Live, but a higher-priority source is in charge.
+    Outranked,
+    /// Live and outranking the leader, but not live for `takeover_ms` yet.
+    Pending,
+    Stale,
+    LowConfidence,
+    /// Not listed in the policy.
+    Disabled,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CandidateStatus {
+    pub source: TempoSource,
+    pub bpm: f64,
+    pub confidence: f64,
+    pub age_ms: u64,
+    pub state: CandidateState,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ArbiterStatus {
+    pub leader: Option<TempoSource>,
+    pub reason: String,
+    pub candidates: Vec<CandidateStatus>,
+}
+
+/// Tempo and phase a source last reported.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceGrid {
+    pub source: TempoSource,
+    pub bpm: f64,
+    /// `None` for sources that keep the running phase, such as manual and tap.
+    pub downbeat_epoch_ms: Option<u64>,
+    pub confidence: f64,
+}
+
+#[derive(Debug, Clone)]
+struct Candidate {
+    source: TempoSource,
+    bpm: f64,
+    downbeat_epoch_ms: Option<u64>,
+    confidence: f64,
+    updated_at_ms: u64,
+    live_since_ms: u64,
+}
+
+/// Picks which tempo source drives the grid. Sources offer updates; only the
+/// leader's are applied.
+#[derive(Debug, Clone, Default)]
+pub struct TempoArbiter {
+    policy: ArbiterPolicy,
+    candidates: Vec<Candidate>,
+    leader: Option<TempoSource>,
+}
+
+impl TempoArbiter {
+    pub fn policy(&self) -> &ArbiterPolicy {
+        &self.policy
+    }
+
+    pub fn set_policy(&mut self, policy: ArbiterPolicy) {
+        self.policy = policy;
+    }
+
+    pub fn leader(&self) -> Option<TempoSource> {
+        self.leader.clone()
+    }
+
+    /// Records an update and returns whether `source` now leads.
+    pub fn offer(
+        &mut self,
+        source: TempoSource,
+        bpm: f64,
+        downbeat_epoch_ms: Option<u64>,
+        confidence: f64,
+        now_ms: u64,
+    ) -> bool {
+        let was_live = self.is_live(&source, now_ms);
+        match self.candidates.iter_mut().find(|candidate| candidate.source == source) {
+            Some(candidate) => {
+                candidate.bpm = bpm;
+                candidate.downbeat_epoch_ms = downbeat_epoch_ms;
+                candidate.confidence = confidence;
+                candidate.updated_at_ms = now_ms;
+                if !was_live {
+                    candidate.live_since_ms = now_ms;
+                }
+            }
+            None => self.candidates.push(Candidate {
+                source: source.clone(),
+                bpm,
+                downbeat_epoch_ms,
+                confidence,
+                updated_at_ms: now_ms,
+                live_since_ms: now_ms,
+            }),
+        }
+
+        self.elect(now_ms);
+        self.leader.as_ref() == Some(&source)
+    }
+
+    /// Keeps a source live without changing its tempo, for sources that only
+    /// report changes.
+    pub fn refresh(&mut self, source: &TempoSource, confidence: f64, now_ms: u64) {
+        let was_live = self.is_live(source, now_ms);
+        if let Some(candidate) = self.candidates.iter_mut().find(|candidate| candidate.source == *source) {
+            candidate.confidence = confidence;
+            candidate.updated_at_ms = now_ms;
+            if !was_live {
+                candidate.live_since_ms = now_ms;
+            }
+        }
+        self.elect(now_ms);
+    }
+
+    pub fn release(&mut self, source: &TempoSource, now_ms: u64) {
+        self.candidates.retain(|candidate| candidate.source != *source);
+        self.elect(now_ms);
+    }
+
+    /// Elects a leader as of `now_ms` and returns the grid it last reported.
+    pub fn leading(&mut self, now_ms: u64) -> Option<SourceGrid> {
+        self.elect(now_ms);
+        let leader = self.leader.as_ref()?;
+        self.candidates
+            .iter()
+            .find(|candidate| candidate.source == *leader)
+            .map(|candidate| SourceGrid {
+                source: candidate.source.clone(),
+                bpm: candidate.bpm,
+                downbeat_epoch_ms: candidate.downbeat_epoch_ms,
+                confidence: candidate.confidence,
+            })
+    }
+
+    /// Confidence-weighted tempo of the live sources that agree with `bpm`.
+    pub fn blend(&self, source: &TempoSource, bpm: f64, confidence: f64, now_ms: u64) -> f64 {
+        let Some(tolerance) = self.policy.blend_tolerance_bpm else {
+            return bpm;
+        };
+        let (sum, weight) = self
+            .candidates
+            .iter()
+            .filter(|candidate| candidate.source != *source && self.is_live(&candidate.source, now_ms))
+            .filter(|candidate| (candidate.bpm - bpm).abs() <= tolerance)
+            .fold((bpm * confidence, confidence), |(sum, weight), candidate| {
+                (
+                    sum + candidate.bpm * candidate.confidence,
+                    weight + candidate.confidence,
+                )
+            });
+        if weight <= f64::EPSILON {
+            bpm
+        } else {
+            sum / weight
+        }
+    }
+
+    pub fn status(&mut self, now_ms: u64) -> ArbiterStatus {
+        self.elect(now_ms);
+        let mut candidates = self
+            .candidates
+            .iter()
+            .map(|candidate| CandidateStatus {
+                source: candidate.source.clone(),
+                bpm: candidate.bpm,
+                confidence: candidate.confidence,
+                age_ms: now_ms.saturating_sub(candidate.updated_at_ms),
+                state: self.state_of(candidate, now_ms),
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|candidate| self.rank(&candidate.source).unwrap_or(usize::MAX));
+
+        ArbiterStatus {
+            leader: self.leader.clone(),
+            reason: self.reason(&candidates),
+            candidates,
+        }
+    }
+
+    fn elect(&mut self, now_ms: u64) {
+        let leader_rank = self
+            .leader
+            .as_ref()
+            .filter(|leader| self.is_live(leader, now_ms))
+            .and_then(|leader| self.rank(leader));
+
+        let mut best: Option<(usize, TempoSource)> = None;
+        for candidate in &self.candidates {
+            let Some(rank) = self.rank(&candidate.source) else {
+                continue;
+            };
+            let ready = leader_rank.is_none_or(|leader| rank >= leader) || self.took_over(candidate, now_ms);
+            if self.is_live(&candidate.source, now_ms)
+                && ready
+                && best.as_ref().is_none_or(|(best_rank, _)| rank < *best_rank)
+            {
+                best = Some((rank, candidate.source.clone()));
+            }
+        }
+
+        if let Some((_, source)) = best {
+            self.leader = Some(source);
+        } else if leader_rank.is_none() {
+            self.leader = None;
+        }
+    }
+
+    fn state_of(&self, candidate: &Candidate, now_ms: u64) -> CandidateState {
+        let Some(policy) = self.source_policy(&candidate.source) else {
+            return CandidateState::Disabled;
+        };
+        if self.leader.as_ref() == Some(&candidate.source) {
+            return CandidateState::Leading;
+        }
+        if is_stale(policy, candidate, now_ms) {
+            return CandidateState::Stale;
+        }
+        if candidate.confidence < policy.min_confidence {
+            return CandidateState::LowConfidence;
+        }
+        let outranks_leader = self
+            .leader
+            .as_ref()
+            .and_then(|leader| self.rank(leader))
+            .is_some_and(|leader| self.rank(&candidate.source).is_some_and(|rank| rank < leader));
+        if outranks_leader {
+            CandidateState::Pending
+        } else {
+            CandidateState::Outranked
+        }
+    }
+
+    fn reason(&self, candidates: &[CandidateStatus]) -> String {
+        let Some(leader) = &self.leader else {
+            return "no live tempo source".to_string();
+        };
+        let passed_over = candidates
+            .iter()
+            .take_while(|candidate| candidate.source != *leader)
+            .filter_map(|candidate| {
+                let why = match candidate.state {
+                    CandidateState::Stale => "stale",
+                    CandidateState::LowConfidence => "low confidence",
+                    CandidateState::Pending => "waiting to take over",
+                    _ => return None,
+                };
+                Some(format!("{} {why}", candidate.source.as_str()))
+            })
+            .collect::<Vec<_>>();
+
+        if passed_over.is_empty() {
+            format!("{} is the highest-priority live source", leader.as_str())
+        } else {
+            format!("{} leads; {}", leader.as_str(), passed_over.join(", "))
+        }
+    }
+
+    fn is_live(&self, source: &TempoSource, now_ms: u64) -> bool {
+        let (Some(policy), Some(candidate)) = (
+            self.source_policy(source),
+            self.candidates.iter().find(|candidate| candidate.source == *source),
+        ) else {
+            return false;
+        };
+        !is_stale(policy, candidate, now_ms) && candidate.confidence >= policy.min_confidence
+    }
+
+    fn took_over(&self, candidate: &Candidate, now_ms: u64) -> bool {
+        self.source_policy(&candidate.source)
+            .is_some_and(|policy| now_ms.saturating_sub(candidate.live_since_ms) >= policy.takeover_ms)
+    }
+
+    fn rank(&self, source: &TempoSource) -> Option<usize> {
+        self.policy.sources.iter().position(|policy| policy.source == *source)
+    }
+
+    fn source_policy(&self, source: &TempoSource) -> Option<&SourcePolicy> {
+        self.policy.sources.iter().find(|policy| policy.source == *source)
+    }
+}
+
+fn is_stale(policy: &SourcePolicy, candidate: &Candidate, now_ms: u64) -> bool {
+    policy
+        .stale_after_ms
+        .is_some_and(|stale_after_ms| now_ms.saturating_sub(candidate.updated_at_ms) > stale_after_ms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highest_priority_live_source_leads() {
+        let mut arbiter = TempoArbiter::default();
+        assert!(arbiter.offer(TempoSource::Auto, 126.0, None, 0.8, 0));
+        assert!(arbiter.offer(TempoSource::Manual, 120.0, None, 1.0, 100));
+        assert!(!arbiter.offer(TempoSource::Auto, 126.0, None, 0.8, 200));
+
+        let status = arbiter.status(200);
+        assert_eq!(status.leader, Some(TempoSource::Manual));
+        assert_eq!(status.reason, "manual is the highest-priority live source");
+        let states = status
+            .candidates
+            .iter()
+            .map(|candidate| candidate.state)
+            .collect::<Vec<_>>();
+        assert_eq!(states, vec![CandidateState::Leading, CandidateState::Outranked]);
+    }
+
+    #[test]
+    fn stale_and_low_confidence_sources_are_passed_over() {
+        let mut arbiter = TempoArbiter::default();
+        arbiter.offer(TempoSource::Manual, 120.0, None, 1.0, 0);
+        for now in [0, 500, 1_000] {
+            arbiter.offer(TempoSource::Link, 128.0, None, 1.0, now);
+        }
+        assert_eq!(arbiter.leader(), Some(TempoSource::Link));
+
+        arbiter.offer(TempoSource::MidiClock, 90.0, None, 0.2, 1_200);
+        let status = arbiter.status(1_300);
+        assert_eq!(status.leader, Some(TempoSource::Link));
+        assert_eq!(status.reason, "link leads; midi_clock low confidence");
+
+        let status = arbiter.status(5_000);
+        assert_eq!(status.leader, Some(TempoSource::Manual));
+        assert_eq!(status.reason, "manual leads; midi_clock stale, link stale");
+    }
+
+    #[test]
+    fn challenger_waits_out_takeover_delay() {
+        let mut arbiter = TempoArbiter::default();
+        arbiter.offer(TempoSource::Manual, 120.0, None, 1.0, 0);
+        assert!(!arbiter.offer(TempoSource::Link, 128.0, None, 1.0, 100));
+
+        let status = arbiter.status(600);
+        assert_eq!(status.reason, "manual leads; link waiting to take over");
+        assert!(arbiter.offer(TempoSource::Link, 128.0, None, 1.0, 1_100));
+    }
+
+    #[test]
+    fn agreeing_sources_blend_by_confidence() {
+        let mut arbiter = TempoArbiter::default();
+        arbiter.set_policy(ArbiterPolicy {
+            blend_tolerance_bpm: Some(2.0),
+            ..ArbiterPolicy::default()
+        });
+        arbiter.offer(TempoSource::Auto, 121.0, None, 0.5, 0);
+        arbiter.offer(TempoSource::Tap, 119.0, None, 0.5, 0);
+        arbiter.offer(TempoSource::Manual, 140.0, None, 1.0, 0);
+
+        assert_eq!(arbiter.blend(&TempoSource::Tap, 119.0, 0.5, 0), 120.0);
+    }
+}
@@ -0,0 +1,468 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::{self, Read};
+
+const HOP_SIZE: usize = 512;
+const PRE_EMPHASIS: f32 = 0.97;
+const MIN_BPM: f64 = 60.0;
+const MAX_BPM: f64 = 200.0;
+const PRIOR_BPM: f64 = 120.0;
+const PRIOR_OCTAVE_WIDTH: f64 = 1.0;
+const ANALYSIS_WINDOW_SECS: f64 = 8.0;
+const MIN_ANALYSIS_SECS: f64 = 4.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TempoEstimate {
+    pub bpm: f64,
+    pub confidence: f64,
+    /// Time of the most recent detected beat, on the tracker's timeline.
+    pub beat_epoch_ms: u64,
+    /// Position inside the current beat at the end of the analysed audio, `0..1`.
+    pub beat_phase: f64,
+}
+
+/// Onset-strength beat tracker over mono PCM. Energy flux of a pre-emphasised
+/// signal is autocorrelated to find the beat period, weighted toward 120 BPM
+/// to resolve octave ambiguity, and a comb over the onset curve gives the phase.
+#[derive(Debug, Clone)]
+pub struct BeatTracker {
+    sample_rate: u32,
+    origin_ms: u64,
+    previous_sample: f32,
+    previous_log_energy: Option<f64>,
+    hop: Vec<f32>,
+    envelope: VecDeque<f64>,
+    frames_seen: u64,
+}
+
+impl BeatTracker {
+    /// `origin_ms` is the timestamp of the first sample that will be pushed.
+    pub fn new(sample_rate: u32, origin_ms: u64) -> Self {
+        Self {
+            sample_rate: sample_rate.max(1),
+            origin_ms,
+            previous_sample: 0.0,
+            previous_log_energy: None,
+            hop: Vec::with_capacity(HOP_SIZE),
+            envelope: VecDeque::new(),
+            frames_seen: 0,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn push_samples(&mut self, samples: &[f32]) {
+        for sample in samples {
+            let emphasised = sample - PRE_EMPHASIS * self.previous_sample;
+            self.previous_sample = *sample;
+            self.hop.push(emphasised);
+            if self.hop.len() == HOP_SIZE {
+                self.push_frame();
+            }
+        }
+    }
+
+    pub fn estimate(&self) -> Option<TempoEstimate> {
+        let frame_rate = self.frame_rate();
+        if (self.envelope.len() as f64) < MIN_ANALYSIS_SECS * frame_rate {
+            return None;
+        }
+
+        let mean = self.envelope.iter().sum::<f64>() / self.envelope.len() as f64;
+        let envelope = self.envelope.iter().map(|value| value - mean).collect::<Vec<_>>();
+        let energy = autocorrelation(&envelope, 0);
+        if energy <= f64::EPSILON {
+            return None;
+        }
+
+        let min_lag = (60.0 * frame_rate / MAX_BPM).floor().max(1.0) as usize;
+        let max_lag = ((60.0 * frame_rate / MIN_BPM).ceil() as usize).min(envelope.len() / 2);
+        let scores = (min_lag..=max_lag + 1)
+            .map(|lag| {
+                let bpm = 60.0 * frame_rate / lag as f64;
+                let prior = (-0.5 * ((bpm / PRIOR_BPM).log2() / PRIOR_OCTAVE_WIDTH).powi(2)).exp();
+                // Periods between two frames split their peak across neighbouring lags.
+                let spread = (lag - 1..=lag + 1)
+                    .map(|neighbour| autocorrelation(&envelope, neighbour))
+                    .sum::<f64>();
+                spread * prior
+            })
+            .collect::<Vec<_>>();
+
+        let best = (1..scores.len() - 1).max_by(|a, b| scores[*a].total_cmp(&scores[*b]))?;
+        let coarse_lag = min_lag + best;
+        let peak_mass = (coarse_lag - 1..=coarse_lag + 1)
+            .map(|lag| autocorrelation(&envelope, lag).max(0.0))
+            .sum::<f64>();
+        let confidence = (peak_mass / energy).clamp(0.0, 1.0);
+        let period_frames = refine_period(&envelope, coarse_lag);
+
+        let (last_beat_frame, beat_phase) = self.beat_phase(period_frames);
+        let beat_ms = (last_beat_frame + 0.5) * HOP_SIZE as f64 * 1_000.0 / self.sample_rate as f64;
+
+        Some(TempoEstimate {
+            bpm: 60.0 * frame_rate / period_frames,
+            confidence,
+            beat_epoch_ms: self.origin_ms + beat_ms.round() as u64,
+            beat_phase,
+        })
+    }
+
+    fn frame_rate(&self) -> f64 {
+        self.sample_rate as f64 / HOP_SIZE as f64
+    }
+
+    fn push_frame(&mut self) {
+        let energy = self.hop.iter().map(|value| f64::from(value * value)).sum::<f64>() / HOP_SIZE as f64;
+        self.hop.clear();
+
+        let log_energy = (energy + 1e-10).ln();
+        let onset = self
+            .previous_log_energy
+            .map_or(0.0, |previous| (log_energy - previous).max(0.0));
+        self.previous_log_energy = Some(log_energy);
+
+        let capacity = (ANALYSIS_WINDOW_SECS * self.frame_rate()).ceil() as usize;
+        if self.envelope.len() == capacity {
+            self.envelope.pop_front();
+        }
+        self.envelope.push_back(onset);
+        self.frames_seen += 1;
+    }
+
+    /// Best comb alignment for `period_frames`. Returns the absolute frame of
+    /// the last beat inside the window and the phase at the end of the window.
+    fn beat_phase(&self, period_frames: f64) -> (f64, f64) {
+        let length = self.envelope.len();
+        let offsets = period_frames.ceil() as usize;
+        let best_offset = (0..offsets)
+            .max_by(|a, b| {
+                comb_score(&self.envelope, *a as f64, period_frames).total_cmp(&comb_score(
+                    &self.envelope,
+                    *b as f64,
+                    period_frames,
+                ))
+            })
+            .unwrap_or(0) as f64;
+
+        let last_index = length as f64 - 1.0;
+        let beats = ((last_index - best_offset) / period_frames).floor();
+        let last_beat = best_offset + beats * period_frames;
+        let window_start = self.frames_seen as f64 - length as f64;
+        let phase = ((last_index - last_beat) / period_frames).clamp(0.0, 1.0);
+        (window_start + last_beat, phase)
+    }
+}
+
+fn autocorrelation(values: &[f64], lag: usize) -> f64 {
+    if lag >= values.len() {
+        return 0.0;
+    }
+    values.iter().zip(&values[lag..]).map(|(a, b)| a * b).sum::<f64>() / (values.len() - lag) as f64
+}
+
+/// The autocorrelation peak sits on whole frames, which is coarse at high
+/// tempos. Looking for the same peak several periods out and dividing back
+/// gives sub-frame resolution.
+fn refine_period(envelope: &[f64], coarse_lag: usize) -> f64 {
+    let multiple = (1..=4)
+        .rev()
+        .find(|multiple| (coarse_lag + 1) * multiple + 1 < envelope.len() / 2)
+        .unwrap_or(1);
+    let centre = coarse_lag * multiple;
+    let best = (centre - multiple..=centre + multiple)
+        .max_by(|a, b| autocorrelation(envelope, *a).total_cmp(&autocorrelation(envelope, *b)))
+        .unwrap_or(centre);
+
+    let (left, peak, right) = (
+        autocorrelation(envelope, best - 1),
+        autocorrelation(envelope, best),
+        autocorrelation(envelope, best + 1),
+    );
+    let curvature = left - 2.0 * peak + right;
+    let offset = if curvature.abs() > f64::EPSILON {
+        (0.5 * (left - right) / curvature).clamp(-0.5, 0.5)
+    } else {
+        0.0
+    };
+    (best as f64 + offset) / multiple as f64
+}
+
+fn comb_score(envelope: &VecDeque<f64>, offset: f64, period: f64) -> f64 {
+    let mut score = 0.0;
+    let mut position = offset;
+    while (position.round() as usize) < envelope.len() {
+        score += envelope[position.round() as usize];
+        position += period;
+    }
+    score
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AutoTempoConfig {
+    pub min_confidence: f64,
+    /// Estimates within this many BPM of the current tempo refine it in place;
+    /// anything further away is a jump that needs confirming.
+    pub bpm_tolerance: f64,
+    /// Consecutive agreeing estimates needed before the grid jumps.
+    pub confirmations: u32,
+    /// Share of a refinement's tempo and phase error applied per estimate, `0..=1`.
+    pub smoothing: f64,
+}
+
+impl Default for AutoTempoConfig {
+    fn default() -> Self {
+        Self {
+            min_confidence: 0.3,
+            bpm_tolerance: 1.5,
+            confirmations: 3,
+            smoothing: 0.25,
+        }
+    }
+}
+
+/// Gates tracker estimates for the tempo grid. Estimates close to the current
+/// tempo nudge its bpm and phase a little at a time; a different tempo only
+/// takes over after repeated, confident evidence.
+#[derive(Debug, Clone, Default)]
+pub struct AutoTempoFollower {
+    config: AutoTempoConfig,
+    /// Last applied `(bpm, beat_epoch_ms)`.
+    committed: Option<(f64, u64)>,
+    candidate: Option<(f64, u32)>,
+}
+
+impl AutoTempoFollower {
+    pub fn new(config: AutoTempoConfig) -> Self {
+        Self {
+            config,
+            committed: None,
+            candidate: None,
+        }
+    }
+
+    /// Returns the estimate to apply to the tempo grid, smoothed when it only
+    /// refines the current tempo.
+    pub fn offer(&mut self, estimate: &TempoEstimate) -> Option<TempoEstimate> {
+        if estimate.confidence < self.config.min_confidence {
+            return None;
+        }
+
+        if let Some((bpm, beat_epoch_ms)) = self.committed {
+            if (estimate.bpm - bpm).abs() <= self.config.bpm_tolerance {
+                self.candidate = None;
+                return Some(self.refine(bpm, beat_epoch_ms, estimate));
+            }
+        }
+
+        let count = match self.candidate {
+            Some((bpm, count)) if (estimate.bpm - bpm).abs() <= self.config.bpm_tolerance => count + 1,
+            _ => 1,
+        };
+        self.candidate = Some((estimate.bpm, count));
+
+        if self.committed.is_some() && count < self.config.confirmations {
+            return None;
+        }
+
+        self.committed = Some((estimate.bpm, estimate.beat_epoch_ms));
+        self.candidate = None;
+        Some(estimate.clone())
+    }
+
+    /// Moves the committed grid part of the way toward `estimate`. The phase
+    /// error is measured against the nearest committed beat, so a tracker that
+    /// picks a neighbouring beat does not count as a shift.
+    fn refine(&mut self, bpm: f64, beat_epoch_ms: u64, estimate: &TempoEstimate) -> TempoEstimate {
+        let smoothing = self.config.smoothing.clamp(0.0, 1.0);
+        let beat_ms = 60_000.0 / bpm;
+        let mut phase_error_ms = (estimate.beat_epoch_ms as f64 - beat_epoch_ms as f64).rem_euclid(beat_ms);
+        if phase_error_ms > beat_ms / 2.0 {
+            phase_error_ms -= beat_ms;
+        }
+
+        let refined_bpm = bpm + smoothing * (estimate.bpm - bpm);
+        let refined_epoch_ms = estimate.beat_epoch_ms as f64 - (1.0 - smoothing) * phase_error_ms;
+        let refined_epoch_ms = refined_epoch_ms.round().max(0.0) as u64;
+        self.committed = Some((refined_bpm, refined_epoch_ms));
+        TempoEstimate {
+            bpm: refined_bpm,
+            beat_epoch_ms: refined_epoch_ms,
+            ..estimate.clone()
+        }
+    }
+}
+
+/// Decodes a PCM or IEEE-float WAV stream and mixes it down to mono.
+pub fn read_wav_mono<R: Read>(mut reader: R) -> io::Result<(u32, Vec<f32>)> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(invalid("not a RIFF/WAVE stream"));
+    }
+
+    let mut format = None;
+    let mut data = None;
+    let mut offset = 12;
+    while offset + 8 <= bytes.len() {
+        let id = &bytes[offset..offset + 4];
+        let size = u32::from_le_bytes(bytes[offset + 4..offset + 8].try_into().unwrap_or_default()) as usize;
+        let body = &bytes[offset + 8..(offset + 8 + size).min(bytes.len())];
+        match id {
+            b"fmt " if body.len() >= 16 => {
+                let tag = u16::from_le_bytes([body[0], body[1]]);
+                let channels = u16::from_le_bytes([body[2], body[3]]).max(1) as usize;
+                let sample_rate = u32::from_le_bytes([body[4], body[5], body[6], body[7]]);
+                let bits = u16::from_le_bytes([body[14], body[15]]);
+                format = Some((tag, channels, sample_rate, bits));
+            }
+            b"data" => data = Some(body),
+            _ => {}
+        }
+        offset += 8 + size + size % 2;
+    }
+
+    let (tag, channels, sample_rate, bits) = format.ok_or_else(|| invalid("missing fmt chunk"))?;
+    let data = data.ok_or_else(|| invalid("missing data chunk"))?;
+    let decode: fn(&[u8]) -> f32 = match (tag, bits) {
+        (1, 16) => |b| f32::from(i16::from_le_bytes([b[0], b[1]])) / 32_768.0,
+        (1, 24) => |b| (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as f32 / 8_388_608.0,
+        (1, 32) => |b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2_147_483_648.0,
+        (3, 32) => |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+        _ => return Err(invalid("unsupported wav sample format")),
+    };
+
+    let sample_bytes = usize::from(bits / 8);
+    let samples = data
+        .chunks_exact(sample_bytes * channels)
+        .map(|frame| frame.chunks_exact(sample_bytes).map(decode).sum::<f32>() / channels as f32)
+        .collect();
+    Ok((sample_rate, samples))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 22_050;
+
+    fn click_track(bpm: f64, seconds: f64, first_click_ms: f64) -> Vec<f32> {
+        let total = (seconds * SAMPLE_RATE as f64) as usize;
+        let beat_samples = 60.0 / bpm * SAMPLE_RATE as f64;
+        let first = first_click_ms / 1_000.0 * SAMPLE_RATE as f64;
+        let mut samples = vec![0.0f32; total];
+        let mut click = first;
+        while (click as usize) < total {
+            for offset in 0..400 {
+                if let Some(sample) = samples.get_mut(click as usize + offset) {
+                    let decay = (-(offset as f32) / 60.0).exp();
+                    *sample = (offset as f32 * 0.9).sin() * decay;
+                }
+            }
+            click += beat_samples;
+        }
+        samples
+    }
+
+    fn estimate_for(bpm: f64, first_click_ms: f64) -> TempoEstimate {
+        let mut tracker = BeatTracker::new(SAMPLE_RATE, 10_000);
+        tracker.push_samples(&click_track(bpm, 10.0, first_click_ms));
+        tracker.estimate().expect("ten seconds should be enough to estimate")
+    }
+
+    #[test]
+    fn click_track_tempo_is_detected() {
+        for bpm in [92.0, 110.0, 128.0, 140.0, 155.0] {
+            let estimate = estimate_for(bpm, 0.0);
+            assert!((estimate.bpm - bpm).abs() < 1.0, "expected {bpm}, got {}", estimate.bpm);
+            assert!(estimate.confidence > 0.3, "confidence was {}", estimate.confidence);
+        }
+    }
+
+    #[test]
+    fn beat_epoch_lands_on_a_click() {
+        let estimate = estimate_for(120.0, 130.0);
+        let since_first_click = estimate.beat_epoch_ms as f64 - 10_130.0;
+        let off_grid = since_first_click.rem_euclid(500.0);
+        assert!(
+            !(30.0..=470.0).contains(&off_grid),
+            "beat was {off_grid}ms off the grid"
+        );
+    }
+
+    #[test]
+    fn silence_has_no_estimate() {
+        let mut tracker = BeatTracker::new(SAMPLE_RATE, 0);
+        tracker.push_samples(&vec![0.0; SAMPLE_RATE as usize * 6]);
+        assert_eq!(tracker.estimate(), None);
+    }
+
+    #[test]
+    fn follower_refines_small_wobble_and_confirms_real_changes() {
+        let mut follower = AutoTempoFollower::default();
+        let estimate = |bpm: f64, beat_epoch_ms: u64| TempoEstimate {
+            bpm,
+            confidence: 0.8,
+            beat_epoch_ms,
+            beat_phase: 0.0,
+        };
+
+        assert!(follower.offer(&estimate(120.0, 1_000)).is_some());
+        let refined = follower
+            .offer(&estimate(120.8, 3_020))
+            .expect("small wobble still refines the grid");
+        assert!((refined.bpm - 120.2).abs() < 1e-9);
+        assert_eq!(refined.beat_epoch_ms, 3_005);
+        let refined = follower
+            .offer(&estimate(119.4, 3_490))
+            .expect("an estimate on a neighbouring beat refines too");
+        assert!((refined.bpm - 120.0).abs() < 0.05);
+        assert_eq!(refined.beat_epoch_ms, 3_501);
+
+        assert!(follower.offer(&estimate(126.0, 0)).is_none());
+        assert!(follower.offer(&estimate(126.3, 0)).is_none());
+        let committed = follower
+            .offer(&estimate(125.9, 0))
+            .expect("third agreeing estimate commits");
+        assert_eq!(committed.bpm, 125.9);
+
+        let low_confidence = TempoEstimate {
+            confidence: 0.1,
+            ..estimate(90.0, 0)
+        };
+        assert!(follower.offer(&low_confidence).is_none());
+    }
+
+    #[test]
+    fn wav_reader_mixes_pcm16_to_mono() {
+        let mut bytes = Vec::new();
+        let data = [i16::MAX, 0, -16_384, -16_384];
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + 8u32).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        bytes.extend_from_slice(&44_100u32.to_le_bytes());
+        bytes.extend_from_slice(&(44_100u32 * 4).to_le_bytes());
+        bytes.extend_from_slice(&4u16.to_le_bytes());
+        bytes.extend_from_slice(&16u16.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&8u32.to_le_bytes());
+        for sample in data {
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+
+        let (sample_rate, samples) = read_wav_mono(bytes.as_slice()).unwrap();
+        assert_eq!(sample_rate, 44_100);
+        assert_eq!(samples.len(), 2);
+        assert!((samples[0] - 0.5).abs() < 0.001);
+        assert!((samples[1] + 0.5).abs() < 0.001);
+    }
+}
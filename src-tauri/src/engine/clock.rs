@@ -0,0 +1,160 @@
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Time source for the engine. Readings are microseconds on a Unix-epoch scale
+/// so they can be compared with timestamps from the UI and from peers.
+pub trait Clock: Debug + Send + Sync {
+    fn now_us(&self) -> u64;
+
+    fn now_ms(&self) -> u64 {
+        self.now_us() / 1_000
+    }
+}
+
+pub type SharedClock = Arc<dyn Clock>;
+
+/// Reads the system clock once, then advances with `Instant`, so the engine
+/// timeline never jumps when NTP steps the wall clock.
+#[derive(Debug, Clone)]
+pub struct MonotonicClock {
+    origin: Instant,
+    origin_us: u64,
+}
+
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        Self {
+            origin: Instant::now(),
+            origin_us: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_micros() as u64)
+                .unwrap_or(0),
+        }
+    }
+}
+
+impl Clock for MonotonicClock {
+    fn now_us(&self) -> u64 {
+        self.origin_us + self.origin.elapsed().as_micros() as u64
+    }
+}
+
+/// Clock that only moves when told to. Clones share the same time.
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    now_us: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    pub fn at_ms(now_ms: u64) -> Self {
+        let clock = Self::default();
+        clock.set_ms(now_ms);
+        clock
+    }
+
+    pub fn set_ms(&self, now_ms: u64) {
+        self.now_us.store(now_ms * 1_000, Ordering::SeqCst);
+    }
+
+    pub fn advance_ms(&self, delta_ms: u64) {
+        self.now_us.fetch_add(delta_ms * 1_000, Ordering::SeqCst);
+    }
+
+    pub fn advance_us(&self, delta_us: u64) {
+        self.now_us.fetch_add(delta_us, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_us(&self) -> u64 {
+        self.now_us.load(Ordering::SeqCst)
+    }
+}
+
+/// Follows an external time reference (a sync master, a Link host clock) by
+/// applying an offset to a local clock. Small errors are slewed out gradually,
+/// large ones are stepped, and readings never go backwards.
+#[derive(Debug)]
+pub struct ExternalSyncClock<C: Clock> {
+    local: C,
+    offset_us: AtomicI64,
+    last_us: AtomicU64,
+    step_threshold_us: u64,
+}
+
+impl<C: Clock> ExternalSyncClock<C> {
+    pub fn new(local: C) -> Self {
+        Self {
+            local,
+            offset_us: AtomicI64::new(0),
+            last_us: AtomicU64::new(0),
+            step_threshold_us: 50_000,
+        }
+    }
+
+    pub fn offset_us(&self) -> i64 {
+        self.offset_us.load(Ordering::SeqCst)
+    }
+
+    /// Records that the reference read `reference_us` when the local clock read `local_us`.
+    pub fn observe(&self, reference_us: u64, local_us: u64) {
+        let measured = reference_us as i64 - local_us as i64;
+        let current = self.offset_us();
+        let error = measured - current;
+        let next = if error.unsigned_abs() >= self.step_threshold_us {
+            measured
+        } else {
+            current + error / 8
+        };
+        self.offset_us.store(next, Ordering::SeqCst);
+    }
+}
+
+impl<C: Clock> Clock for ExternalSyncClock<C> {
+    fn now_us(&self) -> u64 {
+        let now = self.local.now_us().saturating_add_signed(self.offset_us());
+        self.last_us.fetch_max(now, Ordering::SeqCst).max(now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_clock_is_shared_between_clones() {
+        let clock = ManualClock::at_ms(1_000);
+        let shared = clock.clone();
+        clock.advance_ms(250);
+        assert_eq!(shared.now_ms(), 1_250);
+        assert_eq!(shared.now_us(), 1_250_000);
+    }
+
+    #[test]
+    fn monotonic_clock_never_goes_backwards() {
+        let clock = MonotonicClock::default();
+        let first = clock.now_us();
+        assert!(clock.now_us() >= first);
+    }
+
+    #[test]
+    fn external_sync_steps_large_errors_and_slews_small_ones() {
+        let local = ManualClock::at_ms(10_000);
+        let clock = ExternalSyncClock::new(local.clone());
+
+        clock.observe(12_000_000, local.now_us());
+        assert_eq!(clock.now_ms(), 12_000);
+
+        // A 8ms error is corrected an eighth at a time.
+        clock.observe(12_008_000, local.now_us());
+        assert_eq!(clock.offset_us(), 2_001_000);
+
+        // Pulling the reference back does not make time run backwards.
+        clock.observe(11_000_000, local.now_us());
+        assert_eq!(clock.now_us(), 12_000_000);
+        local.advance_ms(2_000);
+        assert_eq!(clock.now_ms(), 13_000);
+    }
+}
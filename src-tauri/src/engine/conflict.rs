@@ -0,0 +1,126 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use thiserror::Error;
+
+use super::scheduler::ScheduledAction;
+
+/// Decisions kept for inspection.
+const DECISION_CAPACITY: usize = 256;
+
+/// Who asked for an action. Declared lowest first, so the derived order is the
+/// precedence: manual > auto > authored.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ActionOrigin {
+    /// A marker from the imported timeline.
+    #[default]
+    Authored,
+    /// The auto-editor.
+    Auto,
+    /// An operator override.
+    Manual,
+}
+
+impl ActionOrigin {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ActionOrigin::Authored => "authored",
+            ActionOrigin::Auto => "auto",
+            ActionOrigin::Manual => "manual",
+        }
+    }
+}
+
+/// Which actions may not share a boundary.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ConflictPolicy {
+    /// Action types of which only one may run at any instant.
+    pub exclusive_actions: Vec<String>,
+}
+
+impl Default for ConflictPolicy {
+    fn default() -> Self {
+        Self {
+            exclusive_actions: vec!["swap_scene".to_string()],
+        }
+    }
+}
+
+impl ConflictPolicy {
+    pub fn conflicts(&self, incoming: &ScheduledAction, pending: &ScheduledAction) -> bool {
+        incoming.execute_at_ms == pending.execute_at_ms
+            && incoming.action == pending.action
+            && self.exclusive_actions.contains(&incoming.action)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictOutcome {
+    /// A higher origin removed a pending action.
+    Preempted,
+    /// A lower origin was refused because a higher one is pending.
+    Suppressed,
+    /// The same origin asked again; the newer action replaced the pending one.
+    Superseded,
+}
+
+impl ConflictOutcome {
+    /// How `incoming` fares against a conflicting `pending` action.
+    pub fn resolve(incoming: &ScheduledAction, pending: &ScheduledAction) -> Self {
+        match incoming.origin.cmp(&pending.origin) {
+            std::cmp::Ordering::Greater => ConflictOutcome::Preempted,
+            std::cmp::Ordering::Less => ConflictOutcome::Suppressed,
+            std::cmp::Ordering::Equal => ConflictOutcome::Superseded,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ConflictDecision {
+    pub decided_at_ms: u64,
+    pub outcome: ConflictOutcome,
+    /// The action left in the queue.
+    pub winner: ScheduledAction,
+    /// The action removed or refused.
+    pub loser: ScheduledAction,
+}
+
+#[derive(Debug, Clone, Error, PartialEq)]
+pub enum ScheduleError {
+    #[error(
+        "{} at {}ms is suppressed by {} action {}",
+        .0.loser.action,
+        .0.loser.execute_at_ms,
+        .0.winner.origin.as_str(),
+        .0.winner.id
+    )]
+    Suppressed(Box<ConflictDecision>),
+    #[error("no pending action with id {0}")]
+    NotPending(u64),
+}
+
+/// The most recent arbitration decisions, oldest first.
+#[derive(Debug, Clone, Default)]
+pub struct ConflictLog {
+    decisions: VecDeque<ConflictDecision>,
+}
+
+impl ConflictLog {
+    pub fn record(&mut self, decision: ConflictDecision) {
+        if self.decisions.len() == DECISION_CAPACITY {
+            self.decisions.pop_front();
+        }
+        self.decisions.push_back(decision);
+    }
+
+    pub fn decisions(&self) -> Vec<ConflictDecision> {
+        self.decisions.iter().cloned().collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.decisions.clear();
+    }
+}
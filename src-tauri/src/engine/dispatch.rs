@@ -0,0 +1,335 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io;
+use std::sync::mpsc::Sender;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use super::clock::{Clock, SharedClock};
+use super::scheduler::ScheduledAction;
+
+/// Latencies kept for the mean and percentile.
+const LATENCY_WINDOW: usize = 512;
+/// The last stretch before a deadline is spent yielding instead of sleeping,
+/// because sleeps can overshoot by a scheduler tick.
+const SPIN_US: u64 = 1_500;
+/// Nice value for the dispatch thread when Linux refuses real-time scheduling.
+#[cfg(target_os = "linux")]
+const DISPATCH_NICE: libc::c_int = -10;
+
+/// An action as it left the dispatcher. `latency_us` is positive when it went
+/// out after `execute_at_ms`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DispatchedAction {
+    pub action: ScheduledAction,
+    pub dispatched_at_us: u64,
+    pub latency_us: i64,
+}
+
+/// Where dispatched actions are delivered, such as Tauri events or a channel.
+pub trait ActionSink: Send {
+    fn deliver(&mut self, action: &DispatchedAction) -> Result<(), String>;
+}
+
+/// Delivers to an `mpsc` channel, for in-process consumers and tests.
+pub struct ChannelSink(pub Sender<DispatchedAction>);
+
+impl ActionSink for ChannelSink {
+    fn deliver(&mut self, action: &DispatchedAction) -> Result<(), String> {
+        self.0.send(action.clone()).map_err(|err| err.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DispatchStats {
+    pub dispatched: u64,
+    /// Actions the sink could not deliver.
+    pub failed: u64,
+    pub last_latency_us: Option<i64>,
+    /// Mean and 95th percentile over the most recent dispatches.
+    pub mean_latency_us: f64,
+    pub p95_latency_us: i64,
+    pub max_latency_us: i64,
+    /// Whether the dispatch thread runs above normal OS priority.
+    pub raised_priority: bool,
+}
+
+/// Hands due actions to a sink and measures how late they went out.
+pub struct ActionDispatcher {
+    sink: Box<dyn ActionSink>,
+    clock: SharedClock,
+    latencies: VecDeque<i64>,
+    stats: DispatchStats,
+}
+
+impl ActionDispatcher {
+    pub fn new(clock: SharedClock, sink: Box<dyn ActionSink>) -> Self {
+        Self {
+            sink,
+            clock,
+            latencies: VecDeque::with_capacity(LATENCY_WINDOW),
+            stats: DispatchStats::default(),
+        }
+    }
+
+    pub fn dispatch(&mut self, actions: Vec<ScheduledAction>) -> Vec<DispatchedAction> {
+        actions
+            .into_iter()
+            .map(|action| {
+                let dispatched_at_us = self.clock.now_us();
+                let latency_us = dispatched_at_us as i64 - action.execute_at_ms as i64 * 1_000;
+                let dispatched = DispatchedAction {
+                    action,
+                    dispatched_at_us,
+                    latency_us,
+                };
+                match self.sink.deliver(&dispatched) {
+                    Ok(()) => self.record(latency_us),
+                    Err(_) => self.stats.failed += 1,
+                }
+                dispatched
+            })
+            .collect()
+    }
+
+    pub fn stats(&self) -> DispatchStats {
+        self.stats.clone()
+    }
+
+    pub fn set_raised_priority(&mut self, raised: bool) {
+        self.stats.raised_priority = raised;
+    }
+
+    fn record(&mut self, latency_us: i64) {
+        if self.latencies.len() == LATENCY_WINDOW {
+            self.latencies.pop_front();
+        }
+        self.latencies.push_back(latency_us);
+
+        let mut sorted = self.latencies.iter().copied().collect::<Vec<_>>();
+        sorted.sort_unstable();
+        let p95_index = (sorted.len() * 95).div_ceil(100).saturating_sub(1);
+
+        self.stats.dispatched += 1;
+        self.stats.last_latency_us = Some(latency_us);
+        self.stats.mean_latency_us = sorted.iter().sum::<i64>() as f64 / sorted.len() as f64;
+        self.stats.p95_latency_us = sorted[p95_index];
+        self.stats.max_latency_us = if self.stats.dispatched == 1 {
+            latency_us
+        } else {
+            self.stats.max_latency_us.max(latency_us)
+        };
+    }
+}
+
+/// Wakes a thread waiting for a deadline early: the dispatch thread when the
+/// head of the queue changes, so it never polls an idle queue, or a MIDI clock
+/// writer when its output is cancelled.
+#[derive(Debug, Default)]
+pub struct DispatchSignal {
+    changed: Mutex<bool>,
+    condvar: Condvar,
+}
+
+impl DispatchSignal {
+    pub fn notify(&self) {
+        if let Ok(mut changed) = self.changed.lock() {
+            *changed = true;
+            self.condvar.notify_all();
+        }
+    }
+
+    /// Blocks until `notify` is called or `clock` reaches `target_us`, or only
+    /// for the signal when there is no deadline. Sleeps on the signal and
+    /// yields just for the last stretch before the deadline. Returns whether
+    /// it was woken by the signal.
+    pub fn wait_until(&self, clock: &dyn Clock, target_us: Option<u64>) -> bool {
+        loop {
+            let Ok(mut changed) = self.changed.lock() else {
+                return false;
+            };
+            if std::mem::take(&mut *changed) {
+                return true;
+            }
+            let Some(target_us) = target_us else {
+                if self.condvar.wait(changed).is_err() {
+                    return false;
+                }
+                continue;
+            };
+            let now_us = clock.now_us();
+            if now_us >= target_us {
+                return false;
+            }
+            let remaining_us = target_us - now_us;
+            if remaining_us > SPIN_US {
+                let timeout = Duration::from_micros(remaining_us - SPIN_US);
+                if self.condvar.wait_timeout(changed, timeout).is_err() {
+                    return false;
+                }
+            } else {
+                drop(changed);
+                thread::yield_now();
+            }
+        }
+    }
+}
+
+/// Raises the calling thread above normal priority so it wakes on time while
+/// the UI and decoders are busy: user-interactive QoS on macOS, the lowest
+/// `SCHED_FIFO` priority on other unixes and time-critical on Windows.
+/// Real-time scheduling needs `rtprio` on Linux, so without it the thread
+/// settles for a lower nice value.
+#[cfg(target_vendor = "apple")]
+pub fn raise_thread_priority() -> io::Result<()> {
+    // SAFETY: only changes the QoS class of the calling thread.
+    match unsafe { libc::pthread_set_qos_class_self_np(libc::qos_class_t::QOS_CLASS_USER_INTERACTIVE, 0) } {
+        0 => Ok(()),
+        code => Err(io::Error::from_raw_os_error(code)),
+    }
+}
+
+#[cfg(all(unix, not(target_vendor = "apple")))]
+pub fn raise_thread_priority() -> io::Result<()> {
+    // SAFETY: `param` is plain data that outlives the call, which only
+    // changes the scheduling of the calling thread.
+    let code = unsafe {
+        let mut param: libc::sched_param = std::mem::zeroed();
+        param.sched_priority = libc::sched_get_priority_min(libc::SCHED_FIFO);
+        libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param)
+    };
+    if code == 0 {
+        return Ok(());
+    }
+
+    // Linux keeps a nice value per thread, addressed by its thread id.
+    #[cfg(target_os = "linux")]
+    {
+        // SAFETY: `gettid` has no preconditions and `setpriority` only touches this thread.
+        if unsafe { libc::setpriority(libc::PRIO_PROCESS, libc::gettid() as libc::id_t, DISPATCH_NICE) } == 0 {
+            return Ok(());
+        }
+    }
+    Err(io::Error::from_raw_os_error(code))
+}
+
+#[cfg(windows)]
+pub fn raise_thread_priority() -> io::Result<()> {
+    use windows_sys::Win32::System::Threading::{GetCurrentThread, SetThreadPriority, THREAD_PRIORITY_TIME_CRITICAL};
+
+    // SAFETY: the pseudo handle always refers to the calling thread.
+    match unsafe { SetThreadPriority(GetCurrentThread(), THREAD_PRIORITY_TIME_CRITICAL) } {
+        0 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn raise_thread_priority() -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "thread priority is not supported on this platform",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::clock::{ManualClock, MonotonicClock};
+    use crate::engine::conflict::ActionOrigin;
+    use crate::engine::scheduler::QuantizedScheduler;
+    use crate::engine::tempo::{GridTarget, TempoEngine};
+    use crate::timeline::types::{CueAction, QuantizeGrid};
+    use std::sync::mpsc;
+    use std::sync::Arc;
+
+    #[test]
+    fn dispatches_due_actions_to_the_sink_with_their_latency() {
+        let clock = ManualClock::at_ms(10_000);
+        let mut tempo = TempoEngine::new(Arc::new(clock.clone()));
+        tempo.set_bpm(120.0, None);
+        let tempo = tempo.resync_downbeat(None);
+
+        let mut scheduler = QuantizedScheduler::new(Arc::new(clock.clone()));
+        let first = scheduler
+            .schedule(&tempo, Some(QuantizeGrid::Quarter), "trigger_clip".to_string(), None)
+            .unwrap();
+        scheduler
+            .schedule(&tempo, Some(QuantizeGrid::Whole), "swap_scene".to_string(), None)
+            .unwrap();
+        assert_eq!(scheduler.next_due_ms(), Some(10_500));
+
+        let (sender, receiver) = mpsc::channel();
+        let mut dispatcher = ActionDispatcher::new(Arc::new(clock.clone()), Box::new(ChannelSink(sender)));
+
+        // No jitter budget: an action 1ms away is not popped yet.
+        clock.set_ms(10_499);
+        assert!(scheduler.pop_until(clock.now_ms()).is_empty());
+
+        clock.set_ms(10_500);
+        clock.advance_us(300);
+        dispatcher.dispatch(scheduler.pop_until(clock.now_ms()));
+        let delivered = receiver.try_recv().expect("action delivered");
+        assert_eq!(delivered.action, first);
+        assert_eq!(delivered.latency_us, 300);
+        assert!(receiver.try_recv().is_err());
+
+        clock.set_ms(12_001);
+        dispatcher.dispatch(scheduler.pop_until(clock.now_ms()));
+        let stats = dispatcher.stats();
+        assert_eq!((stats.dispatched, stats.failed), (2, 0));
+        assert_eq!((stats.last_latency_us, stats.max_latency_us), (Some(1_000), 1_000));
+        assert_eq!(stats.mean_latency_us, 650.0);
+    }
+
+    #[test]
+    fn undeliverable_actions_are_counted() {
+        let (sender, receiver) = mpsc::channel();
+        drop(receiver);
+        let clock = Arc::new(ManualClock::at_ms(1_000));
+        let mut dispatcher = ActionDispatcher::new(clock, Box::new(ChannelSink(sender)));
+        dispatcher.dispatch(vec![ScheduledAction {
+            id: 1,
+            action: "trigger_clip".to_string(),
+            cue: Some(CueAction::TriggerClip),
+            marker_id: None,
+            payload: serde_json::Value::Null,
+            section: None,
+            quantize: QuantizeGrid::Quarter,
+            execute_at_ms: 1_000,
+            target: GridTarget {
+                bar: 1,
+                beat: 1,
+                tick: 0,
+            },
+            priority: 0,
+            origin: ActionOrigin::Manual,
+            pattern_id: None,
+        }]);
+        assert_eq!((dispatcher.stats().dispatched, dispatcher.stats().failed), (0, 1));
+    }
+
+    #[test]
+    fn signal_wakes_the_waiter_or_lets_the_deadline_pass() {
+        let clock = MonotonicClock::default();
+        let signal = Arc::new(DispatchSignal::default());
+        let deadline_us = clock.now_us() + 5_000;
+        assert!(!signal.wait_until(&clock, Some(deadline_us)));
+        assert!(clock.now_us() >= deadline_us);
+
+        let notifier = {
+            let signal = signal.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(5));
+                signal.notify();
+            })
+        };
+        assert!(signal.wait_until(&clock, None));
+        notifier.join().unwrap();
+        // The wakeup is consumed, so the next wait runs to its deadline.
+        assert!(!signal.wait_until(&clock, Some(clock.now_us() + 1_000)));
+    }
+}
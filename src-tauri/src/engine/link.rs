@@ -0,0 +1,848 @@
+use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::time::Duration;
+
+use super::tempo::TempoState;
+
+pub const LINK_MULTICAST_GROUP: Ipv4Addr = Ipv4Addr::new(224, 76, 78, 75);
+pub const LINK_PORT: u16 = 20808;
+
+const DISCOVERY_HEADER: &[u8; 8] = b"_asdp_v\x01";
+const MEASUREMENT_HEADER: &[u8; 8] = b"_link_v\x01";
+const MESSAGE_ALIVE: u8 = 1;
+const MESSAGE_RESPONSE: u8 = 2;
+const MESSAGE_BYEBYE: u8 = 3;
+const MESSAGE_PING: u8 = 1;
+const MESSAGE_PONG: u8 = 2;
+const KEY_TIMELINE: u32 = u32::from_be_bytes(*b"tmln");
+const KEY_SESSION: u32 = u32::from_be_bytes(*b"sess");
+const KEY_MEASUREMENT_ENDPOINT: u32 = u32::from_be_bytes(*b"mep4");
+const KEY_HOST_TIME: u32 = u32::from_be_bytes(*b"__ht");
+const KEY_GHOST_TIME: u32 = u32::from_be_bytes(*b"__gt");
+const KEY_PREV_GHOST_TIME: u32 = u32::from_be_bytes(*b"_pgt");
+const BROADCAST_INTERVAL_US: u64 = 250_000;
+/// Ping round trips collected before a measurement settles on their median.
+const MEASUREMENT_POINTS: usize = 100;
+const PING_TIMEOUT_US: u64 = 50_000;
+const PING_RETRIES: u32 = 5;
+/// How often the clock offset to a joined session is measured again.
+const REMEASURE_INTERVAL_US: u64 = 30_000_000;
+/// Sessions whose ghost clocks are closer than this count as equally old.
+const SESSION_EPSILON_US: f64 = 500_000.0;
+/// Largest ping payload answered; a ping carries a host time and a ghost time.
+const MAX_PING_PAYLOAD: usize = 32;
+const MIN_BPM: f64 = 20.0;
+const MAX_BPM: f64 = 999.0;
+const MAX_DATAGRAM: usize = 512;
+
+pub type NodeId = [u8; 8];
+
+#[derive(Debug, Clone)]
+pub struct LinkConfig {
+    pub bind_addr: SocketAddrV4,
+    pub multicast_group: Option<Ipv4Addr>,
+    pub peers: Vec<SocketAddr>,
+    pub quantum_beats: f64,
+    pub ttl_secs: u8,
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        Self {
+            bind_addr: SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, LINK_PORT),
+            multicast_group: Some(LINK_MULTICAST_GROUP),
+            peers: vec![SocketAddr::V4(SocketAddrV4::new(LINK_MULTICAST_GROUP, LINK_PORT))],
+            quantum_beats: 4.0,
+            ttl_secs: 5,
+        }
+    }
+}
+
+/// Session beat timeline on Link's ghost clock: the beat reached at
+/// `time_origin_us` and the beat length in microseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LinkTimeline {
+    pub micros_per_beat: i64,
+    pub beat_origin_micro_beats: i64,
+    pub time_origin_us: i64,
+}
+
+impl LinkTimeline {
+    /// A new session timeline whose beat at `ghost_us` keeps the bar phase of `tempo` at `now_us`.
+    fn from_tempo(tempo: &TempoState, now_us: u64, ghost_us: i64, quantum_beats: f64) -> Self {
+        let beat_in_bar = tempo
+            .beat_at(now_us as f64 / 1_000.0)
+            .rem_euclid(quantum_beats.max(1.0));
+        Self {
+            micros_per_beat: micros_per_beat(tempo.bpm),
+            beat_origin_micro_beats: (beat_in_bar * 1_000_000.0).round() as i64,
+            time_origin_us: ghost_us,
+        }
+    }
+
+    pub fn bpm(&self) -> f64 {
+        60_000_000.0 / self.micros_per_beat.max(1) as f64
+    }
+
+    pub fn beat_at(&self, ghost_us: i64) -> f64 {
+        let elapsed_us = (ghost_us - self.time_origin_us) as f64;
+        self.beat_origin_micro_beats as f64 / 1_000_000.0 + elapsed_us / self.micros_per_beat.max(1) as f64
+    }
+
+    pub fn time_at(&self, beat: f64) -> f64 {
+        let beats_from_origin = beat - self.beat_origin_micro_beats as f64 / 1_000_000.0;
+        self.time_origin_us as f64 + beats_from_origin * self.micros_per_beat.max(1) as f64
+    }
+
+    /// Changes tempo at `ghost_us` without moving the beat there. Peers take
+    /// the timeline with the later beat origin as the newer one, so the origin
+    /// always moves forward.
+    fn with_tempo(&self, bpm: f64, ghost_us: i64) -> Self {
+        let beat_origin = ((self.beat_at(ghost_us) * 1_000_000.0).round() as i64).max(self.beat_origin_micro_beats + 1);
+        Self {
+            micros_per_beat: micros_per_beat(bpm),
+            beat_origin_micro_beats: beat_origin,
+            time_origin_us: self.time_at(beat_origin as f64 / 1_000_000.0).round() as i64,
+        }
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&KEY_TIMELINE.to_be_bytes());
+        out.extend_from_slice(&24u32.to_be_bytes());
+        out.extend_from_slice(&self.micros_per_beat.to_be_bytes());
+        out.extend_from_slice(&self.beat_origin_micro_beats.to_be_bytes());
+        out.extend_from_slice(&self.time_origin_us.to_be_bytes());
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 24 {
+            return None;
+        }
+        let timeline = Self {
+            micros_per_beat: read_i64(&bytes[0..8])?,
+            beat_origin_micro_beats: read_i64(&bytes[8..16])?,
+            time_origin_us: read_i64(&bytes[16..24])?,
+        };
+        (timeline.micros_per_beat > 0).then_some(timeline)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkStatus {
+    pub node_id: String,
+    pub session_id: String,
+    /// Peers in the same session.
+    pub peer_count: usize,
+    pub bpm: f64,
+}
+
+/// A datagram read from the measurement socket, stamped on arrival.
+#[derive(Debug, Clone)]
+pub struct LinkDatagram {
+    bytes: Vec<u8>,
+    from: SocketAddr,
+    received_at_us: u64,
+}
+
+/// Reads the socket that receives discovery responses, pings and pongs. It
+/// blocks, so it is read outside any lock and each datagram is handed to
+/// `LinkSession::handle`; clock measurements need the arrival time.
+#[derive(Debug)]
+pub struct LinkReceiver {
+    socket: UdpSocket,
+}
+
+impl LinkReceiver {
+    /// Waits up to `timeout` for a datagram and stamps it with `clock_us()`.
+    pub fn receive(&self, timeout: Duration, clock_us: impl FnOnce() -> u64) -> io::Result<Option<LinkDatagram>> {
+        self.socket
+            .set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+        let mut buffer = [0u8; MAX_DATAGRAM];
+        match self.socket.recv_from(&mut buffer) {
+            Ok((length, from)) => Ok(Some(LinkDatagram {
+                bytes: buffer[..length].to_vec(),
+                from,
+                received_at_us: clock_us(),
+            })),
+            Err(err) if is_idle(&err) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Peer {
+    session_id: NodeId,
+    timeline: LinkTimeline,
+    endpoint: Option<SocketAddr>,
+    expires_at_us: u64,
+}
+
+#[derive(Debug)]
+struct DiscoveryMessage {
+    kind: u8,
+    ttl_secs: u8,
+    node_id: NodeId,
+    session_id: Option<NodeId>,
+    timeline: Option<LinkTimeline>,
+    endpoint: Option<SocketAddr>,
+}
+
+/// Ping exchange with a peer, estimating the offset from host time to the
+/// ghost clock of its session.
+#[derive(Debug)]
+struct Measurement {
+    session_id: NodeId,
+    endpoint: SocketAddr,
+    offsets_us: Vec<f64>,
+    sent_at_us: u64,
+    retries: u32,
+}
+
+/// Ableton Link session participant. Peers find each other with `_asdp_v1`
+/// discovery on `224.76.78.75:20808` and share a beat timeline on a session
+/// "ghost" clock. Each peer measures the offset from its host clock to the
+/// ghost clock with `_link_v1` ping/pong exchanges, so no clock sync between
+/// hosts is needed. When two sessions meet, everyone joins the one whose ghost
+/// clock is further ahead, which is the one founded first.
+#[derive(Debug)]
+pub struct LinkSession {
+    discovery: UdpSocket,
+    unicast: UdpSocket,
+    config: LinkConfig,
+    endpoint: SocketAddrV4,
+    node_id: NodeId,
+    session_id: NodeId,
+    /// Ghost time minus host time, in microseconds.
+    ghost_offset_us: i64,
+    timeline: LinkTimeline,
+    peers: HashMap<NodeId, Peer>,
+    measurement: Option<Measurement>,
+    /// Sessions measured as younger than the current one.
+    younger_sessions: HashSet<NodeId>,
+    measured_at_us: u64,
+    last_broadcast_us: Option<u64>,
+}
+
+impl LinkSession {
+    /// Joins the network, founding a session of its own whose ghost clock
+    /// starts at zero at `now_us`.
+    pub fn bind(config: LinkConfig, tempo: &TempoState, now_us: u64) -> io::Result<(Self, LinkReceiver)> {
+        let discovery = bind_shared(config.bind_addr)?;
+        if let Some(group) = config.multicast_group {
+            discovery.join_multicast_v4(&group, config.bind_addr.ip())?;
+        }
+        discovery.set_nonblocking(true)?;
+
+        let unicast = UdpSocket::bind(SocketAddrV4::new(*config.bind_addr.ip(), 0))?;
+        unicast.set_multicast_loop_v4(true)?;
+        let endpoint = SocketAddrV4::new(advertised_ip(&config), unicast.local_addr()?.port());
+        let receiver = LinkReceiver {
+            socket: unicast.try_clone()?,
+        };
+
+        let node_id = random_node_id();
+        let ghost_offset_us = -(now_us as i64);
+        let session = Self {
+            discovery,
+            unicast,
+            endpoint,
+            node_id,
+            session_id: node_id,
+            ghost_offset_us,
+            timeline: LinkTimeline::from_tempo(tempo, now_us, 0, config.quantum_beats),
+            config,
+            peers: HashMap::new(),
+            measurement: None,
+            younger_sessions: HashSet::new(),
+            measured_at_us: now_us,
+            last_broadcast_us: None,
+        };
+        Ok((session, receiver))
+    }
+
+    pub fn node_id(&self) -> NodeId {
+        self.node_id
+    }
+
+    /// Where discovery messages for this session are received.
+    pub fn discovery_addr(&self) -> io::Result<SocketAddr> {
+        self.discovery.local_addr()
+    }
+
+    pub fn add_peer(&mut self, addr: SocketAddr) {
+        if !self.config.peers.contains(&addr) {
+            self.config.peers.push(addr);
+        }
+    }
+
+    pub fn quantum_beats(&self) -> f64 {
+        self.config.quantum_beats
+    }
+
+    pub fn set_quantum_beats(&mut self, quantum_beats: f64) {
+        self.config.quantum_beats = quantum_beats;
+    }
+
+    pub fn timeline(&self) -> LinkTimeline {
+        self.timeline
+    }
+
+    pub fn status(&self) -> LinkStatus {
+        LinkStatus {
+            node_id: hex_id(&self.node_id),
+            session_id: hex_id(&self.session_id),
+            peer_count: self
+                .peers
+                .values()
+                .filter(|peer| peer.session_id == self.session_id)
+                .count(),
+            bpm: self.timeline.bpm(),
+        }
+    }
+
+    /// Latest host time at or before `now_us` where the session beat is a
+    /// multiple of the quantum, in milliseconds.
+    pub fn downbeat_epoch_ms(&self, now_us: u64) -> u64 {
+        let quantum = self.config.quantum_beats.max(1.0);
+        let beat = self.timeline.beat_at(now_us as i64 + self.ghost_offset_us);
+        let downbeat_us = self.timeline.time_at((beat / quantum).floor() * quantum) - self.ghost_offset_us as f64;
+        (downbeat_us / 1_000.0).round().max(0.0) as u64
+    }
+
+    /// Publishes a local tempo change to the session at `now_us`.
+    pub fn propose(&mut self, tempo: &TempoState, now_us: u64) -> io::Result<()> {
+        self.timeline = self
+            .timeline
+            .with_tempo(tempo.bpm, now_us as i64 + self.ghost_offset_us);
+        self.broadcast(MESSAGE_ALIVE, now_us)
+    }
+
+    /// Handles a datagram from the `LinkReceiver`. Returns true when the
+    /// session timeline or its clock offset changed.
+    pub fn handle(&mut self, datagram: &LinkDatagram) -> io::Result<bool> {
+        let changed = if datagram.bytes.starts_with(MEASUREMENT_HEADER) {
+            self.handle_measurement(datagram)?
+        } else {
+            self.handle_discovery(&datagram.bytes, datagram.from, datagram.received_at_us)?
+        };
+        if changed {
+            self.broadcast(MESSAGE_ALIVE, datagram.received_at_us)?;
+        }
+        Ok(changed)
+    }
+
+    /// Drains multicast discovery, runs clock measurements and keeps the
+    /// announcement alive. Returns the session timeline when it changed.
+    pub fn poll(&mut self, now_us: u64) -> io::Result<Option<LinkTimeline>> {
+        let mut changed = false;
+        let mut buffer = [0u8; MAX_DATAGRAM];
+        loop {
+            let (length, from) = match self.discovery.recv_from(&mut buffer) {
+                Ok(received) => received,
+                Err(err) if is_idle(&err) => break,
+                Err(err) => return Err(err),
+            };
+            changed |= self.handle_discovery(&buffer[..length], from, now_us)?;
+        }
+
+        self.peers.retain(|_, peer| peer.expires_at_us >= now_us);
+        self.advance_measurement(now_us)?;
+
+        let broadcast_due = self
+            .last_broadcast_us
+            .is_none_or(|last| now_us.saturating_sub(last) >= BROADCAST_INTERVAL_US);
+        if changed || broadcast_due {
+            self.broadcast(MESSAGE_ALIVE, now_us)?;
+        }
+        Ok(changed.then_some(self.timeline))
+    }
+
+    pub fn leave(self) -> io::Result<()> {
+        let message = self.encode_discovery(MESSAGE_BYEBYE);
+        for peer in &self.config.peers {
+            self.unicast.send_to(&message, peer)?;
+        }
+        Ok(())
+    }
+
+    fn handle_discovery(&mut self, bytes: &[u8], from: SocketAddr, now_us: u64) -> io::Result<bool> {
+        let Some(message) = decode_discovery(bytes) else {
+            return Ok(false);
+        };
+        if message.node_id == self.node_id {
+            return Ok(false);
+        }
+        if message.kind == MESSAGE_BYEBYE {
+            self.peers.remove(&message.node_id);
+            return Ok(false);
+        }
+        let (Some(session_id), Some(timeline)) = (message.session_id, message.timeline) else {
+            return Ok(false);
+        };
+
+        self.peers.insert(
+            message.node_id,
+            Peer {
+                session_id,
+                timeline,
+                endpoint: message.endpoint,
+                expires_at_us: now_us + u64::from(message.ttl_secs) * 1_000_000,
+            },
+        );
+        if message.kind == MESSAGE_ALIVE {
+            self.unicast.send_to(&self.encode_discovery(MESSAGE_RESPONSE), from)?;
+        }
+
+        if session_id == self.session_id {
+            if timeline.beat_origin_micro_beats > self.timeline.beat_origin_micro_beats {
+                self.timeline = timeline;
+                return Ok(true);
+            }
+            return Ok(false);
+        }
+
+        if self.measurement.is_none() && !self.younger_sessions.contains(&session_id) {
+            if let Some(endpoint) = message.endpoint {
+                self.start_measurement(session_id, endpoint, now_us)?;
+            }
+        }
+        Ok(false)
+    }
+
+    fn handle_measurement(&mut self, datagram: &LinkDatagram) -> io::Result<bool> {
+        let Some((&kind, payload)) = datagram.bytes[MEASUREMENT_HEADER.len()..].split_first() else {
+            return Ok(false);
+        };
+        let now_us = datagram.received_at_us;
+
+        if kind == MESSAGE_PING {
+            if payload.len() > MAX_PING_PAYLOAD {
+                return Ok(false);
+            }
+            // The pong carries our session and ghost time, then echoes the ping.
+            let mut pong = measurement_message(MESSAGE_PONG);
+            encode_entry(&mut pong, KEY_SESSION, &self.session_id);
+            encode_entry(&mut pong, KEY_GHOST_TIME, &self.ghost_us(now_us).to_be_bytes());
+            pong.extend_from_slice(payload);
+            self.unicast.send_to(&pong, datagram.from)?;
+            return Ok(false);
+        }
+
+        if kind != MESSAGE_PONG {
+            return Ok(false);
+        }
+        let (mut session_id, mut ghost_us, mut prev_ghost_us, mut host_us) = (None, 0, 0, 0);
+        for (key, value) in entries(payload) {
+            match key {
+                KEY_SESSION => session_id = value.try_into().ok(),
+                KEY_GHOST_TIME => ghost_us = read_i64(value).unwrap_or(0),
+                KEY_PREV_GHOST_TIME => prev_ghost_us = read_i64(value).unwrap_or(0),
+                KEY_HOST_TIME => host_us = read_i64(value).unwrap_or(0),
+                _ => {}
+            }
+        }
+        let Some(measurement) = self
+            .measurement
+            .as_mut()
+            .filter(|measurement| Some(measurement.session_id) == session_id)
+        else {
+            return Ok(false);
+        };
+
+        let mut ping = measurement_message(MESSAGE_PING);
+        encode_entry(&mut ping, KEY_HOST_TIME, &(now_us as i64).to_be_bytes());
+        encode_entry(&mut ping, KEY_PREV_GHOST_TIME, &ghost_us.to_be_bytes());
+        self.unicast.send_to(&ping, measurement.endpoint)?;
+        measurement.sent_at_us = now_us;
+        measurement.retries = 0;
+
+        // The ghost time was read halfway through the round trip.
+        if ghost_us != 0 && host_us != 0 {
+            measurement
+                .offsets_us
+                .push(ghost_us as f64 - (now_us as f64 + host_us as f64) / 2.0);
+            if prev_ghost_us != 0 {
+                measurement
+                    .offsets_us
+                    .push((ghost_us as f64 + prev_ghost_us as f64) / 2.0 - host_us as f64);
+            }
+        }
+        if measurement.offsets_us.len() <= MEASUREMENT_POINTS {
+            return Ok(false);
+        }
+
+        let Some(mut measurement) = self.measurement.take() else {
+            return Ok(false);
+        };
+        measurement.offsets_us.sort_by(f64::total_cmp);
+        let offset_us = measurement.offsets_us[measurement.offsets_us.len() / 2].round() as i64;
+        Ok(self.measured(measurement.session_id, offset_us, now_us))
+    }
+
+    /// Applies a finished measurement: refreshes the offset to the current
+    /// session, or joins a session whose ghost clock is further ahead.
+    fn measured(&mut self, session_id: NodeId, ghost_offset_us: i64, now_us: u64) -> bool {
+        if session_id == self.session_id {
+            self.ghost_offset_us = ghost_offset_us;
+            self.measured_at_us = now_us;
+            return true;
+        }
+
+        let ghost_lead_us = (ghost_offset_us - self.ghost_offset_us) as f64;
+        let older = ghost_lead_us > SESSION_EPSILON_US
+            || (ghost_lead_us.abs() < SESSION_EPSILON_US && session_id < self.session_id);
+        let timeline = self
+            .peers
+            .values()
+            .filter(|peer| peer.session_id == session_id)
+            .map(|peer| peer.timeline)
+            .max_by_key(|timeline| timeline.beat_origin_micro_beats);
+        match timeline {
+            Some(timeline) if older => {
+                self.session_id = session_id;
+                self.ghost_offset_us = ghost_offset_us;
+                self.timeline = timeline;
+                self.measured_at_us = now_us;
+                self.younger_sessions.clear();
+                true
+            }
+            _ => {
+                self.younger_sessions.insert(session_id);
+                false
+            }
+        }
+    }
+
+    /// Retries unanswered pings and periodically re-measures a joined session.
+    fn advance_measurement(&mut self, now_us: u64) -> io::Result<()> {
+        if let Some(measurement) = self.measurement.as_mut() {
+            if now_us.saturating_sub(measurement.sent_at_us) < PING_TIMEOUT_US {
+                return Ok(());
+            }
+            if measurement.retries >= PING_RETRIES {
+                self.measurement = None;
+                return Ok(());
+            }
+            measurement.retries += 1;
+            let (session_id, endpoint) = (measurement.session_id, measurement.endpoint);
+            return self.send_first_ping(session_id, endpoint, now_us);
+        }
+
+        let remeasure_due = now_us.saturating_sub(self.measured_at_us) >= REMEASURE_INTERVAL_US;
+        if self.session_id != self.node_id && remeasure_due {
+            let endpoint = self
+                .peers
+                .values()
+                .filter(|peer| peer.session_id == self.session_id)
+                .find_map(|peer| peer.endpoint);
+            if let Some(endpoint) = endpoint {
+                self.start_measurement(self.session_id, endpoint, now_us)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn start_measurement(&mut self, session_id: NodeId, endpoint: SocketAddr, now_us: u64) -> io::Result<()> {
+        self.measurement = Some(Measurement {
+            session_id,
+            endpoint,
+            offsets_us: Vec::with_capacity(MEASUREMENT_POINTS + 2),
+            sent_at_us: now_us,
+            retries: 0,
+        });
+        self.send_first_ping(session_id, endpoint, now_us)
+    }
+
+    fn send_first_ping(&mut self, session_id: NodeId, endpoint: SocketAddr, now_us: u64) -> io::Result<()> {
+        let mut ping = measurement_message(MESSAGE_PING);
+        encode_entry(&mut ping, KEY_HOST_TIME, &(now_us as i64).to_be_bytes());
+        self.unicast.send_to(&ping, endpoint)?;
+        if let Some(measurement) = self
+            .measurement
+            .as_mut()
+            .filter(|measurement| measurement.session_id == session_id)
+        {
+            measurement.sent_at_us = now_us;
+        }
+        Ok(())
+    }
+
+    fn ghost_us(&self, host_us: u64) -> i64 {
+        host_us as i64 + self.ghost_offset_us
+    }
+
+    fn broadcast(&mut self, kind: u8, now_us: u64) -> io::Result<()> {
+        let message = self.encode_discovery(kind);
+        for peer in &self.config.peers {
+            self.unicast.send_to(&message, peer)?;
+        }
+        self.last_broadcast_us = Some(now_us);
+        Ok(())
+    }
+
+    fn encode_discovery(&self, kind: u8) -> Vec<u8> {
+        let mut out = Vec::with_capacity(80);
+        out.extend_from_slice(DISCOVERY_HEADER);
+        out.push(kind);
+        out.push(self.config.ttl_secs);
+        out.extend_from_slice(&0u16.to_be_bytes());
+        out.extend_from_slice(&self.node_id);
+
+        if kind != MESSAGE_BYEBYE {
+            self.timeline.encode(&mut out);
+            encode_entry(&mut out, KEY_SESSION, &self.session_id);
+            let mut endpoint = u32::from(*self.endpoint.ip()).to_be_bytes().to_vec();
+            endpoint.extend_from_slice(&self.endpoint.port().to_be_bytes());
+            encode_entry(&mut out, KEY_MEASUREMENT_ENDPOINT, &endpoint);
+        }
+        out
+    }
+}
+
+fn decode_discovery(bytes: &[u8]) -> Option<DiscoveryMessage> {
+    let body = bytes.strip_prefix(DISCOVERY_HEADER)?;
+    if body.len() < 12 {
+        return None;
+    }
+
+    let mut message = DiscoveryMessage {
+        kind: body[0],
+        ttl_secs: body[1],
+        node_id: body[4..12].try_into().ok()?,
+        session_id: None,
+        timeline: None,
+        endpoint: None,
+    };
+    for (key, value) in entries(&body[12..]) {
+        match key {
+            KEY_TIMELINE => message.timeline = LinkTimeline::decode(value),
+            KEY_SESSION => message.session_id = value.try_into().ok(),
+            KEY_MEASUREMENT_ENDPOINT if value.len() == 6 => {
+                let ip = Ipv4Addr::from(u32::from_be_bytes(value[0..4].try_into().ok()?));
+                let port = u16::from_be_bytes([value[4], value[5]]);
+                message.endpoint = Some(SocketAddr::V4(SocketAddrV4::new(ip, port)));
+            }
+            _ => {}
+        }
+    }
+    Some(message)
+}
+
+/// Splits a payload into `(key, value)` entries, stopping at a truncated one.
+fn entries(mut payload: &[u8]) -> impl Iterator<Item = (u32, &[u8])> {
+    std::iter::from_fn(move || {
+        let key = u32::from_be_bytes(payload.get(0..4)?.try_into().ok()?);
+        let size = u32::from_be_bytes(payload.get(4..8)?.try_into().ok()?) as usize;
+        let value = payload.get(8..8 + size)?;
+        payload = &payload[8 + size..];
+        Some((key, value))
+    })
+}
+
+fn encode_entry(out: &mut Vec<u8>, key: u32, value: &[u8]) {
+    out.extend_from_slice(&key.to_be_bytes());
+    out.extend_from_slice(&(value.len() as u32).to_be_bytes());
+    out.extend_from_slice(value);
+}
+
+fn measurement_message(kind: u8) -> Vec<u8> {
+    let mut out = Vec::with_capacity(64);
+    out.extend_from_slice(MEASUREMENT_HEADER);
+    out.push(kind);
+    out
+}
+
+fn read_i64(bytes: &[u8]) -> Option<i64> {
+    Some(i64::from_be_bytes(bytes.get(0..8)?.try_into().ok()?))
+}
+
+fn micros_per_beat(bpm: f64) -> i64 {
+    (60_000_000.0 / bpm.clamp(MIN_BPM, MAX_BPM)).round() as i64
+}
+
+fn is_idle(err: &io::Error) -> bool {
+    // Windows reports an ICMP port unreachable from an earlier send as a reset.
+    matches!(
+        err.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::ConnectionReset
+    )
+}
+
+/// Binds the discovery port so other Link apps on this host can bind it too.
+fn bind_shared(addr: SocketAddrV4) -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.bind(&SocketAddr::V4(addr).into())?;
+    Ok(socket.into())
+}
+
+/// Address peers should send pings to: the bound address, or the interface
+/// that routes to the multicast group.
+fn advertised_ip(config: &LinkConfig) -> Ipv4Addr {
+    if !config.bind_addr.ip().is_unspecified() {
+        return *config.bind_addr.ip();
+    }
+    let group = config.multicast_group.unwrap_or(LINK_MULTICAST_GROUP);
+    UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0))
+        .and_then(|probe| {
+            probe.connect(SocketAddrV4::new(group, LINK_PORT))?;
+            probe.local_addr()
+        })
+        .ok()
+        .and_then(|addr| match addr.ip() {
+            IpAddr::V4(ip) if !ip.is_unspecified() => Some(ip),
+            _ => None,
+        })
+        .unwrap_or(Ipv4Addr::LOCALHOST)
+}
+
+/// Link node ids are eight printable ASCII characters.
+fn random_node_id() -> NodeId {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(std::process::id());
+    hasher.finish().to_be_bytes().map(|byte| b'!' + byte % 94)
+}
+
+fn hex_id(id: &NodeId) -> String {
+    id.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::clock::{Clock, ManualClock, MonotonicClock};
+    use crate::engine::tempo::{TempoEngine, TempoSource};
+    use std::sync::Arc;
+
+    struct LoopbackPeer {
+        session: LinkSession,
+        receiver: LinkReceiver,
+    }
+
+    fn loopback_peer(tempo: &TempoState, now_us: u64) -> LoopbackPeer {
+        let config = LinkConfig {
+            bind_addr: SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0),
+            multicast_group: None,
+            peers: Vec::new(),
+            ..LinkConfig::default()
+        };
+        let (session, receiver) = LinkSession::bind(config, tempo, now_us).expect("bind loopback peer");
+        LoopbackPeer { session, receiver }
+    }
+
+    /// Runs both peers for `duration_ms` of real time, as the app's Link loop does.
+    fn exchange(
+        clock: &MonotonicClock,
+        mut peers: [&mut LoopbackPeer; 2],
+        duration_ms: u64,
+    ) -> [Option<LinkTimeline>; 2] {
+        let mut adopted = [None, None];
+        let until_us = clock.now_us() + duration_ms * 1_000;
+        while clock.now_us() < until_us {
+            for (peer, adopted) in peers.iter_mut().zip(adopted.iter_mut()) {
+                let timeout = Duration::from_millis(1);
+                while let Some(datagram) = peer.receiver.receive(timeout, || clock.now_us()).unwrap() {
+                    if peer.session.handle(&datagram).unwrap() {
+                        *adopted = Some(peer.session.timeline());
+                    }
+                }
+                *adopted = peer.session.poll(clock.now_us()).unwrap().or(*adopted);
+            }
+        }
+        adopted
+    }
+
+    #[test]
+    fn messages_use_the_link_wire_format() {
+        let timeline = LinkTimeline {
+            micros_per_beat: 500_000,
+            beat_origin_micro_beats: 3_000_000,
+            time_origin_us: 1_234_567,
+        };
+        let mut bytes = Vec::new();
+        timeline.encode(&mut bytes);
+        assert_eq!(&bytes[0..8], b"tmln\x00\x00\x00\x18");
+        assert_eq!(LinkTimeline::decode(&bytes[8..]), Some(timeline));
+
+        let peer = loopback_peer(&TempoEngine::default().state(), 1_000_000);
+        let alive = peer.session.encode_discovery(MESSAGE_ALIVE);
+        assert_eq!(&alive[0..12], b"_asdp_v\x01\x01\x05\x00\x00");
+        let message = decode_discovery(&alive).unwrap();
+        assert_eq!(message.session_id, Some(peer.session.node_id()));
+        assert_eq!(message.endpoint, Some(peer.receiver.socket.local_addr().unwrap()));
+        assert!(peer.session.node_id().iter().all(|byte| byte.is_ascii_graphic()));
+    }
+
+    #[test]
+    fn tempo_changes_keep_the_beat_and_advance_the_origin() {
+        let timeline = LinkTimeline {
+            micros_per_beat: 500_000,
+            beat_origin_micro_beats: 1_000_000,
+            time_origin_us: 10_000_000,
+        };
+        let changed = timeline.with_tempo(150.0, 11_000_000);
+
+        assert!((changed.bpm() - 150.0).abs() < 1e-9);
+        assert_eq!(changed.beat_origin_micro_beats, 3_000_000);
+        assert!((changed.beat_at(11_000_000) - 3.0).abs() < 1e-9);
+        assert!((changed.beat_at(11_400_000) - 4.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn a_joining_peer_measures_the_running_session_and_follows_proposals() {
+        let clock = MonotonicClock::default();
+        let mut tempo_a = TempoEngine::default();
+        let clock_b = ManualClock::at_ms(10_000);
+        let mut tempo_b = TempoEngine::new(Arc::new(clock_b.clone()));
+        tempo_a.set_bpm(120.0, None);
+        tempo_b.set_bpm(100.0, None);
+
+        // B founded its session a minute before A, so its ghost clock is ahead.
+        let now_us = clock.now_us();
+        let mut b = loopback_peer(&tempo_b.state(), now_us - 60_000_000);
+        let mut a = loopback_peer(&tempo_a.state(), now_us);
+        a.session.add_peer(b.session.discovery_addr().unwrap());
+        b.session.add_peer(a.session.discovery_addr().unwrap());
+
+        let [joined, _] = exchange(&clock, [&mut a, &mut b], 1_500);
+        assert_eq!(joined, Some(b.session.timeline()));
+        assert_eq!(a.session.status().session_id, hex_id(&b.session.node_id()));
+        assert_eq!(b.session.status().session_id, hex_id(&b.session.node_id()));
+        assert_eq!(a.session.status().peer_count, 1);
+        assert!((a.session.timeline().bpm() - 100.0).abs() < 0.01);
+        // The measured offset lines both peers' bars up in host time.
+        let now_us = clock.now_us();
+        let downbeat_a = a.session.downbeat_epoch_ms(now_us) as i64;
+        let downbeat_b = b.session.downbeat_epoch_ms(now_us) as i64;
+        assert!((downbeat_a - downbeat_b).abs() <= 5, "{downbeat_a} vs {downbeat_b}");
+
+        let proposed = tempo_a.set_bpm(128.0, None);
+        a.session.propose(&proposed, clock.now_us()).unwrap();
+        let [_, adopted] = exchange(&clock, [&mut a, &mut b], 300);
+        let timeline = adopted.expect("peer b should adopt the proposal");
+        assert!((timeline.bpm() - 128.0).abs() < 0.01);
+
+        let offered = tempo_b.sync_external(
+            TempoSource::Link,
+            timeline.bpm(),
+            b.session.downbeat_epoch_ms(clock.now_us()),
+            1.0,
+        );
+        assert_eq!(offered.source, TempoSource::Manual);
+
+        // The session only reports changes; staying live hands it the grid.
+        clock_b.advance_ms(1_000);
+        let followed = tempo_b
+            .refresh_source(&TempoSource::Link, 1.0)
+            .expect("link should take over once live long enough");
+        assert!((followed.bpm - 128.0).abs() < 0.01);
+        assert_eq!(followed.source, TempoSource::Link);
+    }
+}
@@ -29,6 +29,7 @@ pub struct RuntimeCapabilities {
     pub selected_decode: DecodeBackend,
 }
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, Error)]
 pub enum MediaRuntimeError {
     #[error("webgpu is not available on this runtime")]
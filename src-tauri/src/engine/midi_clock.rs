@@ -0,0 +1,832 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use super::clock::SharedClock;
+use super::dispatch::{self, DispatchSignal};
+use super::tempo::TempoState;
+use crate::timeline::types::Meter;
+
+pub const MIDI_CLOCK_PPQN: u64 = 24;
+
+const STATUS_TIMING_CLOCK: u8 = 0xF8;
+const STATUS_START: u8 = 0xFA;
+const STATUS_CONTINUE: u8 = 0xFB;
+const STATUS_STOP: u8 = 0xFC;
+const STATUS_SONG_POSITION: u8 = 0xF2;
+const TICKS_PER_SONG_POSITION_UNIT: u64 = 6;
+const TICK_WINDOW: usize = 48;
+const MIN_TICKS_FOR_BPM: usize = 6;
+const INTERVAL_TOLERANCE: f64 = 0.35;
+/// Longest a device read waits for bytes before handing back an empty chunk.
+const DEVICE_POLL_TIMEOUT_MS: u16 = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MidiClockMessage {
+    Tick,
+    Start,
+    Continue,
+    Stop,
+    SongPosition(u16),
+}
+
+/// Source of raw MIDI bytes stamped with their arrival time in microseconds.
+pub trait MidiByteSource {
+    /// Returns the next chunk of bytes, or `None` once the stream has ended.
+    /// A chunk may be empty when the source stopped waiting for input.
+    fn next_chunk(&mut self) -> io::Result<Option<(u64, Vec<u8>)>>;
+}
+
+/// Reads from any byte stream (a raw MIDI device node, a pipe or a file) and
+/// stamps each read with the supplied microsecond clock.
+pub struct ReaderSource<R, C> {
+    reader: R,
+    clock_us: C,
+}
+
+impl<R: Read, C: FnMut() -> u64> ReaderSource<R, C> {
+    pub fn new(reader: R, clock_us: C) -> Self {
+        Self { reader, clock_us }
+    }
+}
+
+impl<R: Read, C: FnMut() -> u64> MidiByteSource for ReaderSource<R, C> {
+    fn next_chunk(&mut self) -> io::Result<Option<(u64, Vec<u8>)>> {
+        let mut buffer = [0u8; 64];
+        let length = self.reader.read(&mut buffer)?;
+        if length == 0 {
+            return Ok(None);
+        }
+        Ok(Some(((self.clock_us)(), buffer[..length].to_vec())))
+    }
+}
+
+/// Reads a raw MIDI device node without blocking for long: when nothing
+/// arrives within 50 ms it returns an empty chunk, so the reader can check
+/// whether it has been stopped and close the device.
+pub struct DeviceSource<C> {
+    reader: ReaderSource<File, C>,
+}
+
+impl<C: FnMut() -> u64> DeviceSource<C> {
+    pub fn new(device: File, clock_us: C) -> Self {
+        Self {
+            reader: ReaderSource::new(device, clock_us),
+        }
+    }
+}
+
+impl<C: FnMut() -> u64> MidiByteSource for DeviceSource<C> {
+    fn next_chunk(&mut self) -> io::Result<Option<(u64, Vec<u8>)>> {
+        if wait_readable(&self.reader.reader, DEVICE_POLL_TIMEOUT_MS)? {
+            self.reader.next_chunk()
+        } else {
+            Ok(Some(((self.reader.clock_us)(), Vec::new())))
+        }
+    }
+}
+
+#[cfg(unix)]
+fn wait_readable(file: &File, timeout_ms: u16) -> io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut poll_fd = libc::pollfd {
+        fd: file.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // SAFETY: `poll_fd` is one valid pollfd that outlives the call.
+    match unsafe { libc::poll(&mut poll_fd, 1, i32::from(timeout_ms)) } {
+        0 => Ok(false),
+        -1 => match io::Error::last_os_error() {
+            err if err.kind() == io::ErrorKind::Interrupted => Ok(false),
+            err => Err(err),
+        },
+        _ => Ok(true),
+    }
+}
+
+/// Without `poll` the read blocks, so a stopped reader exits on the next byte.
+#[cfg(not(unix))]
+fn wait_readable(_file: &File, _timeout_ms: u16) -> io::Result<bool> {
+    Ok(true)
+}
+
+/// Splits a MIDI byte stream into the clock and transport messages, letting
+/// real-time bytes interleave with other traffic.
+#[derive(Debug, Clone, Default)]
+pub struct MidiClockParser {
+    status: Option<u8>,
+    data: Vec<u8>,
+}
+
+impl MidiClockParser {
+    pub fn push(&mut self, byte: u8) -> Option<MidiClockMessage> {
+        match byte {
+            STATUS_TIMING_CLOCK => Some(MidiClockMessage::Tick),
+            STATUS_START => Some(MidiClockMessage::Start),
+            STATUS_CONTINUE => Some(MidiClockMessage::Continue),
+            STATUS_STOP => Some(MidiClockMessage::Stop),
+            0xF9 | 0xFD..=0xFF => None,
+            0x80..=0xF7 => {
+                self.status = Some(byte);
+                self.data.clear();
+                None
+            }
+            _ => {
+                if self.status != Some(STATUS_SONG_POSITION) {
+                    return None;
+                }
+                self.data.push(byte);
+                if self.data.len() < 2 {
+                    return None;
+                }
+                let position = u16::from(self.data[0]) | (u16::from(self.data[1]) << 7);
+                self.status = None;
+                self.data.clear();
+                Some(MidiClockMessage::SongPosition(position))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MidiClockUpdate {
+    pub bpm: Option<f64>,
+    pub confidence: f64,
+    pub downbeat_epoch_ms: Option<u64>,
+    pub running: bool,
+    pub song_position_ticks: u64,
+}
+
+/// Follows an incoming 24 PPQN clock. Tick intervals are fitted with a
+/// least-squares line over a sliding window so single late or early ticks do
+/// not move the tempo, and dropped ticks are bridged instead of halving it.
+#[derive(Debug, Clone)]
+pub struct MidiClockFollower {
+    parser: MidiClockParser,
+    bar_ticks: u64,
+    running: bool,
+    song_position_ticks: u64,
+    anchor_pending: bool,
+    anchor: Option<(u64, u64)>,
+    window: VecDeque<(u64, u64)>,
+    next_index: u64,
+    tick_us: Option<f64>,
+    confidence: f64,
+}
+
+impl Default for MidiClockFollower {
+    fn default() -> Self {
+        Self {
+            parser: MidiClockParser::default(),
+            bar_ticks: bar_ticks(&Meter::default()),
+            running: false,
+            song_position_ticks: 0,
+            anchor_pending: false,
+            anchor: None,
+            window: VecDeque::with_capacity(TICK_WINDOW),
+            next_index: 0,
+            tick_us: None,
+            confidence: 0.0,
+        }
+    }
+}
+
+impl MidiClockFollower {
+    /// Sets the bar length used to place the downbeat from a song position.
+    pub fn set_meter(&mut self, meter: &Meter) {
+        self.bar_ticks = bar_ticks(meter);
+    }
+
+    /// Feeds raw bytes received at `timestamp_us`. Returns an update on every
+    /// quarter note and on every transport change.
+    pub fn feed(&mut self, timestamp_us: u64, bytes: &[u8]) -> Option<MidiClockUpdate> {
+        let mut update = None;
+        for byte in bytes {
+            if let Some(message) = self.parser.push(*byte) {
+                update = self.handle(timestamp_us, message).or(update);
+            }
+        }
+        update
+    }
+
+    /// Drains `source` until it ends, returning the most recent update.
+    pub fn drain<S: MidiByteSource>(&mut self, source: &mut S) -> io::Result<Option<MidiClockUpdate>> {
+        let mut update = None;
+        while let Some((timestamp_us, bytes)) = source.next_chunk()? {
+            update = self.feed(timestamp_us, &bytes).or(update);
+        }
+        Ok(update)
+    }
+
+    pub fn handle(&mut self, timestamp_us: u64, message: MidiClockMessage) -> Option<MidiClockUpdate> {
+        match message {
+            MidiClockMessage::Tick => self.tick(timestamp_us),
+            MidiClockMessage::Start => {
+                self.running = true;
+                self.song_position_ticks = 0;
+                self.anchor_pending = true;
+                Some(self.update())
+            }
+            MidiClockMessage::Continue => {
+                self.running = true;
+                Some(self.update())
+            }
+            MidiClockMessage::Stop => {
+                self.running = false;
+                Some(self.update())
+            }
+            MidiClockMessage::SongPosition(position) => {
+                self.song_position_ticks = u64::from(position) * TICKS_PER_SONG_POSITION_UNIT;
+                self.anchor_pending = true;
+                Some(self.update())
+            }
+        }
+    }
+
+    pub fn bpm(&self) -> Option<f64> {
+        self.tick_us
+            .map(|tick_us| 60_000_000.0 / (tick_us * MIDI_CLOCK_PPQN as f64))
+    }
+
+    pub fn update(&self) -> MidiClockUpdate {
+        MidiClockUpdate {
+            bpm: self.bpm(),
+            confidence: self.confidence,
+            downbeat_epoch_ms: self.downbeat_epoch_ms(),
+            running: self.running,
+            song_position_ticks: self.song_position_ticks,
+        }
+    }
+
+    fn tick(&mut self, timestamp_us: u64) -> Option<MidiClockUpdate> {
+        self.track_interval(timestamp_us);
+
+        if !self.running {
+            return None;
+        }
+
+        if self.anchor_pending {
+            self.anchor = Some((timestamp_us, self.song_position_ticks));
+            self.anchor_pending = false;
+        }
+
+        let position = self.song_position_ticks;
+        self.song_position_ticks += 1;
+        position.is_multiple_of(MIDI_CLOCK_PPQN).then(|| self.update())
+    }
+
+    fn track_interval(&mut self, timestamp_us: u64) {
+        match (self.window.back(), self.tick_us) {
+            (Some(&(last_index, last_us)), Some(tick_us)) => {
+                let ratio = timestamp_us.saturating_sub(last_us) as f64 / tick_us;
+                let steps = ratio.round().max(1.0);
+                if (ratio - steps).abs() > INTERVAL_TOLERANCE || steps > 4.0 {
+                    // Not a whole number of ticks: the clock jumped, so relearn it.
+                    self.window.clear();
+                    self.tick_us = None;
+                    self.next_index = 0;
+                } else {
+                    self.next_index = last_index + steps as u64;
+                }
+            }
+            (Some(&(last_index, _)), None) => self.next_index = last_index + 1,
+            (None, _) => self.next_index = 0,
+        }
+
+        if self.window.len() == TICK_WINDOW {
+            self.window.pop_front();
+        }
+        self.window.push_back((self.next_index, timestamp_us));
+        self.fit_tick_interval();
+    }
+
+    fn fit_tick_interval(&mut self) {
+        if self.window.len() < 2 {
+            return;
+        }
+
+        let (first_index, first_us) = self.window[0];
+        let points = self
+            .window
+            .iter()
+            .map(|(index, us)| ((index - first_index) as f64, us.saturating_sub(first_us) as f64))
+            .collect::<Vec<_>>();
+        let count = points.len() as f64;
+        let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / count;
+        let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / count;
+        let covariance = points.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum::<f64>();
+        let variance = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum::<f64>();
+        if variance <= f64::EPSILON || covariance <= 0.0 {
+            return;
+        }
+
+        let slope = covariance / variance;
+        if self.window.len() >= MIN_TICKS_FOR_BPM || self.tick_us.is_none() {
+            self.tick_us = Some(slope);
+        }
+
+        let residual = points
+            .iter()
+            .map(|(x, y)| (y - (mean_y + slope * (x - mean_x))).powi(2))
+            .sum::<f64>()
+            / count;
+        let sample_weight = (self.window.len() as f64 / TICK_WINDOW as f64).clamp(0.2, 1.0);
+        self.confidence = ((1.0 - residual.sqrt() / slope) * sample_weight).clamp(0.0, 1.0);
+    }
+
+    fn downbeat_epoch_ms(&self) -> Option<u64> {
+        let (anchor_us, anchor_ticks) = self.anchor?;
+        let ticks_into_bar = anchor_ticks % self.bar_ticks;
+        let offset_us = match self.tick_us {
+            Some(tick_us) => ticks_into_bar as f64 * tick_us,
+            None if ticks_into_bar == 0 => 0.0,
+            None => return None,
+        };
+        Some(((anchor_us as f64 - offset_us) / 1_000.0).round().max(0.0) as u64)
+    }
+}
+
+/// Destination for generated clock and transport messages. `timestamp_us` is
+/// the exact grid time of the message, which may still be in the future.
+pub trait MidiClockSink {
+    fn send(&mut self, timestamp_us: u64, message: &[u8]) -> io::Result<()>;
+}
+
+/// Writes messages to a byte stream such as a raw MIDI device node. Raw device
+/// nodes cannot schedule output, so `send` only queues each message and a
+/// writer thread holds it until `clock` reaches its timestamp; messages already
+/// due are written at once. Dropping the sink lets the thread write what is
+/// queued and exit, while `cancel` discards it.
+pub struct WriterSink {
+    queue: Sender<(u64, Vec<u8>)>,
+    cancel: Arc<DispatchSignal>,
+    writer: JoinHandle<io::Result<()>>,
+}
+
+impl WriterSink {
+    pub fn spawn<W: Write + Send + 'static>(writer: W, clock: SharedClock) -> io::Result<Self> {
+        let (queue, messages) = mpsc::channel();
+        let cancel = Arc::new(DispatchSignal::default());
+        let writer = {
+            let cancel = cancel.clone();
+            thread::Builder::new()
+                .name("fftron-midi-clock-out".to_string())
+                .spawn(move || write_when_due(writer, clock, messages, &cancel))?
+        };
+        Ok(Self { queue, cancel, writer })
+    }
+
+    /// Stops the writer thread without writing what is still queued, and
+    /// returns the write error that ended it early, if any.
+    pub fn cancel(self) -> io::Result<()> {
+        self.cancel.notify();
+        drop(self.queue);
+        self.writer
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("midi clock writer panicked")))
+    }
+}
+
+impl MidiClockSink for WriterSink {
+    fn send(&mut self, timestamp_us: u64, message: &[u8]) -> io::Result<()> {
+        self.queue
+            .send((timestamp_us, message.to_vec()))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "midi clock writer stopped"))
+    }
+}
+
+fn write_when_due(
+    mut writer: impl Write,
+    clock: SharedClock,
+    messages: Receiver<(u64, Vec<u8>)>,
+    cancel: &DispatchSignal,
+) -> io::Result<()> {
+    let _ = dispatch::raise_thread_priority();
+    for (timestamp_us, message) in messages {
+        if cancel.wait_until(clock.as_ref(), Some(timestamp_us)) {
+            return Ok(());
+        }
+        writer.write_all(&message)?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ClockGrid {
+    bpm: f64,
+    downbeat_epoch_ms: u64,
+    bar_ticks: u64,
+}
+
+impl ClockGrid {
+    fn from_tempo(tempo: &TempoState) -> Self {
+        Self {
+            bpm: tempo.bpm,
+            downbeat_epoch_ms: tempo.downbeat_epoch_ms,
+            bar_ticks: bar_ticks(&tempo.meter),
+        }
+    }
+
+    fn tick_us(&self) -> f64 {
+        60_000_000.0 / (self.bpm * MIDI_CLOCK_PPQN as f64)
+    }
+
+    fn time_us(&self, tick: i64) -> f64 {
+        self.downbeat_epoch_ms as f64 * 1_000.0 + tick as f64 * self.tick_us()
+    }
+
+    /// First tick whose time is strictly after `timestamp_us`.
+    fn tick_after(&self, timestamp_us: f64) -> i64 {
+        ((timestamp_us - self.downbeat_epoch_ms as f64 * 1_000.0) / self.tick_us()).floor() as i64 + 1
+    }
+}
+
+/// Generates a 24 PPQN clock locked to the tempo grid. Tick times are derived
+/// from the grid rather than accumulated, so they never drift; when the grid
+/// moves the generator picks up the new tick phase and, if the bar position
+/// changed, relocates receivers with Stop, Song Position Pointer and Continue.
+#[derive(Debug, Clone, Default)]
+pub struct MidiClockGenerator {
+    running: bool,
+    pending_transport: Option<u8>,
+    grid: Option<ClockGrid>,
+    next_tick: i64,
+    last_tick_us: Option<f64>,
+    song_position_ticks: u64,
+}
+
+impl MidiClockGenerator {
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    pub fn song_position_ticks(&self) -> u64 {
+        self.song_position_ticks
+    }
+
+    /// Rewinds receivers to the song start and starts them on the next downbeat.
+    pub fn start<S: MidiClockSink>(&mut self, tempo: &TempoState, now_us: u64, sink: &mut S) -> io::Result<()> {
+        let grid = ClockGrid::from_tempo(tempo);
+        sink.send(now_us, &song_position_message(0))?;
+        self.grid = Some(grid);
+        self.next_tick = next_tick_in_phase(&grid, now_us as f64, 0);
+        self.song_position_ticks = 0;
+        self.running = true;
+        self.pending_transport = Some(STATUS_START);
+        Ok(())
+    }
+
+    pub fn stop<S: MidiClockSink>(&mut self, now_us: u64, sink: &mut S) -> io::Result<()> {
+        if !self.running {
+            return Ok(());
+        }
+        self.running = false;
+        self.pending_transport = None;
+        sink.send(now_us, &[STATUS_STOP])?;
+        sink.send(now_us, &song_position_message(self.song_position_ticks))
+    }
+
+    /// Emits every message due up to and including `until_us`. Returns the
+    /// number of clock ticks sent.
+    pub fn render<S: MidiClockSink>(&mut self, tempo: &TempoState, until_us: u64, sink: &mut S) -> io::Result<usize> {
+        let grid = ClockGrid::from_tempo(tempo);
+        if self.grid.is_some_and(|current| current != grid) {
+            self.realign(grid, until_us, sink)?;
+        }
+        self.grid = Some(grid);
+
+        let mut sent = 0;
+        while self.running && grid.time_us(self.next_tick) <= until_us as f64 {
+            let at_us = grid.time_us(self.next_tick);
+            let timestamp_us = at_us.round().max(0.0) as u64;
+            if let Some(status) = self.pending_transport.take() {
+                sink.send(timestamp_us, &[status])?;
+            }
+            sink.send(timestamp_us, &[STATUS_TIMING_CLOCK])?;
+            self.last_tick_us = Some(at_us);
+            self.next_tick += 1;
+            self.song_position_ticks += 1;
+            sent += 1;
+        }
+        Ok(sent)
+    }
+
+    fn realign<S: MidiClockSink>(&mut self, grid: ClockGrid, now_us: u64, sink: &mut S) -> io::Result<()> {
+        let bar_ticks = grid.bar_ticks;
+        if self.pending_transport.is_some() {
+            let phase = self.song_position_ticks % bar_ticks;
+            self.next_tick = next_tick_in_phase(&grid, now_us as f64, phase);
+            return Ok(());
+        }
+
+        let resume_after = match self.last_tick_us {
+            Some(last_us) => last_us + grid.tick_us() / 2.0,
+            None => now_us as f64,
+        };
+        self.next_tick = grid.tick_after(resume_after);
+
+        let grid_in_bar = self.next_tick.rem_euclid(bar_ticks as i64) as u64;
+        if !self.running || grid_in_bar == self.song_position_ticks % bar_ticks {
+            return Ok(());
+        }
+
+        // Relocate on the next sixteenth, the resolution of Song Position Pointer.
+        let snap = TICKS_PER_SONG_POSITION_UNIT as i64;
+        self.next_tick = (self.next_tick + snap - 1).div_euclid(snap) * snap;
+        let target_in_bar = self.next_tick.rem_euclid(bar_ticks as i64) as u64;
+        let bar_start = self.song_position_ticks - self.song_position_ticks % bar_ticks;
+        let mut position = bar_start + target_in_bar;
+        if position + bar_ticks / 2 < self.song_position_ticks {
+            position += bar_ticks;
+        }
+
+        let timestamp_us = now_us.max(self.last_tick_us.unwrap_or(0.0) as u64);
+        sink.send(timestamp_us, &[STATUS_STOP])?;
+        sink.send(timestamp_us, &song_position_message(position))?;
+        self.song_position_ticks = position;
+        self.pending_transport = Some(STATUS_CONTINUE);
+        Ok(())
+    }
+}
+
+/// First tick at or after `timestamp_us` sitting `phase` ticks into a bar.
+fn next_tick_in_phase(grid: &ClockGrid, timestamp_us: f64, phase: u64) -> i64 {
+    let first = grid.tick_after(timestamp_us - 1.0);
+    first + (phase as i64 - first).rem_euclid(grid.bar_ticks as i64)
+}
+
+fn bar_ticks(meter: &Meter) -> u64 {
+    (meter.bar_beats() * MIDI_CLOCK_PPQN as f64).round() as u64
+}
+
+fn song_position_message(position_ticks: u64) -> [u8; 3] {
+    let sixteenths = (position_ticks / TICKS_PER_SONG_POSITION_UNIT).min(0x3FFF) as u16;
+    [STATUS_SONG_POSITION, (sixteenths & 0x7F) as u8, (sixteenths >> 7) as u8]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::clock::ManualClock;
+
+    struct ScriptedSource(VecDeque<(u64, Vec<u8>)>);
+
+    impl MidiByteSource for ScriptedSource {
+        fn next_chunk(&mut self) -> io::Result<Option<(u64, Vec<u8>)>> {
+            Ok(self.0.pop_front())
+        }
+    }
+
+    fn clock_script(start_us: u64, bpm: f64, ticks: usize, jitter_us: &[i64]) -> Vec<(u64, Vec<u8>)> {
+        let tick_us = 60_000_000.0 / (bpm * MIDI_CLOCK_PPQN as f64);
+        (0..ticks)
+            .map(|index| {
+                let jitter = jitter_us[index % jitter_us.len()];
+                let at = (start_us as f64 + index as f64 * tick_us) as i64 + jitter;
+                (at as u64, vec![STATUS_TIMING_CLOCK])
+            })
+            .collect()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn device_source_returns_empty_chunks_while_idle() {
+        use std::os::fd::OwnedFd;
+
+        let (reader, mut writer) = io::pipe().unwrap();
+        let mut source = DeviceSource::new(File::from(OwnedFd::from(reader)), || 7);
+        assert_eq!(source.next_chunk().unwrap(), Some((7, Vec::new())));
+
+        writer.write_all(&[STATUS_TIMING_CLOCK]).unwrap();
+        assert_eq!(source.next_chunk().unwrap(), Some((7, vec![STATUS_TIMING_CLOCK])));
+        drop(writer);
+        assert_eq!(source.next_chunk().unwrap(), None);
+    }
+
+    #[test]
+    fn parser_handles_interleaved_realtime_bytes() {
+        let mut parser = MidiClockParser::default();
+        let messages = [0x90, 0x3C, 0xF8, 0x40, STATUS_SONG_POSITION, 0x10, 0xF8, 0x01]
+            .into_iter()
+            .filter_map(|byte| parser.push(byte))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            messages,
+            vec![
+                MidiClockMessage::Tick,
+                MidiClockMessage::Tick,
+                MidiClockMessage::SongPosition(0x10 | (1 << 7)),
+            ]
+        );
+    }
+
+    #[test]
+    fn jittery_ticks_settle_on_tempo() {
+        let mut follower = MidiClockFollower::default();
+        let mut script = vec![(0, vec![STATUS_START])];
+        script.extend(clock_script(
+            1_000_000,
+            125.0,
+            24 * 8,
+            &[0, 900, -700, 400, -1_100, 300],
+        ));
+
+        let update = follower
+            .drain(&mut ScriptedSource(script.into()))
+            .unwrap()
+            .expect("clock should produce an update");
+
+        let bpm = update.bpm.expect("bpm after two bars");
+        assert!((bpm - 125.0).abs() < 0.2, "bpm was {bpm}");
+        assert!(update.running);
+    }
+
+    #[test]
+    fn dropped_tick_does_not_halve_tempo() {
+        let mut follower = MidiClockFollower::default();
+        let mut script = clock_script(0, 120.0, 96, &[0]);
+        script.remove(50);
+        follower.drain(&mut ScriptedSource(script.into())).unwrap();
+
+        let bpm = follower.bpm().unwrap();
+        assert!((bpm - 120.0).abs() < 0.1, "bpm was {bpm}");
+    }
+
+    #[test]
+    fn tempo_jump_is_relearned() {
+        let mut follower = MidiClockFollower::default();
+        let mut script = clock_script(0, 120.0, 48, &[0]);
+        script.extend(clock_script(1_000_000, 90.0, 48, &[0]));
+        follower.drain(&mut ScriptedSource(script.into())).unwrap();
+
+        let bpm = follower.bpm().unwrap();
+        assert!((bpm - 90.0).abs() < 0.1, "bpm was {bpm}");
+    }
+
+    #[test]
+    fn start_and_song_position_reanchor_downbeat() {
+        let mut follower = MidiClockFollower::default();
+        follower
+            .drain(&mut ScriptedSource(clock_script(0, 120.0, 48, &[0]).into()))
+            .unwrap();
+        assert_eq!(follower.update().downbeat_epoch_ms, None);
+
+        follower.feed(999_000, &[STATUS_START]);
+        follower
+            .drain(&mut ScriptedSource(clock_script(1_000_000, 120.0, 48, &[0]).into()))
+            .unwrap();
+        assert_eq!(follower.update().downbeat_epoch_ms, Some(1_000));
+
+        // Song position 4 sixteenths = one beat (500ms at 120 BPM) into the bar.
+        follower.feed(
+            1_990_000,
+            &[STATUS_STOP, STATUS_SONG_POSITION, 0x04, 0x00, STATUS_CONTINUE],
+        );
+        follower.feed(2_000_000, &[STATUS_TIMING_CLOCK]);
+
+        let update = follower.update();
+        assert_eq!(update.song_position_ticks, 25);
+        assert_eq!(update.downbeat_epoch_ms, Some(1_500));
+    }
+
+    #[derive(Default)]
+    struct RecordingSink(Vec<(u64, Vec<u8>)>);
+
+    impl MidiClockSink for RecordingSink {
+        fn send(&mut self, timestamp_us: u64, message: &[u8]) -> io::Result<()> {
+            self.0.push((timestamp_us, message.to_vec()));
+            Ok(())
+        }
+    }
+
+    impl RecordingSink {
+        fn tick_times(&self) -> Vec<u64> {
+            self.0
+                .iter()
+                .filter(|(_, message)| message == &[STATUS_TIMING_CLOCK])
+                .map(|(at, _)| *at)
+                .collect()
+        }
+    }
+
+    /// A writer the test can read while the sink's thread owns it.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuffer {
+        fn settle(&self) -> Vec<u8> {
+            thread::sleep(std::time::Duration::from_millis(20));
+            self.0.lock().unwrap().clone()
+        }
+    }
+
+    #[test]
+    fn writer_sink_holds_messages_until_their_timestamp() {
+        let clock = Arc::new(ManualClock::at_ms(1_000));
+        let buffer = SharedBuffer::default();
+        let mut sink = WriterSink::spawn(buffer.clone(), clock.clone()).unwrap();
+
+        // Sending never waits, even for messages the clock has not reached.
+        sink.send(1_000_000, &[STATUS_TIMING_CLOCK]).unwrap();
+        sink.send(1_002_000, &[STATUS_STOP]).unwrap();
+        assert_eq!(buffer.settle(), vec![STATUS_TIMING_CLOCK]);
+
+        clock.advance_ms(2);
+        assert_eq!(buffer.settle(), vec![STATUS_TIMING_CLOCK, STATUS_STOP]);
+
+        sink.send(5_000_000, &[STATUS_START]).unwrap();
+        sink.cancel().unwrap();
+        assert_eq!(buffer.settle(), vec![STATUS_TIMING_CLOCK, STATUS_STOP]);
+    }
+
+    fn tempo_state(bpm: f64, downbeat_epoch_ms: u64) -> TempoState {
+        let mut tempo = crate::engine::tempo::TempoEngine::default();
+        tempo.set_bpm(bpm, None);
+        tempo.resync_downbeat(Some(downbeat_epoch_ms))
+    }
+
+    #[test]
+    fn generator_starts_on_next_downbeat_with_exact_spacing() {
+        let tempo = tempo_state(120.0, 1_000);
+        let mut generator = MidiClockGenerator::default();
+        let mut sink = RecordingSink::default();
+
+        generator.start(&tempo, 1_200_000, &mut sink).unwrap();
+        let sent = generator.render(&tempo, 4_000_000, &mut sink).unwrap();
+
+        assert_eq!(sink.0[0], (1_200_000, vec![STATUS_SONG_POSITION, 0, 0]));
+        assert_eq!(sink.0[1], (3_000_000, vec![STATUS_START]));
+        assert_eq!(sent, 49);
+
+        let ticks = sink.tick_times();
+        assert_eq!(ticks[0], 3_000_000);
+        assert!(ticks
+            .windows(2)
+            .all(|pair| (20_833..=20_834).contains(&(pair[1] - pair[0]))));
+        assert_eq!(ticks[24], 3_500_000);
+    }
+
+    #[test]
+    fn generator_follows_tempo_changes_without_doubling_ticks() {
+        let mut generator = MidiClockGenerator::default();
+        let mut sink = RecordingSink::default();
+        let tempo = tempo_state(120.0, 0);
+        generator.start(&tempo, 0, &mut sink).unwrap();
+        generator.render(&tempo, 1_000_000, &mut sink).unwrap();
+
+        // Same bar phase at 1s (two beats in), shorter beats from then on.
+        let faster = tempo_state(150.0, 200);
+        generator.render(&faster, 2_000_000, &mut sink).unwrap();
+
+        let ticks = sink.tick_times();
+        let spacing = ticks.windows(2).map(|pair| pair[1] - pair[0]).collect::<Vec<_>>();
+        assert!(spacing.iter().all(|gap| *gap >= 16_666 && *gap <= 20_834 + 16_667 / 2));
+        assert!((16_666..=16_667).contains(spacing.last().unwrap()));
+    }
+
+    #[test]
+    fn resync_relocates_receivers_to_new_bar_position() {
+        let mut generator = MidiClockGenerator::default();
+        let mut sink = RecordingSink::default();
+        let tempo = tempo_state(120.0, 0);
+        generator.start(&tempo, 0, &mut sink).unwrap();
+        generator.render(&tempo, 1_000_000, &mut sink).unwrap();
+        sink.0.clear();
+
+        // Downbeat moved by a quarter note: the next grid tick is a beat off.
+        let resynced = tempo_state(120.0, 1_500);
+        generator.render(&resynced, 1_200_000, &mut sink).unwrap();
+
+        assert_eq!(sink.0[0].1, vec![STATUS_STOP]);
+        assert_eq!(sink.0[1].1[0], STATUS_SONG_POSITION);
+        let continue_at = sink
+            .0
+            .iter()
+            .position(|(_, message)| message == &[STATUS_CONTINUE])
+            .unwrap();
+        assert_eq!(sink.0[continue_at + 1].1, vec![STATUS_TIMING_CLOCK]);
+        assert_eq!(
+            generator.next_tick.rem_euclid(96) as u64,
+            generator.song_position_ticks() % 96
+        );
+    }
+}
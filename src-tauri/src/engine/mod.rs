@@ -1,3 +1,16 @@
+pub mod arbiter;
+pub mod auto_tempo;
+pub mod clock;
+pub mod conflict;
+pub mod dispatch;
+pub mod link;
 pub mod media;
+pub mod midi_clock;
+pub mod osc;
+pub mod pattern;
 pub mod scheduler;
+pub mod tap_tempo;
 pub mod tempo;
+pub mod tempo_log;
+pub mod tempo_map;
+pub mod transport;
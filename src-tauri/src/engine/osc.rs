@@ -0,0 +1,1163 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+use thiserror::Error;
+
+use super::scheduler::{ScheduledAction, SectionChange};
+use super::tempo::{BeatPosition, TempoState};
+use crate::timeline::types::QuantizeGrid;
+
+pub const OSC_PORT: u16 = 9000;
+pub const OSC_NAMESPACE: &str = "/fftron";
+
+const BUNDLE_TAG: &[u8; 8] = b"#bundle\0";
+/// Seconds from the NTP epoch (1900) to the Unix epoch.
+const NTP_UNIX_OFFSET_SECS: u64 = 2_208_988_800;
+/// Beats the ticker replays after a stall before it skips ahead.
+const MAX_CATCH_UP_BEATS: i64 = 4;
+const MAX_DATAGRAM: usize = 4_096;
+const RECV_TIMEOUT: Duration = Duration::from_millis(20);
+
+#[derive(Debug, Error, PartialEq)]
+pub enum OscError {
+    #[error("packet is truncated")]
+    Truncated,
+    #[error("packet is not an OSC message or bundle")]
+    Malformed,
+    #[error("type tag '{0}' is not supported")]
+    UnsupportedTag(char),
+    #[error("no handler for {0}")]
+    UnknownAddress(String),
+    #[error("{0} expects {1}")]
+    InvalidArguments(String, &'static str),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OscArg {
+    Int(i32),
+    Float(f32),
+    String(String),
+    Blob(Vec<u8>),
+    Long(i64),
+    Double(f64),
+    /// NTP timetag: seconds since 1900 in the high 32 bits, fraction in the low.
+    Time(u64),
+    Bool(bool),
+    Nil,
+    Impulse,
+}
+
+impl OscArg {
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            OscArg::Int(value) => Some(f64::from(*value)),
+            OscArg::Float(value) => Some(f64::from(*value)),
+            OscArg::Long(value) => Some(*value as f64),
+            OscArg::Double(value) => Some(*value),
+            OscArg::Bool(value) => Some(if *value { 1.0 } else { 0.0 }),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            OscArg::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn tag(&self) -> char {
+        match self {
+            OscArg::Int(_) => 'i',
+            OscArg::Float(_) => 'f',
+            OscArg::String(_) => 's',
+            OscArg::Blob(_) => 'b',
+            OscArg::Long(_) => 'h',
+            OscArg::Double(_) => 'd',
+            OscArg::Time(_) => 't',
+            OscArg::Bool(true) => 'T',
+            OscArg::Bool(false) => 'F',
+            OscArg::Nil => 'N',
+            OscArg::Impulse => 'I',
+        }
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            OscArg::Int(value) => out.extend_from_slice(&value.to_be_bytes()),
+            OscArg::Float(value) => out.extend_from_slice(&value.to_be_bytes()),
+            OscArg::String(value) => write_string(out, value),
+            OscArg::Blob(bytes) => {
+                out.extend_from_slice(&(bytes.len() as i32).to_be_bytes());
+                out.extend_from_slice(bytes);
+                pad(out);
+            }
+            OscArg::Long(value) => out.extend_from_slice(&value.to_be_bytes()),
+            OscArg::Double(value) => out.extend_from_slice(&value.to_be_bytes()),
+            OscArg::Time(value) => out.extend_from_slice(&value.to_be_bytes()),
+            OscArg::Bool(_) | OscArg::Nil | OscArg::Impulse => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OscMessage {
+    pub address: String,
+    pub args: Vec<OscArg>,
+}
+
+impl OscMessage {
+    pub fn new(address: impl Into<String>, args: Vec<OscArg>) -> Self {
+        Self {
+            address: address.into(),
+            args,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_string(&mut out, &self.address);
+        let tags = std::iter::once(',')
+            .chain(self.args.iter().map(OscArg::tag))
+            .collect::<String>();
+        write_string(&mut out, &tags);
+        for arg in &self.args {
+            arg.encode(&mut out);
+        }
+        out
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, OscError> {
+        let mut reader = Reader { bytes, offset: 0 };
+        let address = reader.string()?;
+        if !address.starts_with('/') {
+            return Err(OscError::Malformed);
+        }
+        // Very old senders omit the type tag string entirely.
+        if reader.is_empty() {
+            return Ok(Self::new(address, Vec::new()));
+        }
+        let tags = reader.string()?;
+        let Some(tags) = tags.strip_prefix(',') else {
+            return Err(OscError::Malformed);
+        };
+
+        let mut args = Vec::with_capacity(tags.len());
+        for tag in tags.chars() {
+            args.push(match tag {
+                'i' => OscArg::Int(i32::from_be_bytes(reader.array()?)),
+                'f' => OscArg::Float(f32::from_be_bytes(reader.array()?)),
+                's' | 'S' => OscArg::String(reader.string()?),
+                'b' => {
+                    let len = usize::try_from(i32::from_be_bytes(reader.array()?)).map_err(|_| OscError::Malformed)?;
+                    let blob = reader.take(len)?.to_vec();
+                    reader.align()?;
+                    OscArg::Blob(blob)
+                }
+                'h' => OscArg::Long(i64::from_be_bytes(reader.array()?)),
+                'd' => OscArg::Double(f64::from_be_bytes(reader.array()?)),
+                't' => OscArg::Time(u64::from_be_bytes(reader.array()?)),
+                'T' => OscArg::Bool(true),
+                'F' => OscArg::Bool(false),
+                'N' => OscArg::Nil,
+                'I' => OscArg::Impulse,
+                other => return Err(OscError::UnsupportedTag(other)),
+            });
+        }
+        Ok(Self::new(address, args))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OscPacket {
+    Message(OscMessage),
+    Bundle { timetag: u64, packets: Vec<OscPacket> },
+}
+
+impl OscPacket {
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            OscPacket::Message(message) => message.encode(),
+            OscPacket::Bundle { timetag, packets } => {
+                let mut out = BUNDLE_TAG.to_vec();
+                out.extend_from_slice(&timetag.to_be_bytes());
+                for packet in packets {
+                    let element = packet.encode();
+                    out.extend_from_slice(&(element.len() as i32).to_be_bytes());
+                    out.extend_from_slice(&element);
+                }
+                out
+            }
+        }
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, OscError> {
+        let Some(body) = bytes.strip_prefix(BUNDLE_TAG.as_slice()) else {
+            return OscMessage::decode(bytes).map(OscPacket::Message);
+        };
+        let mut reader = Reader { bytes: body, offset: 0 };
+        let timetag = u64::from_be_bytes(reader.array()?);
+        let mut packets = Vec::new();
+        while !reader.is_empty() {
+            let len = usize::try_from(i32::from_be_bytes(reader.array()?)).map_err(|_| OscError::Malformed)?;
+            packets.push(Self::decode(reader.take(len)?)?);
+        }
+        Ok(OscPacket::Bundle { timetag, packets })
+    }
+
+    /// Messages in the order they appear, with bundles flattened.
+    pub fn into_messages(self) -> Vec<OscMessage> {
+        match self {
+            OscPacket::Message(message) => vec![message],
+            OscPacket::Bundle { packets, .. } => packets.into_iter().flat_map(OscPacket::into_messages).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OscQuery {
+    Tempo,
+    Position,
+    Section,
+    Queue,
+}
+
+/// Control operations addressed under `/fftron`. Each maps onto the Tauri
+/// command of the same name.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OscCommand {
+    SetBpm(f64),
+    NudgeBpm(f64),
+    Tap,
+    Resync,
+    ActivateSection(String),
+    QueueSectionChange {
+        section: String,
+        quantize: Option<QuantizeGrid>,
+    },
+    QueueCue {
+        action: String,
+        section: Option<String>,
+        quantize: Option<QuantizeGrid>,
+    },
+    Panic,
+    Query(OscQuery),
+}
+
+impl OscCommand {
+    /// Parses a control message. Returns `Ok(None)` for button releases, which
+    /// controllers such as TouchOSC send as a trailing `0`.
+    pub fn parse(message: &OscMessage) -> Result<Option<Self>, OscError> {
+        let Some(path) = message.address.strip_prefix(OSC_NAMESPACE) else {
+            return Err(OscError::UnknownAddress(message.address.clone()));
+        };
+        let invalid = |expects| OscError::InvalidArguments(message.address.clone(), expects);
+        let number = || {
+            message
+                .args
+                .first()
+                .and_then(OscArg::as_f64)
+                .ok_or_else(|| invalid("a number"))
+        };
+        let text = |index: usize| message.args.get(index).and_then(OscArg::as_str);
+        let grid = |index: usize| {
+            text(index)
+                .map(|grid| serde_json::from_value(serde_json::Value::String(grid.to_string())))
+                .transpose()
+                .map_err(|_| invalid("a quantize grid such as 1/4n"))
+        };
+
+        let command = match path {
+            "/tempo/bpm" => OscCommand::SetBpm(number()?),
+            "/tempo/nudge" => OscCommand::NudgeBpm(number()?),
+            "/tap" if is_press(&message.args) => OscCommand::Tap,
+            "/resync" if is_press(&message.args) => OscCommand::Resync,
+            "/panic" if is_press(&message.args) => OscCommand::Panic,
+            "/tap" | "/resync" | "/panic" => return Ok(None),
+            "/section/activate" => {
+                OscCommand::ActivateSection(text(0).ok_or_else(|| invalid("a section name"))?.to_string())
+            }
+            "/section/queue" => OscCommand::QueueSectionChange {
+                section: text(0).ok_or_else(|| invalid("a section name"))?.to_string(),
+                quantize: grid(1)?,
+            },
+            "/cue/queue" => {
+                let action = text(0).ok_or_else(|| invalid("an action name"))?.to_string();
+                let section = text(1).filter(|section| !section.is_empty()).map(str::to_string);
+                let quantize = grid(2)?;
+                OscCommand::QueueCue {
+                    action,
+                    section,
+                    quantize,
+                }
+            }
+            "/query/tempo" => OscCommand::Query(OscQuery::Tempo),
+            "/query/position" => OscCommand::Query(OscQuery::Position),
+            "/query/section" => OscCommand::Query(OscQuery::Section),
+            "/query/queue" => OscCommand::Query(OscQuery::Queue),
+            _ => return Err(OscError::UnknownAddress(message.address.clone())),
+        };
+        Ok(Some(command))
+    }
+}
+
+/// Triggers fire on a bare message, an impulse or a non-zero value.
+fn is_press(args: &[OscArg]) -> bool {
+    match args.first() {
+        None | Some(OscArg::Impulse) | Some(OscArg::Nil) => true,
+        Some(arg) => arg.as_f64().is_none_or(|value| value != 0.0),
+    }
+}
+
+pub fn tempo_reply(tempo: &TempoState) -> OscMessage {
+    OscMessage::new(
+        format!("{OSC_NAMESPACE}/tempo"),
+        vec![
+            OscArg::Float(tempo.bpm as f32),
+            OscArg::String(tempo.source.as_str().to_string()),
+            OscArg::Float(tempo.confidence as f32),
+            OscArg::Long(tempo.downbeat_epoch_ms as i64),
+        ],
+    )
+}
+
+pub fn position_reply(position: &BeatPosition) -> OscMessage {
+    OscMessage::new(
+        format!("{OSC_NAMESPACE}/position"),
+        vec![
+            OscArg::Int(position.bar as i32),
+            OscArg::Int(position.beat as i32),
+            OscArg::Int(position.tick as i32),
+        ],
+    )
+}
+
+pub fn section_reply(section: Option<&str>, marker_count: usize) -> OscMessage {
+    OscMessage::new(
+        format!("{OSC_NAMESPACE}/section"),
+        vec![
+            OscArg::String(section.unwrap_or_default().to_string()),
+            OscArg::Int(marker_count as i32),
+        ],
+    )
+}
+
+/// Incoming section and the bar, beat, tick and time it starts on.
+pub fn section_change_reply(change: &SectionChange) -> OscMessage {
+    OscMessage::new(
+        format!("{OSC_NAMESPACE}/section/pending"),
+        vec![
+            OscArg::String(change.to.clone()),
+            OscArg::Int(change.at.bar as i32),
+            OscArg::Int(change.at.beat as i32),
+            OscArg::Int(change.at.tick as i32),
+            OscArg::Long(change.execute_at_ms as i64),
+        ],
+    )
+}
+
+/// Queue length, followed by the id, action and time of the next action if any.
+pub fn queue_reply(actions: &[ScheduledAction]) -> OscMessage {
+    let mut args = vec![OscArg::Int(actions.len() as i32)];
+    if let Some(next) = actions.iter().min_by_key(|action| action.execute_at_ms) {
+        args.extend([
+            OscArg::Long(next.id as i64),
+            OscArg::String(next.action.clone()),
+            OscArg::Long(next.execute_at_ms as i64),
+        ]);
+    }
+    OscMessage::new(format!("{OSC_NAMESPACE}/queue"), args)
+}
+
+pub fn queued_reply(action: &ScheduledAction) -> OscMessage {
+    OscMessage::new(
+        format!("{OSC_NAMESPACE}/cue/queued"),
+        vec![
+            OscArg::Long(action.id as i64),
+            OscArg::String(action.action.clone()),
+            OscArg::Long(action.execute_at_ms as i64),
+        ],
+    )
+}
+
+pub fn error_reply(address: &str, error: &str) -> OscMessage {
+    OscMessage::new(
+        format!("{OSC_NAMESPACE}/error"),
+        vec![OscArg::String(address.to_string()), OscArg::String(error.to_string())],
+    )
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OscConfig {
+    pub port: u16,
+    /// Send replies to this port on the sender's host instead of the port the
+    /// request came from, for controllers that listen on a fixed port.
+    pub reply_port: Option<u16>,
+}
+
+impl Default for OscConfig {
+    fn default() -> Self {
+        Self {
+            port: OSC_PORT,
+            reply_port: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OscStatus {
+    pub port: u16,
+    pub reply_port: Option<u16>,
+    pub received: u64,
+    pub errors: u64,
+}
+
+/// Sender of a datagram and the messages it carried.
+pub type OscDatagram = (SocketAddr, Result<Vec<OscMessage>, OscError>);
+
+/// UDP endpoint for OSC control messages.
+#[derive(Debug)]
+pub struct OscServer {
+    socket: UdpSocket,
+    config: OscConfig,
+    received: u64,
+    errors: u64,
+}
+
+impl OscServer {
+    pub fn bind(config: OscConfig) -> io::Result<Self> {
+        let socket = UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, config.port))?;
+        socket.set_read_timeout(Some(RECV_TIMEOUT))?;
+        let config = OscConfig {
+            port: socket.local_addr()?.port(),
+            ..config
+        };
+        Ok(Self {
+            socket,
+            config,
+            received: 0,
+            errors: 0,
+        })
+    }
+
+    pub fn status(&self) -> OscStatus {
+        OscStatus {
+            port: self.config.port,
+            reply_port: self.config.reply_port,
+            received: self.received,
+            errors: self.errors,
+        }
+    }
+
+    /// Waits briefly for one datagram. Returns `Ok(None)` when nothing arrived.
+    pub fn recv(&mut self) -> io::Result<Option<OscDatagram>> {
+        let mut buf = [0u8; MAX_DATAGRAM];
+        match self.socket.recv_from(&mut buf) {
+            Ok((len, from)) => {
+                self.received += 1;
+                Ok(Some((
+                    from,
+                    OscPacket::decode(&buf[..len]).map(OscPacket::into_messages),
+                )))
+            }
+            Err(err) if matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn reply(&mut self, to: SocketAddr, message: &OscMessage) -> io::Result<()> {
+        if message.address.ends_with("/error") {
+            self.errors += 1;
+        }
+        let mut to = to;
+        if let Some(port) = self.config.reply_port {
+            to.set_port(port);
+        }
+        self.socket.send_to(&message.encode(), to).map(|_| ())
+    }
+}
+
+/// OSC timetag for an engine clock time, which is on the Unix epoch scale.
+pub fn timetag_from_ms(epoch_ms: u64) -> u64 {
+    let seconds = epoch_ms / 1_000 + NTP_UNIX_OFFSET_SECS;
+    let fraction = ((epoch_ms % 1_000) << 32) / 1_000;
+    (seconds << 32) | fraction
+}
+
+/// A counted beat of the meter and the time it falls.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BeatTick {
+    pub bar: i64,
+    /// 1-based beat in the bar, counted in the meter's denominator.
+    pub beat: u32,
+    pub at_ms: u64,
+}
+
+/// Walks the beat grid and reports each beat once, even when the tempo
+/// changes between calls.
+#[derive(Debug, Clone, Default)]
+pub struct BeatTicker {
+    next_beat: Option<i64>,
+}
+
+impl BeatTicker {
+    /// Beats that fall at or before `until_ms` and have not been reported yet.
+    /// The first call and any jump backwards only set the position; a jump
+    /// forward replays at most a few beats.
+    pub fn due(&mut self, tempo: &TempoState, until_ms: u64) -> Vec<BeatTick> {
+        let unit = tempo.meter.beat_unit();
+        let current = (tempo.beat_at(until_ms as f64) / unit + 1e-9).floor() as i64;
+        let first = match self.next_beat {
+            Some(next) if next <= current + 1 => next.max(current + 1 - MAX_CATCH_UP_BEATS),
+            _ => current + 1,
+        };
+        self.next_beat = Some(current + 1);
+
+        let numerator = i64::from(tempo.meter.numerator.max(1));
+        (first..=current)
+            .map(|index| BeatTick {
+                bar: index.div_euclid(numerator) + 1,
+                beat: index.rem_euclid(numerator) as u32 + 1,
+                at_ms: tempo.time_at_beat(index as f64 * unit).round().max(0.0) as u64,
+            })
+            .collect()
+    }
+}
+
+/// Which events are broadcast, and where to.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OscOutputConfig {
+    /// Destinations as `host:port`.
+    pub targets: Vec<String>,
+    pub beats: bool,
+    pub bars: bool,
+    pub sections: bool,
+    pub actions: bool,
+    /// Beats and actions are sent this long before they fall, stamped with
+    /// their exact time.
+    pub look_ahead_ms: u64,
+}
+
+impl Default for OscOutputConfig {
+    fn default() -> Self {
+        Self {
+            targets: Vec::new(),
+            beats: true,
+            bars: true,
+            sections: true,
+            actions: true,
+            look_ahead_ms: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OscOutputStatus {
+    pub config: OscOutputConfig,
+    pub sent: u64,
+    pub errors: u64,
+}
+
+/// Beat bundle stamped with the beat's time: `/fftron/beat` and, on a
+/// downbeat, `/fftron/bar`.
+pub fn beat_bundle(tick: &BeatTick, tempo: &TempoState, config: &OscOutputConfig) -> Option<OscPacket> {
+    let mut packets = Vec::new();
+    if config.beats {
+        packets.push(OscPacket::Message(OscMessage::new(
+            format!("{OSC_NAMESPACE}/beat"),
+            vec![
+                OscArg::Int(tick.bar as i32),
+                OscArg::Int(tick.beat as i32),
+                OscArg::Float(tempo.bpm_at(tick.at_ms) as f32),
+            ],
+        )));
+    }
+    if config.bars && tick.beat == 1 {
+        packets.push(OscPacket::Message(OscMessage::new(
+            format!("{OSC_NAMESPACE}/bar"),
+            vec![OscArg::Int(tick.bar as i32)],
+        )));
+    }
+    (!packets.is_empty()).then(|| OscPacket::Bundle {
+        timetag: timetag_from_ms(tick.at_ms),
+        packets,
+    })
+}
+
+/// Action bundle stamped with `execute_at_ms`, so receivers can schedule it.
+pub fn action_bundle(action: &ScheduledAction) -> OscPacket {
+    let quantize = serde_json::to_value(&action.quantize)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default();
+    OscPacket::Bundle {
+        timetag: timetag_from_ms(action.execute_at_ms),
+        packets: vec![OscPacket::Message(OscMessage::new(
+            format!("{OSC_NAMESPACE}/action"),
+            vec![
+                OscArg::Long(action.id as i64),
+                OscArg::String(action.action.clone()),
+                OscArg::String(action.section.clone().unwrap_or_default()),
+                OscArg::String(quantize),
+                OscArg::Long(action.execute_at_ms as i64),
+                OscArg::String(action.marker_id.clone().unwrap_or_default()),
+                OscArg::String(if action.payload.is_null() {
+                    String::new()
+                } else {
+                    action.payload.to_string()
+                }),
+            ],
+        ))],
+    }
+}
+
+/// Withdraws an action sent ahead of its time.
+pub fn action_cancel_message(id: u64) -> OscMessage {
+    OscMessage::new(format!("{OSC_NAMESPACE}/action/cancel"), vec![OscArg::Long(id as i64)])
+}
+
+/// What receivers are told about a queued action.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ActionNotice {
+    Queued(ScheduledAction),
+    /// An action already sent was withdrawn or changed; a changed one is
+    /// queued again after this.
+    Cancelled(u64),
+}
+
+impl ActionNotice {
+    pub fn packet(&self) -> OscPacket {
+        match self {
+            ActionNotice::Queued(action) => action_bundle(action),
+            ActionNotice::Cancelled(id) => OscPacket::Message(action_cancel_message(*id)),
+        }
+    }
+}
+
+/// Sends each queued action once, ahead of its time, and withdraws it again
+/// if it leaves the queue or moves before it is dispatched.
+#[derive(Debug, Clone, Default)]
+pub struct ActionAnnouncer {
+    sent: HashMap<u64, ScheduledAction>,
+}
+
+impl ActionAnnouncer {
+    /// Notices for the pending `queue`: actions due at or before `until_ms`
+    /// that have not been sent, and cancels for sent ones that changed or are
+    /// gone. Dispatched actions must be passed to `dispatched` first.
+    pub fn due<'a>(
+        &mut self,
+        queue: impl IntoIterator<Item = &'a ScheduledAction>,
+        until_ms: u64,
+    ) -> Vec<ActionNotice> {
+        let pending = queue
+            .into_iter()
+            .map(|action| (action.id, action))
+            .collect::<HashMap<_, _>>();
+        let mut notices = Vec::new();
+        self.sent.retain(|id, sent| {
+            let unchanged = pending.get(id).is_some_and(|action| *action == sent);
+            if !unchanged {
+                notices.push(ActionNotice::Cancelled(*id));
+            }
+            unchanged
+        });
+
+        let mut queued = pending
+            .into_values()
+            .filter(|action| action.execute_at_ms <= until_ms && !self.sent.contains_key(&action.id))
+            .collect::<Vec<_>>();
+        queued.sort_by_key(|action| (action.execute_at_ms, action.id));
+        for action in queued {
+            self.sent.insert(action.id, action.clone());
+            notices.push(ActionNotice::Queued(action.clone()));
+        }
+        notices
+    }
+
+    /// Notices for actions popped from the queue: those not sent ahead, or
+    /// changed since, go out now.
+    pub fn dispatched(&mut self, actions: &[ScheduledAction]) -> Vec<ActionNotice> {
+        let mut notices = Vec::new();
+        for action in actions {
+            match self.sent.remove(&action.id) {
+                Some(sent) if sent == *action => {}
+                Some(_) => {
+                    notices.push(ActionNotice::Cancelled(action.id));
+                    notices.push(ActionNotice::Queued(action.clone()));
+                }
+                None => notices.push(ActionNotice::Queued(action.clone())),
+            }
+        }
+        notices
+    }
+}
+
+pub fn section_message(section: &str, marker_count: usize) -> OscMessage {
+    OscMessage::new(
+        format!("{OSC_NAMESPACE}/section/changed"),
+        vec![OscArg::String(section.to_string()), OscArg::Int(marker_count as i32)],
+    )
+}
+
+/// Queued section change stamped with its boundary, with the boundary time
+/// after the marker count. It is sent ahead of the boundary.
+pub fn section_change_bundle(change: &SectionChange, marker_count: usize) -> OscPacket {
+    let mut message = section_message(&change.to, marker_count);
+    message.args.push(OscArg::Long(change.execute_at_ms as i64));
+    OscPacket::Bundle {
+        timetag: timetag_from_ms(change.execute_at_ms),
+        packets: vec![OscPacket::Message(message)],
+    }
+}
+
+/// Sends engine events to a fixed set of OSC targets.
+#[derive(Debug)]
+pub struct OscBroadcaster {
+    socket: UdpSocket,
+    config: OscOutputConfig,
+    targets: Vec<SocketAddr>,
+    ticker: BeatTicker,
+    announcer: ActionAnnouncer,
+    sent: u64,
+    errors: u64,
+}
+
+impl OscBroadcaster {
+    pub fn bind(config: OscOutputConfig) -> io::Result<Self> {
+        let mut targets = Vec::new();
+        for target in &config.targets {
+            let addr = target
+                .to_socket_addrs()?
+                .find(SocketAddr::is_ipv4)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("{target} has no IPv4 address")))?;
+            targets.push(addr);
+        }
+        if targets.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "no OSC output targets"));
+        }
+        let socket = UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0))?;
+        socket.set_broadcast(true)?;
+        Ok(Self {
+            socket,
+            config,
+            targets,
+            ticker: BeatTicker::default(),
+            announcer: ActionAnnouncer::default(),
+            sent: 0,
+            errors: 0,
+        })
+    }
+
+    pub fn config(&self) -> &OscOutputConfig {
+        &self.config
+    }
+
+    pub fn status(&self) -> OscOutputStatus {
+        OscOutputStatus {
+            config: self.config.clone(),
+            sent: self.sent,
+            errors: self.errors,
+        }
+    }
+
+    /// Sends the beats that fall before `now_ms` plus the look-ahead.
+    pub fn send_beats(&mut self, tempo: &TempoState, now_ms: u64) {
+        let ticks = self.ticker.due(tempo, now_ms + self.config.look_ahead_ms);
+        if !(self.config.beats || self.config.bars) {
+            return;
+        }
+        for tick in ticks {
+            if let Some(packet) = beat_bundle(&tick, tempo, &self.config) {
+                self.send(&packet);
+            }
+        }
+    }
+
+    /// Sends queued actions that fall before `now_ms` plus the look-ahead,
+    /// and cancels sent ones that moved or were removed.
+    pub fn send_queued_actions<'a>(&mut self, queue: impl IntoIterator<Item = &'a ScheduledAction>, now_ms: u64) {
+        let notices = self.announcer.due(queue, now_ms + self.config.look_ahead_ms);
+        self.send_notices(&notices);
+    }
+
+    /// Sends dispatched actions that were not already sent ahead.
+    pub fn send_actions(&mut self, actions: &[ScheduledAction]) {
+        let notices = self.announcer.dispatched(actions);
+        self.send_notices(&notices);
+    }
+
+    pub fn send_section(&mut self, section: &str, marker_count: usize) {
+        if self.config.sections {
+            self.send(&OscPacket::Message(section_message(section, marker_count)));
+        }
+    }
+
+    pub fn send_section_change(&mut self, change: &SectionChange, marker_count: usize) {
+        if self.config.sections {
+            self.send(&section_change_bundle(change, marker_count));
+        }
+    }
+
+    fn send_notices(&mut self, notices: &[ActionNotice]) {
+        if !self.config.actions {
+            return;
+        }
+        for notice in notices {
+            self.send(&notice.packet());
+        }
+    }
+
+    fn send(&mut self, packet: &OscPacket) {
+        let bytes = packet.encode();
+        for target in &self.targets {
+            match self.socket.send_to(&bytes, target) {
+                Ok(_) => self.sent += 1,
+                Err(_) => self.errors += 1,
+            }
+        }
+    }
+}
+
+fn write_string(out: &mut Vec<u8>, value: &str) {
+    out.extend_from_slice(value.as_bytes());
+    out.push(0);
+    pad(out);
+}
+
+fn pad(out: &mut Vec<u8>) {
+    while !out.len().is_multiple_of(4) {
+        out.push(0);
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn is_empty(&self) -> bool {
+        self.offset >= self.bytes.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], OscError> {
+        let end = self.offset.checked_add(len).ok_or(OscError::Truncated)?;
+        let slice = self.bytes.get(self.offset..end).ok_or(OscError::Truncated)?;
+        self.offset = end;
+        Ok(slice)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], OscError> {
+        self.take(N)?.try_into().map_err(|_| OscError::Truncated)
+    }
+
+    fn align(&mut self) -> Result<(), OscError> {
+        let padding = (4 - self.offset % 4) % 4;
+        self.take(padding).map(|_| ())
+    }
+
+    fn string(&mut self) -> Result<String, OscError> {
+        let rest = self.bytes.get(self.offset..).ok_or(OscError::Truncated)?;
+        let len = rest.iter().position(|byte| *byte == 0).ok_or(OscError::Truncated)?;
+        let value = std::str::from_utf8(&rest[..len])
+            .map_err(|_| OscError::Malformed)?
+            .to_string();
+        self.take(len + 1)?;
+        self.align()?;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::clock::ManualClock;
+    use crate::engine::conflict::ActionOrigin;
+    use crate::engine::tempo::{GridTarget, TempoEngine};
+    use crate::timeline::types::{CueAction, Meter};
+    use std::sync::Arc;
+
+    #[test]
+    fn messages_and_bundles_round_trip() {
+        let message = OscMessage::new(
+            "/fftron/cue/queue",
+            vec![
+                OscArg::String("swap_scene".to_string()),
+                OscArg::Float(0.5),
+                OscArg::Int(-3),
+                OscArg::Blob(vec![1, 2, 3]),
+                OscArg::Long(1 << 40),
+                OscArg::Bool(true),
+                OscArg::Impulse,
+            ],
+        );
+        let bytes = message.encode();
+        assert_eq!(bytes.len() % 4, 0);
+        assert_eq!(&bytes[..20], b"/fftron/cue/queue\0\0\0");
+
+        let bundle = OscPacket::Bundle {
+            timetag: 1,
+            packets: vec![
+                OscPacket::Message(message.clone()),
+                OscPacket::Message(OscMessage::new("/fftron/tap", vec![])),
+            ],
+        };
+        let decoded = OscPacket::decode(&bundle.encode()).expect("bundle should decode");
+        assert_eq!(decoded, bundle);
+        assert_eq!(decoded.into_messages()[0], message);
+    }
+
+    #[test]
+    fn truncated_packets_are_rejected() {
+        let bytes = OscMessage::new("/fftron/tempo/bpm", vec![OscArg::Float(120.0)]).encode();
+        assert_eq!(OscPacket::decode(&bytes[..bytes.len() - 2]), Err(OscError::Truncated));
+        assert_eq!(OscPacket::decode(b"fftron\0\0"), Err(OscError::Malformed));
+    }
+
+    #[test]
+    fn control_addresses_parse_into_commands() {
+        let parse = |address: &str, args: Vec<OscArg>| OscCommand::parse(&OscMessage::new(address, args));
+
+        assert_eq!(
+            parse("/fftron/tempo/bpm", vec![OscArg::Int(128)]),
+            Ok(Some(OscCommand::SetBpm(128.0)))
+        );
+        assert_eq!(
+            parse("/fftron/tap", vec![OscArg::Float(1.0)]),
+            Ok(Some(OscCommand::Tap))
+        );
+        assert_eq!(parse("/fftron/tap", vec![OscArg::Float(0.0)]), Ok(None));
+        assert_eq!(parse("/fftron/panic", vec![]), Ok(Some(OscCommand::Panic)));
+        assert_eq!(parse("/fftron/panic", vec![OscArg::Int(0)]), Ok(None));
+        assert_eq!(
+            parse(
+                "/fftron/cue/queue",
+                vec![
+                    OscArg::String("swap_scene".to_string()),
+                    OscArg::String(String::new()),
+                    OscArg::String("1n".to_string()),
+                ]
+            ),
+            Ok(Some(OscCommand::QueueCue {
+                action: "swap_scene".to_string(),
+                section: None,
+                quantize: Some(QuantizeGrid::Whole),
+            }))
+        );
+        assert_eq!(
+            parse(
+                "/fftron/section/queue",
+                vec![OscArg::String("chorus-a".to_string()), OscArg::String("4m".to_string())]
+            ),
+            Ok(Some(OscCommand::QueueSectionChange {
+                section: "chorus-a".to_string(),
+                quantize: Some(QuantizeGrid::FourBars),
+            }))
+        );
+        assert!(matches!(
+            parse("/fftron/tempo/bpm", vec![OscArg::String("fast".to_string())]),
+            Err(OscError::InvalidArguments(..))
+        ));
+        assert!(matches!(parse("/other/tap", vec![]), Err(OscError::UnknownAddress(_))));
+    }
+
+    #[test]
+    fn timetags_count_from_1900() {
+        assert_eq!(timetag_from_ms(0), NTP_UNIX_OFFSET_SECS << 32);
+        assert_eq!(timetag_from_ms(1_500), ((NTP_UNIX_OFFSET_SECS + 1) << 32) | (1 << 31));
+    }
+
+    #[test]
+    fn ticker_reports_each_beat_once_in_the_meter() {
+        let mut tempo = TempoEngine::new(Arc::new(ManualClock::at_ms(10_000)));
+        tempo.set_bpm(120.0, None);
+        tempo.resync_downbeat(None);
+        let tempo = tempo
+            .set_meter(Meter {
+                numerator: 6,
+                denominator: 8,
+            })
+            .expect("valid meter");
+
+        let mut ticker = BeatTicker::default();
+        assert!(ticker.due(&tempo, 10_100).is_empty());
+        // Eighth notes at 120 BPM fall every 250ms.
+        let beats = (1..=14)
+            .flat_map(|step| ticker.due(&tempo, 10_100 + step * 100))
+            .map(|tick| (tick.bar, tick.beat, tick.at_ms))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            beats,
+            vec![
+                (1, 2, 10_250),
+                (1, 3, 10_500),
+                (1, 4, 10_750),
+                (1, 5, 11_000),
+                (1, 6, 11_250),
+                (2, 1, 11_500)
+            ]
+        );
+        assert!(ticker.due(&tempo, 11_600).is_empty());
+
+        // After a stall only the last few beats are replayed.
+        assert_eq!(ticker.due(&tempo, 20_000).len(), MAX_CATCH_UP_BEATS as usize);
+    }
+
+    #[test]
+    fn actions_are_bundled_at_their_execution_time() {
+        let action = ScheduledAction {
+            id: 7,
+            action: "swap_scene".to_string(),
+            cue: Some(CueAction::SwapScene),
+            marker_id: Some("m7".to_string()),
+            payload: serde_json::json!({ "scene": 2 }),
+            section: Some("chorus-a".to_string()),
+            quantize: QuantizeGrid::Whole,
+            execute_at_ms: 12_000,
+            target: GridTarget {
+                bar: 1,
+                beat: 1,
+                tick: 0,
+            },
+            priority: 0,
+            origin: ActionOrigin::Manual,
+            pattern_id: None,
+        };
+        let OscPacket::Bundle { timetag, packets } = action_bundle(&action) else {
+            panic!("actions are sent as bundles");
+        };
+        assert_eq!(timetag, timetag_from_ms(12_000));
+        assert_eq!(
+            packets,
+            vec![OscPacket::Message(OscMessage::new(
+                "/fftron/action",
+                vec![
+                    OscArg::Long(7),
+                    OscArg::String("swap_scene".to_string()),
+                    OscArg::String("chorus-a".to_string()),
+                    OscArg::String("1n".to_string()),
+                    OscArg::Long(12_000),
+                    OscArg::String("m7".to_string()),
+                    OscArg::String(r#"{"scene":2}"#.to_string()),
+                ],
+            ))]
+        );
+
+        let change = SectionChange {
+            from: Some("verse-a".to_string()),
+            to: "chorus-a".to_string(),
+            quantize: QuantizeGrid::FourBars,
+            at: GridTarget {
+                bar: 9,
+                beat: 1,
+                tick: 0,
+            },
+            execute_at_ms: 16_000,
+        };
+        let OscPacket::Bundle { timetag, packets } = section_change_bundle(&change, 3) else {
+            panic!("section changes are sent as bundles");
+        };
+        assert_eq!(timetag, timetag_from_ms(16_000));
+        assert_eq!(
+            packets,
+            vec![OscPacket::Message(OscMessage::new(
+                "/fftron/section/changed",
+                vec![
+                    OscArg::String("chorus-a".to_string()),
+                    OscArg::Int(3),
+                    OscArg::Long(16_000),
+                ],
+            ))]
+        );
+    }
+
+    #[test]
+    fn queued_actions_are_sent_ahead_once_and_withdrawn_when_they_move() {
+        let action = |id, execute_at_ms| ScheduledAction {
+            id,
+            action: "apply_accent".to_string(),
+            cue: None,
+            marker_id: None,
+            payload: serde_json::Value::Null,
+            section: None,
+            quantize: QuantizeGrid::Quarter,
+            execute_at_ms,
+            target: GridTarget {
+                bar: 1,
+                beat: 1,
+                tick: 0,
+            },
+            priority: 0,
+            origin: ActionOrigin::Manual,
+            pattern_id: None,
+        };
+        let mut announcer = ActionAnnouncer::default();
+        let (first, second) = (action(1, 12_000), action(2, 20_000));
+
+        // Only actions inside the look-ahead go out, and only once.
+        let queue = [first.clone(), second.clone()];
+        assert_eq!(announcer.due(&queue, 12_500), vec![ActionNotice::Queued(first.clone())]);
+        assert!(announcer.due(&queue, 12_600).is_empty());
+
+        // A sent action that moves is withdrawn and sent again at its new time.
+        let moved = action(1, 12_250);
+        let queue = [moved.clone(), second.clone()];
+        assert_eq!(
+            announcer.due(&queue, 12_600),
+            vec![ActionNotice::Cancelled(1), ActionNotice::Queued(moved.clone())]
+        );
+
+        // One removed before it was dispatched is withdrawn.
+        assert_eq!(announcer.due([&second], 12_600), vec![ActionNotice::Cancelled(1)]);
+
+        // Dispatched actions go out only if they were not sent ahead.
+        assert_eq!(
+            announcer.due([&second], 20_000),
+            vec![ActionNotice::Queued(second.clone())]
+        );
+        assert!(announcer.dispatched(&[second]).is_empty());
+        let late = action(3, 21_000);
+        assert_eq!(announcer.dispatched(std::slice::from_ref(&late)), vec![ActionNotice::Queued(late)]);
+        assert!(announcer.due([], 30_000).is_empty());
+
+        assert_eq!(
+            ActionNotice::Cancelled(1).packet(),
+            OscPacket::Message(OscMessage::new("/fftron/action/cancel", vec![OscArg::Long(1)]))
+        );
+    }
+
+    #[test]
+    fn server_answers_on_the_reply_port() {
+        let mut server = OscServer::bind(OscConfig {
+            port: 0,
+            reply_port: None,
+        })
+        .expect("bind osc server");
+        let client = UdpSocket::bind("127.0.0.1:0").expect("bind client");
+        client
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .expect("client timeout");
+        let server_addr = SocketAddr::from((Ipv4Addr::LOCALHOST, server.status().port));
+
+        client
+            .send_to(&OscMessage::new("/fftron/query/tempo", vec![]).encode(), server_addr)
+            .expect("send query");
+        let (from, messages) = server.recv().expect("recv").expect("datagram");
+        assert_eq!(messages, Ok(vec![OscMessage::new("/fftron/query/tempo", vec![])]));
+
+        server.reply(from, &section_reply(Some("verse-a"), 4)).expect("reply");
+        let mut buf = [0u8; 256];
+        let (len, _) = client.recv_from(&mut buf).expect("reply arrives");
+        assert_eq!(
+            OscPacket::decode(&buf[..len]),
+            Ok(OscPacket::Message(section_reply(Some("verse-a"), 4)))
+        );
+    }
+}
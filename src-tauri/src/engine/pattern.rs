@@ -0,0 +1,160 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+use super::conflict::ActionOrigin;
+use super::tempo::GridTarget;
+use crate::timeline::types::Meter;
+
+/// Most steps a pattern bar can hold, one per mask bit.
+pub const MAX_PATTERN_STEPS: u32 = 32;
+
+/// Longest cycle a pattern can repeat over.
+pub const MAX_PATTERN_CYCLE_BARS: u32 = 64;
+
+/// An action that repeats on a step grid, such as an accent on every beat of
+/// bars 2 and 4 or a 16-step sequence.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ActionPattern {
+    pub action: String,
+    #[serde(default)]
+    pub payload: Value,
+    /// Section the pattern plays in; it stops when that section hands over.
+    #[serde(default)]
+    pub section: Option<String>,
+    /// Equal steps each bar is split into; 16 gives sixteenths in 4/4.
+    pub steps: u32,
+    /// Steps that fire, bit 0 first.
+    pub mask: u32,
+    /// Bars of the cycle that play, from 1; empty plays every bar.
+    #[serde(default)]
+    pub bars: Vec<u32>,
+    /// Cycle length in bars; defaults to the highest of `bars`.
+    #[serde(default)]
+    pub cycle_bars: Option<u32>,
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default)]
+    pub origin: ActionOrigin,
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum PatternError {
+    #[error("a pattern bar holds 1 to {MAX_PATTERN_STEPS} steps, got {0}")]
+    InvalidSteps(u32),
+    #[error("mask {0:#b} sets none of the {1} steps")]
+    EmptyMask(u32, u32),
+    #[error("bar {0} is outside the {1}-bar cycle")]
+    InvalidBar(u32, u32),
+    #[error("a pattern cycle spans 1 to {MAX_PATTERN_CYCLE_BARS} bars, got {0}")]
+    InvalidCycle(u32),
+}
+
+impl ActionPattern {
+    pub fn validate(&self) -> Result<(), PatternError> {
+        if !(1..=MAX_PATTERN_STEPS).contains(&self.steps) {
+            return Err(PatternError::InvalidSteps(self.steps));
+        }
+        if self.mask & (u32::MAX >> (MAX_PATTERN_STEPS - self.steps)) == 0 {
+            return Err(PatternError::EmptyMask(self.mask, self.steps));
+        }
+        let cycle = self.cycle_bars();
+        if cycle > MAX_PATTERN_CYCLE_BARS {
+            return Err(PatternError::InvalidCycle(cycle));
+        }
+        match self.bars.iter().find(|bar| **bar == 0 || **bar > cycle) {
+            Some(bar) => Err(PatternError::InvalidBar(*bar, cycle)),
+            None => Ok(()),
+        }
+    }
+
+    pub fn cycle_bars(&self) -> u32 {
+        self.cycle_bars
+            .unwrap_or_else(|| self.bars.iter().copied().max().unwrap_or(1))
+            .max(1)
+    }
+
+    /// Whether `step`, counted from the pattern start, fires.
+    pub fn fires(&self, step: u64) -> bool {
+        let steps = u64::from(self.steps);
+        let bar = step / steps % u64::from(self.cycle_bars()) + 1;
+        self.mask >> (step % steps) & 1 == 1 && (self.bars.is_empty() || self.bars.iter().any(|b| u64::from(*b) == bar))
+    }
+
+    /// Distance of `step` from the pattern start in quarter notes.
+    pub fn step_beats(&self, step: u64, meter: &Meter) -> f64 {
+        step as f64 * meter.bar_beats() / f64::from(self.steps)
+    }
+
+    /// First step at or after `beats` quarter notes from the start.
+    pub fn step_at(&self, beats: f64, meter: &Meter) -> u64 {
+        let steps = beats / meter.bar_beats() * f64::from(self.steps);
+        (steps - 1e-6).ceil().max(0.0) as u64
+    }
+}
+
+/// A pattern the scheduler is playing. Only the next hit is queued at a time.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RunningPattern {
+    pub id: u64,
+    pub pattern: ActionPattern,
+    /// Where step 0 lands.
+    pub start: GridTarget,
+    /// Where the pattern stops, once its section is handing over.
+    pub end: Option<GridTarget>,
+    /// Step after the last one queued.
+    pub next_step: u64,
+    /// Queued hit as action id and step.
+    #[serde(skip)]
+    pub(crate) pending: Option<(u64, u64)>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(steps: u32, mask: u32, bars: Vec<u32>) -> ActionPattern {
+        ActionPattern {
+            action: "apply_accent".into(),
+            payload: Value::Null,
+            section: None,
+            steps,
+            mask,
+            bars,
+            cycle_bars: None,
+            priority: 0,
+            origin: ActionOrigin::default(),
+        }
+    }
+
+    #[test]
+    fn steps_fire_by_mask_on_the_listed_bars() {
+        // Every beat of bars 2 and 4 in a four-bar cycle.
+        let accents = pattern(4, 0b1111, vec![2, 4]);
+        assert_eq!(accents.validate(), Ok(()));
+        let fired = (0..20).filter(|step| accents.fires(*step)).collect::<Vec<_>>();
+        assert_eq!(fired, vec![4, 5, 6, 7, 12, 13, 14, 15]);
+
+        let hats = pattern(16, 0b0100_0100_0100_0100, Vec::new());
+        let fired = (0..32).filter(|step| hats.fires(*step)).collect::<Vec<_>>();
+        assert_eq!(fired, vec![2, 6, 10, 14, 18, 22, 26, 30]);
+        let meter = Meter::default();
+        assert_eq!(hats.step_beats(6, &meter), 1.5);
+        assert_eq!(hats.step_at(1.4, &meter), 6);
+        assert_eq!(hats.step_at(1.5, &meter), 6);
+
+        assert_eq!(pattern(0, 1, Vec::new()).validate(), Err(PatternError::InvalidSteps(0)));
+        assert_eq!(
+            pattern(4, 0b1_0000, Vec::new()).validate(),
+            Err(PatternError::EmptyMask(16, 4))
+        );
+        let mut short = pattern(4, 1, vec![3]);
+        short.cycle_bars = Some(2);
+        assert_eq!(short.validate(), Err(PatternError::InvalidBar(3, 2)));
+        short.cycle_bars = Some(1_000_000);
+        assert_eq!(short.validate(), Err(PatternError::InvalidCycle(1_000_000)));
+        assert_eq!(pattern(4, 1, vec![65]).validate(), Err(PatternError::InvalidCycle(65)));
+    }
+}
@@ -1,138 +1,1640 @@
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
+use serde_json::Value;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
 
-use crate::timeline::types::QuantizeGrid;
+use super::clock::{MonotonicClock, SharedClock};
+use super::conflict::{ActionOrigin, ConflictDecision, ConflictLog, ConflictOutcome, ConflictPolicy, ScheduleError};
+use super::pattern::{ActionPattern, PatternError, RunningPattern};
+use super::tempo::{GridTarget, TempoState};
+use super::transport::Transport;
+use crate::timeline::types::{CueAction, EngineCueMarker, Meter, QuantizeGrid};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct ScheduledAction {
     pub id: u64,
     pub action: String,
+    /// Typed cue when `action` names one.
+    #[serde(default)]
+    pub cue: Option<CueAction>,
+    /// Authored marker the action was queued from.
+    #[serde(default)]
+    pub marker_id: Option<String>,
+    /// Marker payload, such as which clip to fire.
+    #[serde(default)]
+    pub payload: Value,
     pub section: Option<String>,
     pub quantize: QuantizeGrid,
     pub execute_at_ms: u64,
+    /// Musical position of `execute_at_ms`; the time follows it when the tempo changes.
+    pub target: GridTarget,
+    /// Higher runs first among actions due at the same time.
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default)]
+    pub origin: ActionOrigin,
+    /// Pattern the action is a hit of.
+    #[serde(default)]
+    pub pattern_id: Option<u64>,
 }
 
+impl ScheduledAction {
+    fn key(&self) -> QueueKey {
+        (
+            self.execute_at_ms,
+            Reverse(self.origin),
+            Reverse(self.priority),
+            self.id,
+        )
+    }
+}
+
+/// Dispatch order: time, then origin, then priority, then queue order.
+type QueueKey = (u64, Reverse<ActionOrigin>, Reverse<i32>, u64);
+
+/// An action to queue. Without `quantize` the scheduler grid is used.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ActionRequest {
+    pub action: String,
+    pub cue: Option<CueAction>,
+    pub marker_id: Option<String>,
+    pub payload: Value,
+    pub section: Option<String>,
+    pub quantize: Option<QuantizeGrid>,
+    pub priority: i32,
+    pub origin: ActionOrigin,
+    pub pattern_id: Option<u64>,
+}
+
+impl ActionRequest {
+    /// A named action, typed as a cue when the name is one.
+    pub fn named(action: String) -> Self {
+        Self {
+            cue: CueAction::from_name(&action),
+            action,
+            ..Self::default()
+        }
+    }
+
+    /// An authored marker with its cue, id and payload, on the marker's grid.
+    pub fn from_marker(marker: &EngineCueMarker) -> Self {
+        Self {
+            action: marker.action.as_str().to_string(),
+            cue: Some(marker.action.clone()),
+            marker_id: Some(marker.id.clone()),
+            payload: marker.payload.clone(),
+            section: Some(marker.section.clone()),
+            quantize: Some(marker.quantize.clone()),
+            ..Self::default()
+        }
+    }
+}
+
+/// How a section's markers are placed when the section is queued.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SectionPlayback {
+    /// Each marker at its bar and beat, counted from a shared start boundary.
+    #[default]
+    Sequence,
+    /// Each marker on the next boundary of its own grid.
+    NextBoundary,
+}
+
+/// A section change waiting for its boundary.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SectionChange {
+    /// Section playing when the change was queued.
+    pub from: Option<String>,
+    pub to: String,
+    pub quantize: QuantizeGrid,
+    /// Boundary the incoming section's bar 1 beat 1 lands on.
+    pub at: GridTarget,
+    pub execute_at_ms: u64,
+}
+
+impl SectionChange {
+    pub fn new(from: Option<String>, to: String, quantize: QuantizeGrid, at: GridTarget, tempo: &TempoState) -> Self {
+        Self {
+            from,
+            to,
+            quantize,
+            execute_at_ms: tempo.time_at_beat(at.beats(&tempo.meter)).round().max(0.0) as u64,
+            at,
+        }
+    }
+}
+
+/// Action emitted by a panic once the queue is cleared.
+pub const SAFE_STATE_ACTION: &str = "safe_state";
+
+/// Grid shifts smaller than this many quarter notes are float noise from
+/// re-anchoring, not a moved bar line.
+const SHIFT_EPSILON_BEATS: f64 = 1e-6;
+
 #[derive(Debug, Clone)]
 pub struct QuantizedScheduler {
     grid: QuantizeGrid,
     look_ahead_ms: u64,
     jitter_budget_ms: u64,
     next_id: u64,
-    queue: VecDeque<ScheduledAction>,
+    queue: BTreeMap<QueueKey, ScheduledAction>,
+    /// Queue key of each action by id, for removal without a scan.
+    keys: HashMap<u64, QueueKey>,
+    conflict_policy: ConflictPolicy,
+    decisions: ConflictLog,
+    /// Bar on which phrase grids (`2m`..`16m`) start counting.
+    phrase_origin_bar: i64,
+    section_change: Option<SectionChange>,
+    patterns: BTreeMap<u64, RunningPattern>,
+    /// Grid the pending actions were last placed on.
+    tempo: Option<TempoState>,
+    clock: SharedClock,
 }
 
 impl Default for QuantizedScheduler {
     fn default() -> Self {
+        Self::new(Arc::new(MonotonicClock::default()))
+    }
+}
+
+impl QuantizedScheduler {
+    pub fn new(clock: SharedClock) -> Self {
         Self {
             grid: QuantizeGrid::Quarter,
             look_ahead_ms: 100,
             jitter_budget_ms: 5,
             next_id: 1,
-            queue: VecDeque::new(),
+            queue: BTreeMap::new(),
+            keys: HashMap::new(),
+            conflict_policy: ConflictPolicy::default(),
+            decisions: ConflictLog::default(),
+            phrase_origin_bar: 1,
+            section_change: None,
+            patterns: BTreeMap::new(),
+            tempo: None,
+            clock,
         }
     }
-}
 
-impl QuantizedScheduler {
     pub fn set_grid(&mut self, grid: QuantizeGrid) -> QuantizeGrid {
         self.grid = grid.clone();
         grid
     }
 
+    pub fn phrase_origin_bar(&self) -> i64 {
+        self.phrase_origin_bar
+    }
+
+    /// Aligns phrase grids to start on `bar`, so 4-bar phrases from bar 3 land
+    /// on bars 3, 7, 11 and so on. Pending actions are not moved.
+    pub fn set_phrase_origin(&mut self, bar: i64) -> i64 {
+        self.phrase_origin_bar = bar;
+        bar
+    }
+
+    /// Queues an authored action at default priority.
     pub fn schedule(
         &mut self,
-        now_ms: u64,
-        bpm: f64,
-        downbeat_epoch_ms: u64,
+        tempo: &TempoState,
         quantize: Option<QuantizeGrid>,
         action: String,
         section: Option<String>,
-    ) -> ScheduledAction {
-        let quantize_value = quantize.unwrap_or_else(|| self.grid.clone());
-        let execute_at_ms = quantize_next_boundary(now_ms + self.look_ahead_ms, bpm, downbeat_epoch_ms, &quantize_value);
+    ) -> Result<ScheduledAction, ScheduleError> {
+        self.submit(
+            tempo,
+            ActionRequest {
+                section,
+                quantize,
+                ..ActionRequest::named(action)
+            },
+        )
+    }
+
+    /// Queues an action on its next grid boundary, arbitrating against pending
+    /// actions it conflicts with. Fails when a higher origin holds the slot.
+    pub fn submit(&mut self, tempo: &TempoState, request: ActionRequest) -> Result<ScheduledAction, ScheduleError> {
+        self.follow_tempo(tempo);
+        let quantize = request.quantize.clone().unwrap_or_else(|| self.grid.clone());
+        let now_ms = self.clock.now_ms();
+        let beat = next_boundary_beat(now_ms + self.look_ahead_ms, tempo, &quantize, self.phrase_origin_bar);
+        self.place(tempo, request, quantize, beat)
+    }
+
+    /// Queues `markers` as the authored sequence: bar 1 beat 1 lands on the next
+    /// `start` boundary and every marker keeps its bar and beat from there.
+    /// Returns the actions that were queued.
+    pub fn schedule_section(
+        &mut self,
+        tempo: &TempoState,
+        markers: &[EngineCueMarker],
+        start: &QuantizeGrid,
+    ) -> Vec<ScheduledAction> {
+        self.follow_tempo(tempo);
+        let start = self.next_boundary(tempo, start);
+        self.schedule_section_at(tempo, markers, &start)
+    }
+
+    /// [`Self::schedule_section`] with bar 1 beat 1 on `start`.
+    pub fn schedule_section_at(
+        &mut self,
+        tempo: &TempoState,
+        markers: &[EngineCueMarker],
+        start: &GridTarget,
+    ) -> Vec<ScheduledAction> {
+        self.follow_tempo(tempo);
+        let start_beat = start.beats(&tempo.meter);
+        self.schedule_markers(tempo, markers, |beats| Some(start_beat + beats))
+    }
 
+    /// The first `grid` boundary past the look-ahead.
+    pub fn next_boundary(&self, tempo: &TempoState, grid: &QuantizeGrid) -> GridTarget {
+        let now_ms = self.clock.now_ms();
+        let beat = next_boundary_beat(now_ms + self.look_ahead_ms, tempo, grid, self.phrase_origin_bar);
+        GridTarget::at_beat(beat, &tempo.meter)
+    }
+
+    /// Queues the markers the transport plays on loop `pass`, each at its bar
+    /// and beat as a song position. Markers behind the playhead are skipped.
+    pub fn schedule_transport_pass(
+        &mut self,
+        tempo: &TempoState,
+        transport: &Transport,
+        markers: &[EngineCueMarker],
+        pass: u32,
+    ) -> Vec<ScheduledAction> {
+        self.follow_tempo(tempo);
+        let now_beat = tempo.beat_at(self.clock.now_ms() as f64);
+        self.schedule_markers(tempo, markers, |beats| {
+            transport
+                .grid_beat_at(beats, pass, &tempo.meter)
+                .filter(|beat| *beat >= now_beat - SHIFT_EPSILON_BEATS)
+        })
+    }
+
+    /// Places each marker at the grid beat `grid_beat` gives for its position in
+    /// quarter notes from bar 1 beat 1; markers it maps to `None` are skipped.
+    fn schedule_markers(
+        &mut self,
+        tempo: &TempoState,
+        markers: &[EngineCueMarker],
+        grid_beat: impl Fn(f64) -> Option<f64>,
+    ) -> Vec<ScheduledAction> {
+        let meter = &tempo.meter;
+        markers
+            .iter()
+            .filter_map(|marker| {
+                let offset = f64::from(marker.beat.saturating_sub(1)) * meter.beat_unit();
+                let beat = grid_beat(f64::from(marker.bar.saturating_sub(1)) * meter.bar_beats() + offset)?;
+                let request = ActionRequest::from_marker(marker);
+                // Keep the action on the grid its position lies on, so a resync
+                // moves the section as a whole instead of piling markers on bar lines.
+                self.place(tempo, request, position_grid(offset, meter), beat).ok()
+            })
+            .collect()
+    }
+
+    fn place(
+        &mut self,
+        tempo: &TempoState,
+        request: ActionRequest,
+        quantize: QuantizeGrid,
+        beat: f64,
+    ) -> Result<ScheduledAction, ScheduleError> {
         let scheduled = ScheduledAction {
             id: self.next_id,
-            action,
+            action: request.action,
+            cue: request.cue,
+            marker_id: request.marker_id,
+            payload: request.payload,
+            section: request.section,
+            quantize,
+            execute_at_ms: tempo.time_at_beat(beat).round() as u64,
+            target: GridTarget::at_beat(beat, &tempo.meter),
+            priority: request.priority,
+            origin: request.origin,
+            pattern_id: request.pattern_id,
+        };
+        self.next_id += 1;
+        self.admit(scheduled)
+    }
+
+    /// Moves pending actions onto a changed tempo grid. A phase-continuous
+    /// change (`set_bpm`, nudges, ramps) keeps every target and only re-times
+    /// it. When bar lines move, as on a resync or an external downbeat, each
+    /// action keeps its distance from now in beats and snaps to the nearest
+    /// slot of its grid that is still ahead. Actions already due are left alone;
+    /// the rest are arbitrated again in queue order, since snapping can bring
+    /// conflicting actions onto the same slot.
+    pub fn follow_tempo(&mut self, tempo: &TempoState) {
+        let Some(previous) = self.tempo.replace(tempo.clone()) else {
+            return;
+        };
+        if previous.same_grid(tempo) || (self.queue.is_empty() && self.section_change.is_none()) {
+            return;
+        }
+
+        let now_ms = self.clock.now_ms();
+        let mut moved = Vec::new();
+        for mut action in self.flush() {
+            if action.execute_at_ms <= now_ms {
+                self.insert(action);
+                continue;
+            }
+            // Pattern hits are placed again from the pattern on the new grid.
+            if action.pattern_id.is_some() {
+                self.rewind_pattern(&action);
+                continue;
+            }
+            let beat = self.retarget(&action.target, &action.quantize, &previous, tempo);
+            action.target = GridTarget::at_beat(beat, &tempo.meter);
+            action.execute_at_ms = tempo.time_at_beat(beat).round().max(0.0) as u64;
+            moved.push(action);
+        }
+        moved.sort_by_key(|action| action.id);
+        for action in moved {
+            // A suppressed action is dropped; the decision log records it.
+            let _ = self.admit(action);
+        }
+        if let Some(change) = self.section_change.take() {
+            let beat = self.retarget(&change.at, &change.quantize, &previous, tempo);
+            let at = GridTarget::at_beat(beat, &tempo.meter);
+            self.section_change = Some(SectionChange::new(change.from, change.to, change.quantize, at, tempo));
+        }
+        self.fill_patterns(tempo);
+    }
+
+    /// Where `target` on the `previous` grid lands on the new one, in quarter
+    /// notes: the same beat, or the nearest `quantize` slot still ahead if the
+    /// grid moved.
+    fn retarget(&self, target: &GridTarget, quantize: &QuantizeGrid, previous: &TempoState, tempo: &TempoState) -> f64 {
+        let now_ms = self.clock.now_ms() as f64;
+        let now_beat = tempo.beat_at(now_ms);
+        let shift = now_beat - previous.beat_at(now_ms);
+        let beat = target.beats(&previous.meter) + shift;
+        if shift.abs() <= SHIFT_EPSILON_BEATS && previous.meter == tempo.meter {
+            return beat;
+        }
+        let snapped = boundary_beat(beat, &tempo.meter, quantize, self.phrase_origin_bar, f64::round);
+        if snapped > now_beat {
+            return snapped;
+        }
+        boundary_beat(
+            now_beat + SHIFT_EPSILON_BEATS,
+            &tempo.meter,
+            quantize,
+            self.phrase_origin_bar,
+            f64::ceil,
+        )
+    }
+
+    /// Queues a switch from `from` to `to` on the next `quantize` boundary,
+    /// replacing any pending one.
+    pub fn queue_section_change(
+        &mut self,
+        tempo: &TempoState,
+        from: Option<String>,
+        to: String,
+        quantize: QuantizeGrid,
+    ) -> SectionChange {
+        self.follow_tempo(tempo);
+        let at = self.next_boundary(tempo, &quantize);
+        let change = SectionChange::new(from, to, quantize, at, tempo);
+        self.section_change = Some(change.clone());
+        change
+    }
+
+    pub fn section_change(&self) -> Option<SectionChange> {
+        self.section_change.clone()
+    }
+
+    pub fn cancel_section_change(&mut self) -> Option<SectionChange> {
+        self.section_change.take()
+    }
+
+    /// Takes the pending change once its boundary is within `lead_ms`.
+    pub fn take_due_section_change(&mut self, lead_ms: u64) -> Option<SectionChange> {
+        let due = self.clock.now_ms() + lead_ms;
+        match &self.section_change {
+            Some(change) if change.execute_at_ms <= due => self.section_change.take(),
+            _ => None,
+        }
+    }
+
+    /// Hands the queue over at the change boundary: the outgoing section's
+    /// actions before it play out, those from it on are withdrawn, its patterns
+    /// end there, and the incoming markers are queued from it.
+    pub fn switch_section(
+        &mut self,
+        tempo: &TempoState,
+        change: &SectionChange,
+        markers: &[EngineCueMarker],
+    ) -> Vec<ScheduledAction> {
+        if let Some(from) = &change.from {
+            self.end_section_patterns(tempo, from, &change.at);
+            let overlapping = self
+                .queue
+                .values()
+                .filter(|action| action.section.as_ref() == Some(from) && action.execute_at_ms >= change.execute_at_ms)
+                .map(|action| action.id)
+                .collect::<Vec<_>>();
+            for id in overlapping {
+                self.remove(id);
+            }
+        }
+        self.schedule_section_at(tempo, markers, &change.at)
+    }
+
+    /// Starts `pattern` with step 0 on the next `start` boundary. It repeats
+    /// until cancelled or until its section hands over.
+    pub fn start_pattern(
+        &mut self,
+        tempo: &TempoState,
+        pattern: ActionPattern,
+        start: &QuantizeGrid,
+    ) -> Result<RunningPattern, PatternError> {
+        pattern.validate()?;
+        self.follow_tempo(tempo);
+        let id = self.next_id;
+        self.next_id += 1;
+        let start = self.next_boundary(tempo, start);
+        self.patterns.insert(
+            id,
+            RunningPattern {
+                id,
+                pattern,
+                start,
+                end: None,
+                next_step: 0,
+                pending: None,
+            },
+        );
+        self.fill_patterns(tempo);
+        Ok(self.patterns[&id].clone())
+    }
+
+    pub fn patterns(&self) -> Vec<RunningPattern> {
+        self.patterns.values().cloned().collect()
+    }
+
+    /// Stops a pattern and withdraws its queued hit.
+    pub fn cancel_pattern(&mut self, id: u64) -> Option<RunningPattern> {
+        let running = self.patterns.remove(&id)?;
+        if let Some((hit, _)) = running.pending {
+            self.remove(hit);
+        }
+        Some(running)
+    }
+
+    /// Queues the next hit of each pattern whose last one has been dispatched,
+    /// and drops patterns that have reached their end.
+    pub fn refill_patterns(&mut self, tempo: &TempoState) -> Vec<ScheduledAction> {
+        self.follow_tempo(tempo);
+        self.fill_patterns(tempo)
+    }
+
+    /// Ends `section`'s patterns at `at`. Hits before it still play; a queued
+    /// hit on or after it is withdrawn.
+    pub fn end_section_patterns(&mut self, tempo: &TempoState, section: &str, at: &GridTarget) {
+        let end_ms = tempo.time_at_beat(at.beats(&tempo.meter)).round() as u64;
+        let mut withdrawn = Vec::new();
+        for running in self.patterns.values_mut() {
+            if running.pattern.section.as_deref() == Some(section) {
+                running.end = Some(*at);
+                withdrawn.extend(running.pending.map(|(hit, _)| hit));
+            }
+        }
+        for hit in withdrawn {
+            if self.keys.get(&hit).is_some_and(|key| key.0 >= end_ms) {
+                self.remove(hit);
+            }
+        }
+        self.fill_patterns(tempo);
+    }
+
+    /// Queues the next firing step of every pattern without a hit in the queue.
+    /// Steps already behind the playhead are skipped.
+    fn fill_patterns(&mut self, tempo: &TempoState) -> Vec<ScheduledAction> {
+        let meter = &tempo.meter;
+        let now_beat = tempo.beat_at(self.clock.now_ms() as f64);
+        let mut queued = Vec::new();
+        for id in self.patterns.keys().copied().collect::<Vec<_>>() {
+            let Some(mut running) = self.patterns.remove(&id) else {
+                continue;
+            };
+            if running.pending.is_some_and(|(hit, _)| self.keys.contains_key(&hit)) {
+                self.patterns.insert(id, running);
+                continue;
+            }
+            running.pending = None;
+            let pattern = running.pattern.clone();
+            let start = running.start.beats(meter);
+            let end = running.end.as_ref().map(|end| end.beats(meter));
+            running.next_step = running.next_step.max(pattern.step_at(now_beat - start, meter));
+
+            let mut ended = false;
+            // Every cycle holds a hit, so one cycle is as far as the next one can be.
+            for _ in 0..u64::from(pattern.steps) * u64::from(pattern.cycle_bars()) {
+                let step = running.next_step;
+                let beat = start + pattern.step_beats(step, meter);
+                if end.is_some_and(|end| beat >= end - SHIFT_EPSILON_BEATS) {
+                    ended = true;
+                    break;
+                }
+                running.next_step += 1;
+                if !pattern.fires(step) {
+                    continue;
+                }
+                let request = ActionRequest {
+                    payload: pattern.payload.clone(),
+                    section: pattern.section.clone(),
+                    priority: pattern.priority,
+                    origin: pattern.origin,
+                    pattern_id: Some(id),
+                    ..ActionRequest::named(pattern.action.clone())
+                };
+                let quantize = position_grid(beat.rem_euclid(meter.bar_beats()), meter);
+                if let Ok(hit) = self.place(tempo, request, quantize, beat) {
+                    running.pending = Some((hit.id, step));
+                    queued.push(hit);
+                    break;
+                }
+            }
+            if !ended {
+                self.patterns.insert(id, running);
+            }
+        }
+        queued
+    }
+
+    /// Steps a pattern back onto its withdrawn hit so the next fill queues it again.
+    fn rewind_pattern(&mut self, hit: &ScheduledAction) {
+        let Some(running) = hit.pattern_id.and_then(|id| self.patterns.get_mut(&id)) else {
+            return;
+        };
+        if let Some((_, step)) = running.pending.filter(|(pending, _)| *pending == hit.id) {
+            running.next_step = step;
+            running.pending = None;
+        }
+    }
+
+    pub fn conflict_policy(&self) -> ConflictPolicy {
+        self.conflict_policy.clone()
+    }
+
+    pub fn set_conflict_policy(&mut self, policy: ConflictPolicy) -> ConflictPolicy {
+        self.conflict_policy = policy.clone();
+        policy
+    }
+
+    /// Recent arbitration decisions, oldest first.
+    pub fn conflict_decisions(&self) -> Vec<ConflictDecision> {
+        self.decisions.decisions()
+    }
+
+    /// Queued actions in dispatch order.
+    pub fn list(&self) -> Vec<ScheduledAction> {
+        self.queued().cloned().collect()
+    }
+
+    pub fn queued(&self) -> impl Iterator<Item = &ScheduledAction> {
+        self.queue.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Execution time of the next action to dispatch.
+    pub fn next_due_ms(&self) -> Option<u64> {
+        self.queue.first_key_value().map(|(_, action)| action.execute_at_ms)
+    }
+
+    pub fn remove(&mut self, id: u64) -> Option<ScheduledAction> {
+        let key = self.keys.remove(&id)?;
+        self.queue.remove(&key)
+    }
+
+    /// Removes pending actions matching every given filter; `None` matches
+    /// anything. Running patterns that match are stopped as well, so their
+    /// hits do not come back on the next refill.
+    pub fn cancel_matching(&mut self, section: Option<&str>, action: Option<&str>) -> Vec<ScheduledAction> {
+        let matches = |candidate_section: Option<&str>, candidate_action: &str| {
+            section.is_none_or(|section| candidate_section == Some(section))
+                && action.is_none_or(|action| candidate_action == action)
+        };
+        let ids = self
+            .queue
+            .values()
+            .filter(|scheduled| matches(scheduled.section.as_deref(), &scheduled.action))
+            .map(|scheduled| scheduled.id)
+            .collect::<Vec<_>>();
+        self.patterns
+            .retain(|_, running| !matches(running.pattern.section.as_deref(), &running.pattern.action));
+        ids.into_iter().filter_map(|id| self.remove(id)).collect()
+    }
+
+    /// Withdraws `section`'s pending actions, as when the playhead jumps. Its
+    /// patterns keep running and queue their next hit on the next refill.
+    pub fn withdraw_section(&mut self, section: &str) -> Vec<ScheduledAction> {
+        let ids = self
+            .queue
+            .values()
+            .filter(|scheduled| scheduled.section.as_deref() == Some(section))
+            .map(|scheduled| scheduled.id)
+            .collect::<Vec<_>>();
+        let withdrawn = ids.into_iter().filter_map(|id| self.remove(id)).collect::<Vec<_>>();
+        for hit in &withdrawn {
+            self.rewind_pattern(hit);
+        }
+        withdrawn
+    }
+
+    /// Swaps what a pending action does, keeping its id, slot, priority and
+    /// origin. The replacement is arbitrated like a new action; when it is
+    /// suppressed the original stays queued.
+    pub fn replace(
+        &mut self,
+        id: u64,
+        action: String,
+        section: Option<String>,
+        payload: Value,
+    ) -> Result<ScheduledAction, ScheduleError> {
+        let original = self.remove(id).ok_or(ScheduleError::NotPending(id))?;
+        let request = ActionRequest::named(action);
+        let replacement = ScheduledAction {
+            action: request.action,
+            cue: request.cue,
+            marker_id: None,
+            payload,
             section,
-            quantize: quantize_value,
-            execute_at_ms,
+            pattern_id: None,
+            ..original.clone()
+        };
+        match self.admit(replacement) {
+            Ok(replaced) => Ok(replaced),
+            Err(err) => {
+                self.insert(original);
+                Err(err)
+            }
+        }
+    }
+
+    /// Empties the queue, returning what was pending in dispatch order.
+    pub fn flush(&mut self) -> Vec<ScheduledAction> {
+        self.keys.clear();
+        std::mem::take(&mut self.queue).into_values().collect()
+    }
+
+    /// Flushes the queue, stops every pattern, drops any pending section change
+    /// and queues [`SAFE_STATE_ACTION`] to run immediately, ahead of anything
+    /// scheduled afterwards for the same time.
+    pub fn panic(&mut self) -> ScheduledAction {
+        self.flush();
+        self.patterns.clear();
+        self.section_change = None;
+        let now_ms = self.clock.now_ms();
+        let scheduled = ScheduledAction {
+            id: self.next_id,
+            action: SAFE_STATE_ACTION.to_string(),
+            cue: None,
+            marker_id: None,
+            payload: Value::Null,
+            section: None,
+            quantize: self.grid.clone(),
+            execute_at_ms: now_ms,
+            target: self
+                .tempo
+                .as_ref()
+                .map(|tempo| GridTarget::at_beat(tempo.beat_at(now_ms as f64), &tempo.meter))
+                .unwrap_or(GridTarget {
+                    bar: 1,
+                    beat: 1,
+                    tick: 0,
+                }),
+            priority: i32::MAX,
+            origin: ActionOrigin::Manual,
+            pattern_id: None,
         };
         self.next_id += 1;
-        self.queue.push_back(scheduled.clone());
+        self.insert(scheduled.clone());
         scheduled
     }
 
-    pub fn list(&self) -> Vec<ScheduledAction> {
-        self.queue.iter().cloned().collect()
+    /// Pops actions due within the jitter budget of `timestamp_ms`, for callers
+    /// that poll.
+    pub fn pop_due(&mut self, timestamp_ms: Option<u64>) -> Vec<ScheduledAction> {
+        let now_ms = timestamp_ms.unwrap_or_else(|| self.clock.now_ms());
+        self.pop_until(now_ms + self.jitter_budget_ms)
     }
 
-    pub fn pop_due(&mut self, now_ms: u64) -> Vec<ScheduledAction> {
+    /// Pops actions due at or before `deadline_ms`, with no jitter budget.
+    pub fn pop_until(&mut self, deadline_ms: u64) -> Vec<ScheduledAction> {
         let mut due = Vec::new();
         while self
             .queue
-            .front()
-            .is_some_and(|action| action.execute_at_ms <= now_ms + self.jitter_budget_ms)
+            .first_key_value()
+            .is_some_and(|(_, action)| action.execute_at_ms <= deadline_ms)
         {
-            if let Some(action) = self.queue.pop_front() {
+            if let Some((_, action)) = self.queue.pop_first() {
+                self.keys.remove(&action.id);
                 due.push(action);
             }
         }
         due
     }
+
+    fn admit(&mut self, incoming: ScheduledAction) -> Result<ScheduledAction, ScheduleError> {
+        let start = (
+            incoming.execute_at_ms,
+            Reverse(ActionOrigin::Manual),
+            Reverse(i32::MAX),
+            0,
+        );
+        let conflicting = self
+            .queue
+            .range(start..)
+            .take_while(|(key, _)| key.0 == incoming.execute_at_ms)
+            .map(|(_, pending)| pending)
+            .filter(|pending| self.conflict_policy.conflicts(&incoming, pending))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let decided_at_ms = self.clock.now_ms();
+        // Pending actions are in origin order, so the first one is the strongest.
+        if let Some(pending) = conflicting.first() {
+            if ConflictOutcome::resolve(&incoming, pending) == ConflictOutcome::Suppressed {
+                let decision = ConflictDecision {
+                    decided_at_ms,
+                    outcome: ConflictOutcome::Suppressed,
+                    winner: pending.clone(),
+                    loser: incoming,
+                };
+                self.decisions.record(decision.clone());
+                return Err(ScheduleError::Suppressed(Box::new(decision)));
+            }
+        }
+        for pending in conflicting {
+            self.remove(pending.id);
+            self.decisions.record(ConflictDecision {
+                decided_at_ms,
+                outcome: ConflictOutcome::resolve(&incoming, &pending),
+                winner: incoming.clone(),
+                loser: pending,
+            });
+        }
+        self.insert(incoming.clone());
+        Ok(incoming)
+    }
+
+    fn insert(&mut self, action: ScheduledAction) {
+        let key = action.key();
+        self.keys.insert(action.id, key);
+        self.queue.insert(key, action);
+    }
 }
 
-pub fn quantize_next_boundary(now_ms: u64, bpm: f64, downbeat_epoch_ms: u64, grid: &QuantizeGrid) -> u64 {
-    let safe_bpm = bpm.clamp(20.0, 300.0);
-    let beat_ms = 60_000.0 / safe_bpm;
-    let slot_beats = match grid {
-        QuantizeGrid::Whole => 4.0,
+/// Length of one grid slot in quarter notes. `1n` spans a whole bar of the
+/// meter, and phrase grids span whole numbers of bars.
+pub fn grid_beats(grid: &QuantizeGrid, meter: &Meter) -> f64 {
+    match grid {
+        QuantizeGrid::Whole => meter.bar_beats(),
+        QuantizeGrid::DottedHalf => 3.0,
         QuantizeGrid::Half => 2.0,
+        QuantizeGrid::DottedQuarter => 1.5,
         QuantizeGrid::Quarter => 1.0,
+        QuantizeGrid::QuarterTriplet => 2.0 / 3.0,
+        QuantizeGrid::DottedEighth => 0.75,
         QuantizeGrid::Eighth => 0.5,
+        QuantizeGrid::EighthTriplet => 1.0 / 3.0,
         QuantizeGrid::Sixteenth => 0.25,
+        QuantizeGrid::ThirtySecond => 0.125,
+        QuantizeGrid::TwoBars | QuantizeGrid::FourBars | QuantizeGrid::EightBars | QuantizeGrid::SixteenBars => {
+            f64::from(grid.phrase_bars().unwrap_or(1)) * meter.bar_beats()
+        }
+    }
+}
+
+/// Coarsest straight grid a position `offset` quarter notes into the bar lies on.
+fn position_grid(offset: f64, meter: &Meter) -> QuantizeGrid {
+    [
+        QuantizeGrid::Whole,
+        QuantizeGrid::Half,
+        QuantizeGrid::Quarter,
+        QuantizeGrid::Eighth,
+        QuantizeGrid::Sixteenth,
+    ]
+    .into_iter()
+    .find(|grid| {
+        let slots = offset / grid_beats(grid, meter);
+        (slots - slots.round()).abs() < 1e-9
+    })
+    .unwrap_or(QuantizeGrid::ThirtySecond)
+}
+
+/// Next grid boundary at or after `now_ms`. Slots restart on every downbeat, so
+/// grids that do not divide the bar (quarters in 7/8, dotted quarters in 4/4)
+/// still land on bar lines. Phrase grids count from bar 1. Positions are
+/// measured in beats through the tempo map, so boundaries stay on the beat
+/// while the tempo ramps.
+pub fn quantize_next_boundary(now_ms: u64, tempo: &TempoState, grid: &QuantizeGrid) -> u64 {
+    tempo.time_at_beat(next_boundary_beat(now_ms, tempo, grid, 1)).round() as u64
+}
+
+/// [`quantize_next_boundary`] in quarter notes from the downbeat anchor, with
+/// phrases starting on `phrase_origin_bar`.
+fn next_boundary_beat(now_ms: u64, tempo: &TempoState, grid: &QuantizeGrid, phrase_origin_bar: i64) -> f64 {
+    let beat = if now_ms <= tempo.downbeat_epoch_ms {
+        0.0
+    } else {
+        tempo.beat_at(now_ms as f64)
     };
-    let slot_ms = beat_ms * slot_beats;
+    boundary_beat(beat, &tempo.meter, grid, phrase_origin_bar, f64::ceil)
+}
 
-    if now_ms <= downbeat_epoch_ms {
-        return downbeat_epoch_ms;
+/// Grid slot near `beat`, picked by `round` (`ceil` for the next one).
+fn boundary_beat(beat: f64, meter: &Meter, grid: &QuantizeGrid, phrase_origin_bar: i64, round: fn(f64) -> f64) -> f64 {
+    let bar_beats = meter.bar_beats();
+    let slot_beats = grid_beats(grid, meter);
+    if grid.phrase_bars().is_some() {
+        let origin = (phrase_origin_bar - 1) as f64 * bar_beats;
+        return origin + round((beat - origin) / slot_beats - 1e-9) * slot_beats;
     }
-
-    let elapsed = now_ms - downbeat_epoch_ms;
-    let slots = (elapsed as f64 / slot_ms).ceil();
-    downbeat_epoch_ms + (slots * slot_ms).round() as u64
+    let bar_start = (beat / bar_beats).floor() * bar_beats;
+    // Absorb float error from inverting ramps so a time on the boundary stays on it.
+    let slots = round((beat - bar_start) / slot_beats - 1e-9);
+    (bar_start + slots * slot_beats).min(bar_start + bar_beats)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::engine::clock::ManualClock;
+    use crate::engine::tempo::{GridTarget, TempoEngine};
+    use crate::engine::tempo_map::TempoRamp;
+    use crate::engine::transport::LoopRegion;
+
+    fn tempo_state(bpm: f64, downbeat_epoch_ms: u64, meter: Meter) -> TempoState {
+        let mut tempo = TempoEngine::new(Arc::new(ManualClock::at_ms(downbeat_epoch_ms)));
+        tempo.set_bpm(bpm, None);
+        tempo.resync_downbeat(None);
+        tempo.set_meter(meter).expect("valid meter")
+    }
 
     #[test]
     fn quantize_to_next_quarter_note_boundary() {
-        let now = 1_010;
-        let downbeat = 1_000;
-        let bpm = 120.0;
+        let tempo = tempo_state(120.0, 1_000, Meter::default());
 
-        let boundary = quantize_next_boundary(now, bpm, downbeat, &QuantizeGrid::Quarter);
+        let boundary = quantize_next_boundary(1_010, &tempo, &QuantizeGrid::Quarter);
         assert_eq!(boundary, 1_500);
     }
 
     #[test]
-    fn scheduler_queues_and_releases_due_actions() {
-        let mut scheduler = QuantizedScheduler::default();
-        let action = scheduler.schedule(
-            1_000,
-            120.0,
-            1_000,
-            Some(QuantizeGrid::Quarter),
-            "trigger_clip".into(),
-            Some("verse-a".into()),
+    fn whole_grid_follows_bar_length() {
+        let three_four = Meter {
+            numerator: 3,
+            denominator: 4,
+        };
+        let tempo = tempo_state(120.0, 1_000, three_four);
+
+        assert_eq!(quantize_next_boundary(1_010, &tempo, &QuantizeGrid::Whole), 2_500);
+        assert_eq!(quantize_next_boundary(2_510, &tempo, &QuantizeGrid::Whole), 4_000);
+    }
+
+    #[test]
+    fn slots_restart_on_each_downbeat_in_odd_meters() {
+        let seven_eight = Meter {
+            numerator: 7,
+            denominator: 8,
+        };
+        let tempo = tempo_state(120.0, 0, seven_eight);
+
+        // Bar is 1750ms: quarters fall at 0, 500, 1000, 1500, then the next bar at 1750.
+        assert_eq!(quantize_next_boundary(1_600, &tempo, &QuantizeGrid::Quarter), 1_750);
+        assert_eq!(quantize_next_boundary(1_800, &tempo, &QuantizeGrid::Quarter), 2_250);
+    }
+
+    #[test]
+    fn boundaries_follow_a_tempo_ramp() {
+        let mut engine = TempoEngine::default();
+        engine.set_bpm(120.0, None);
+        engine.resync_downbeat(Some(0));
+        let tempo = engine.ramp_bpm(60.0, 1, TempoRamp::Linear, Some(0)).unwrap();
+
+        // Beats get longer through the ramp, so each quarter lands later than at a fixed 120 BPM.
+        let mut now = 1;
+        let mut previous = 0;
+        for beat in 1..=4 {
+            let boundary = quantize_next_boundary(now, &tempo, &QuantizeGrid::Quarter);
+            assert_eq!(boundary, tempo.time_at_beat(f64::from(beat)).round() as u64);
+            assert!(boundary - previous > 500 || beat == 1);
+            previous = boundary;
+            now = boundary + 1;
+        }
+        assert_eq!(
+            quantize_next_boundary(previous + 1, &tempo, &QuantizeGrid::Quarter),
+            previous + 1_000
+        );
+        assert_eq!(
+            quantize_next_boundary(1, &tempo, &QuantizeGrid::Whole),
+            tempo.time_at_beat(4.0).round() as u64
         );
+    }
+
+    /// Walks consecutive quarter boundaries, applying `change` at `change_at`
+    /// between two of them, and returns the beat index landed on at each boundary.
+    fn walk_quarters(engine: &mut TempoEngine, change_at: u64, change: impl Fn(&mut TempoEngine, u64)) -> Vec<i64> {
+        let mut beats = Vec::new();
+        let mut now = 10_001;
+        let mut changed = false;
+        while beats.len() < 12 {
+            let boundary = quantize_next_boundary(now, &engine.state(), &QuantizeGrid::Quarter);
+            if !changed && boundary > change_at {
+                change(engine, change_at);
+                changed = true;
+                now = change_at;
+                continue;
+            }
+            let position = engine.state().position_at(boundary + 1);
+            beats.push((position.bar - 1) * 4 + i64::from(position.beat) - 1);
+            now = boundary + 1;
+        }
+        beats
+    }
 
+    #[test]
+    fn nudge_mid_bar_neither_skips_nor_repeats_a_beat() {
+        for delta in [-8.0, 8.0, 40.0] {
+            let mut engine = TempoEngine::default();
+            engine.set_bpm(120.0, None);
+            engine.resync_downbeat(Some(10_000));
+
+            // Nudge three quarters of the way through beat 2 of the second bar.
+            let beats = walk_quarters(&mut engine, 12_875, |engine, at| {
+                engine.nudge_bpm(delta, Some(at));
+            });
+            let expected = (1..=12).collect::<Vec<_>>();
+            assert_eq!(beats, expected, "nudge {delta}");
+        }
+    }
+
+    #[test]
+    fn set_bpm_mid_bar_keeps_the_next_downbeat_on_beat_one() {
+        let mut engine = TempoEngine::default();
+        engine.set_bpm(120.0, None);
+        engine.resync_downbeat(Some(10_000));
+        engine.set_bpm(90.0, Some(10_600));
+
+        let tempo = engine.state();
+        let downbeat = quantize_next_boundary(10_601, &tempo, &QuantizeGrid::Whole);
+        // 1.2 beats had played at 120 BPM; the remaining 2.8 take 2.8 * 666.7ms.
+        assert!((downbeat as f64 - (10_600.0 + 2.8 * 60_000.0 / 90.0)).abs() <= 1.0);
+        assert_eq!(tempo.position_at(downbeat + 1).beat, 1);
+    }
+
+    #[test]
+    fn scheduler_queues_and_releases_due_actions() {
+        let clock = ManualClock::at_ms(1_000);
+        let mut scheduler = QuantizedScheduler::new(Arc::new(clock.clone()));
+        let tempo = tempo_state(120.0, 1_000, Meter::default());
+        let action = scheduler
+            .schedule(
+                &tempo,
+                Some(QuantizeGrid::Quarter),
+                "trigger_clip".into(),
+                Some("verse-a".into()),
+            )
+            .unwrap();
+
+        // Look-ahead pushes the 1000ms downbeat to the next quarter.
+        assert_eq!(action.execute_at_ms, 1_500);
         assert_eq!(scheduler.list().len(), 1);
-        let due = scheduler.pop_due(action.execute_at_ms);
+
+        clock.set_ms(1_490);
+        assert!(scheduler.pop_due(None).is_empty());
+        clock.set_ms(1_495);
+        let due = scheduler.pop_due(None);
         assert_eq!(due.len(), 1);
         assert_eq!(scheduler.list().len(), 0);
     }
+
+    #[test]
+    fn finer_grids_queued_later_are_not_held_behind_coarser_ones() {
+        let clock = ManualClock::at_ms(1_010);
+        let mut scheduler = QuantizedScheduler::new(Arc::new(clock.clone()));
+        let tempo = tempo_state(120.0, 1_000, Meter::default());
+
+        let bar = scheduler
+            .schedule(&tempo, Some(QuantizeGrid::Whole), "swap_scene".into(), None)
+            .unwrap();
+        let sixteenth = scheduler
+            .schedule(&tempo, Some(QuantizeGrid::Sixteenth), "apply_accent".into(), None)
+            .unwrap();
+        let quarter = scheduler
+            .schedule(&tempo, Some(QuantizeGrid::Quarter), "trigger_clip".into(), None)
+            .unwrap();
+        assert_eq!(
+            (bar.execute_at_ms, sixteenth.execute_at_ms, quarter.execute_at_ms),
+            (3_000, 1_125, 1_500)
+        );
+        assert_eq!(scheduler.next_due_ms(), Some(1_125));
+
+        let mut dispatched = Vec::new();
+        for now in (1_010..=3_000).step_by(5) {
+            dispatched.extend(scheduler.pop_until(now).into_iter().map(|action| (action.id, now)));
+        }
+        assert_eq!(
+            dispatched,
+            vec![(sixteenth.id, 1_125), (quarter.id, 1_500), (bar.id, 3_000)]
+        );
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn simultaneous_actions_run_by_priority_then_queue_order() {
+        // Every grid lands on the 3000ms downbeat from here.
+        let clock = ManualClock::at_ms(2_850);
+        let mut scheduler = QuantizedScheduler::new(Arc::new(clock.clone()));
+        let tempo = tempo_state(120.0, 1_000, Meter::default());
+
+        let clip = scheduler
+            .schedule(&tempo, Some(QuantizeGrid::Quarter), "trigger_clip".into(), None)
+            .unwrap();
+        let accent = scheduler
+            .schedule(&tempo, Some(QuantizeGrid::Sixteenth), "apply_accent".into(), None)
+            .unwrap();
+        let scene = scheduler
+            .submit(
+                &tempo,
+                ActionRequest {
+                    action: "swap_scene".into(),
+                    quantize: Some(QuantizeGrid::Whole),
+                    priority: 10,
+                    ..ActionRequest::default()
+                },
+            )
+            .unwrap();
+        let order = scheduler.list().iter().map(|action| action.id).collect::<Vec<_>>();
+        assert_eq!(order, vec![scene.id, clip.id, accent.id]);
+
+        assert_eq!(scheduler.remove(clip.id).map(|action| action.id), Some(clip.id));
+        assert!(scheduler.remove(clip.id).is_none());
+        let due = scheduler
+            .pop_until(3_000)
+            .iter()
+            .map(|action| action.id)
+            .collect::<Vec<_>>();
+        assert_eq!(due, vec![scene.id, accent.id]);
+    }
+
+    #[test]
+    fn pending_actions_can_be_cancelled_replaced_and_flushed() {
+        let clock = ManualClock::at_ms(1_010);
+        let mut scheduler = QuantizedScheduler::new(Arc::new(clock.clone()));
+        let tempo = tempo_state(120.0, 1_000, Meter::default());
+        let verse = Some("verse".to_string());
+        let chorus = Some("chorus".to_string());
+
+        let clip = scheduler
+            .schedule(&tempo, None, "trigger_clip".into(), verse.clone())
+            .unwrap();
+        let accent = scheduler
+            .schedule(&tempo, None, "apply_accent".into(), verse.clone())
+            .unwrap();
+        let scene = scheduler
+            .schedule(&tempo, Some(QuantizeGrid::Whole), "swap_scene".into(), chorus.clone())
+            .unwrap();
+        let chorus_clip = scheduler
+            .schedule(&tempo, None, "trigger_clip".into(), chorus.clone())
+            .unwrap();
+
+        let cancelled = scheduler.cancel_matching(Some("verse"), Some("trigger_clip"));
+        assert_eq!(cancelled, vec![clip]);
+        let replaced = scheduler
+            .replace(accent.id, "swap_scene".into(), chorus, Value::Null)
+            .expect("pending");
+        assert_eq!(
+            (replaced.execute_at_ms, replaced.action.as_str(), replaced.cue),
+            (accent.execute_at_ms, "swap_scene", Some(CueAction::SwapScene))
+        );
+        assert!(scheduler.cancel_matching(Some("verse"), None).is_empty());
+
+        let ids = |actions: Vec<ScheduledAction>| actions.iter().map(|action| action.id).collect::<Vec<_>>();
+        assert_eq!(
+            ids(scheduler.cancel_matching(None, Some("swap_scene"))),
+            vec![accent.id, scene.id]
+        );
+        assert_eq!(ids(scheduler.flush()), vec![chorus_clip.id]);
+        assert!(scheduler.is_empty());
+        assert!(matches!(
+            scheduler.replace(accent.id, "apply_accent".into(), None, Value::Null),
+            Err(ScheduleError::NotPending(_))
+        ));
+    }
+
+    #[test]
+    fn a_replacement_is_arbitrated_like_a_new_action() {
+        let clock = ManualClock::at_ms(1_010);
+        let mut scheduler = QuantizedScheduler::new(Arc::new(clock.clone()));
+        let tempo = tempo_state(120.0, 1_000, Meter::default());
+        let scene = scheduler
+            .submit(
+                &tempo,
+                ActionRequest {
+                    quantize: Some(QuantizeGrid::Whole),
+                    origin: ActionOrigin::Manual,
+                    ..ActionRequest::named("swap_scene".into())
+                },
+            )
+            .unwrap();
+        let clip = scheduler
+            .submit(
+                &tempo,
+                ActionRequest {
+                    payload: serde_json::json!({ "clip": "intro" }),
+                    quantize: Some(QuantizeGrid::Whole),
+                    origin: ActionOrigin::Auto,
+                    ..ActionRequest::named("trigger_clip".into())
+                },
+            )
+            .unwrap();
+        assert_eq!(scene.execute_at_ms, clip.execute_at_ms);
+
+        // An auto swap may not take the slot of a manual one, so the clip stays as it was.
+        let result = scheduler.replace(clip.id, "swap_scene".into(), None, Value::Null);
+        assert!(matches!(result, Err(ScheduleError::Suppressed(_))));
+        assert_eq!(scheduler.list(), vec![scene.clone(), clip.clone()]);
+        assert_eq!(scheduler.conflict_decisions().len(), 1);
+
+        let replaced = scheduler
+            .replace(clip.id, "apply_accent".into(), None, serde_json::json!({ "gain": 2 }))
+            .unwrap();
+        assert_eq!(
+            (replaced.cue, replaced.payload, replaced.origin),
+            (Some(CueAction::ApplyAccent), serde_json::json!({ "gain": 2 }), ActionOrigin::Auto)
+        );
+    }
+
+    #[test]
+    fn panic_clears_the_queue_and_emits_a_safe_state_action_now() {
+        let clock = ManualClock::at_ms(1_010);
+        let mut scheduler = QuantizedScheduler::new(Arc::new(clock.clone()));
+        let tempo = tempo_state(120.0, 1_000, Meter::default());
+        scheduler.schedule(&tempo, None, "trigger_clip".into(), None).unwrap();
+        scheduler
+            .schedule(&tempo, Some(QuantizeGrid::Whole), "swap_scene".into(), None)
+            .unwrap();
+
+        let safe = scheduler.panic();
+        assert_eq!((safe.action.as_str(), safe.execute_at_ms), (SAFE_STATE_ACTION, 1_010));
+        assert_eq!(scheduler.pop_until(1_010), vec![safe]);
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn conflicting_actions_on_a_boundary_are_arbitrated_by_origin() {
+        let clock = ManualClock::at_ms(1_010);
+        let mut scheduler = QuantizedScheduler::new(Arc::new(clock.clone()));
+        let tempo = tempo_state(120.0, 1_000, Meter::default());
+        let mut submit = |action: &str, quantize, origin| {
+            scheduler.submit(
+                &tempo,
+                ActionRequest {
+                    action: action.to_string(),
+                    quantize: Some(quantize),
+                    origin,
+                    ..ActionRequest::default()
+                },
+            )
+        };
+
+        let authored = submit("swap_scene", QuantizeGrid::Whole, ActionOrigin::Authored).unwrap();
+        let auto = submit("swap_scene", QuantizeGrid::Whole, ActionOrigin::Auto).unwrap();
+        let refused = submit("swap_scene", QuantizeGrid::Whole, ActionOrigin::Authored);
+        assert!(matches!(refused, Err(ScheduleError::Suppressed(ref decision)) if decision.winner == auto));
+        let manual = submit("swap_scene", QuantizeGrid::Whole, ActionOrigin::Manual).unwrap();
+        let retake = submit("swap_scene", QuantizeGrid::Whole, ActionOrigin::Manual).unwrap();
+        // Non-exclusive actions and other boundaries are left alone.
+        let accent = submit("apply_accent", QuantizeGrid::Whole, ActionOrigin::Authored).unwrap();
+        let early = submit("swap_scene", QuantizeGrid::Quarter, ActionOrigin::Authored).unwrap();
+
+        let order = scheduler.list().iter().map(|action| action.id).collect::<Vec<_>>();
+        assert_eq!(order, vec![early.id, retake.id, accent.id]);
+        let decisions = scheduler
+            .conflict_decisions()
+            .into_iter()
+            .map(|decision| (decision.outcome, decision.winner.id, decision.loser.id))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            decisions,
+            vec![
+                (ConflictOutcome::Preempted, auto.id, authored.id),
+                (ConflictOutcome::Suppressed, auto.id, auto.id + 1),
+                (ConflictOutcome::Preempted, manual.id, auto.id),
+                (ConflictOutcome::Superseded, retake.id, manual.id),
+            ]
+        );
+    }
+
+    #[test]
+    fn pending_actions_keep_their_beat_when_the_tempo_changes() {
+        let clock = ManualClock::at_ms(1_010);
+        let mut scheduler = QuantizedScheduler::new(Arc::new(clock.clone()));
+        let tempo = tempo_state(120.0, 1_000, Meter::default());
+        let bar = scheduler
+            .schedule(&tempo, Some(QuantizeGrid::Whole), "swap_scene".into(), None)
+            .unwrap();
+        let sixteenth = scheduler
+            .schedule(&tempo, Some(QuantizeGrid::Sixteenth), "apply_accent".into(), None)
+            .unwrap();
+        assert_eq!(
+            bar.target,
+            GridTarget {
+                bar: 2,
+                beat: 1,
+                tick: 0
+            }
+        );
+        assert_eq!(
+            sixteenth.target,
+            GridTarget {
+                bar: 1,
+                beat: 1,
+                tick: 240
+            }
+        );
+
+        // Halving the tempo on beat 3 stretches the last two beats of the bar.
+        clock.set_ms(2_000);
+        scheduler.follow_tempo(&tempo.with_bpm_at(60.0, 2_000));
+        let pending = scheduler.list();
+        assert_eq!(pending[0], sixteenth, "actions already due are left alone");
+        assert_eq!((pending[1].execute_at_ms, pending[1].target), (4_000, bar.target));
+    }
+
+    #[test]
+    fn actions_snapped_onto_one_slot_are_arbitrated_again() {
+        let clock = ManualClock::at_ms(1_010);
+        let mut scheduler = QuantizedScheduler::new(Arc::new(clock.clone()));
+        let tempo = tempo_state(120.0, 1_000, Meter::default());
+        let authored = scheduler
+            .schedule(&tempo, Some(QuantizeGrid::Quarter), "swap_scene".into(), None)
+            .unwrap();
+        let manual = scheduler
+            .submit(
+                &tempo,
+                ActionRequest {
+                    quantize: Some(QuantizeGrid::Half),
+                    origin: ActionOrigin::Manual,
+                    ..ActionRequest::named("swap_scene".into())
+                },
+            )
+            .unwrap();
+        assert_eq!((authored.execute_at_ms, manual.execute_at_ms), (1_500, 2_000));
+
+        // Bar lines move 0.6 beats: the quarter rounds up to beat 2 and the half
+        // rounds down onto the same slot.
+        scheduler.follow_tempo(&TempoState {
+            downbeat_epoch_ms: 700,
+            ..tempo.clone()
+        });
+        let queued = scheduler.list();
+        assert_eq!(
+            (queued.len(), queued[0].id, queued[0].execute_at_ms),
+            (1, manual.id, 1_700)
+        );
+        let decisions = scheduler.conflict_decisions();
+        assert_eq!(
+            (decisions.len(), decisions[0].outcome, decisions[0].loser.id),
+            (1, ConflictOutcome::Preempted, authored.id)
+        );
+    }
+
+    #[test]
+    fn a_resync_snaps_pending_actions_onto_the_moved_grid() {
+        let clock = ManualClock::at_ms(1_010);
+        let mut scheduler = QuantizedScheduler::new(Arc::new(clock.clone()));
+        let tempo = tempo_state(120.0, 1_000, Meter::default());
+        let bar = scheduler
+            .schedule(&tempo, Some(QuantizeGrid::Whole), "swap_scene".into(), None)
+            .unwrap();
+        let quarter = scheduler
+            .schedule(&tempo, Some(QuantizeGrid::Quarter), "trigger_clip".into(), None)
+            .unwrap();
+        assert_eq!((quarter.execute_at_ms, bar.execute_at_ms), (1_500, 3_000));
+
+        // The downbeat lands 400ms late: the quarter's slot has just passed, so
+        // it moves to the next one; the bar keeps its distance and snaps.
+        clock.set_ms(1_400);
+        scheduler.follow_tempo(&TempoState {
+            downbeat_epoch_ms: 1_400,
+            ..tempo.clone()
+        });
+        let retimed = scheduler
+            .list()
+            .into_iter()
+            .map(|action| (action.id, action.execute_at_ms, action.target))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            retimed,
+            vec![
+                (
+                    quarter.id,
+                    1_900,
+                    GridTarget {
+                        bar: 1,
+                        beat: 2,
+                        tick: 0
+                    }
+                ),
+                (
+                    bar.id,
+                    3_400,
+                    GridTarget {
+                        bar: 2,
+                        beat: 1,
+                        tick: 0
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn triplet_dotted_and_thirty_second_grids() {
+        let tempo = tempo_state(120.0, 1_000, Meter::default());
+        let boundary = |now_ms, grid| quantize_next_boundary(now_ms, &tempo, &grid);
+
+        assert_eq!(boundary(1_010, QuantizeGrid::EighthTriplet), 1_167);
+        assert_eq!(boundary(1_010, QuantizeGrid::QuarterTriplet), 1_333);
+        assert_eq!(boundary(1_010, QuantizeGrid::ThirtySecond), 1_063);
+        assert_eq!(boundary(1_010, QuantizeGrid::DottedEighth), 1_375);
+        assert_eq!(boundary(1_010, QuantizeGrid::DottedQuarter), 1_750);
+        // Dotted slots restart on the downbeat instead of crossing the bar line.
+        assert_eq!(boundary(2_800, QuantizeGrid::DottedQuarter), 3_000);
+        assert_eq!(boundary(1_010, QuantizeGrid::DottedHalf), 2_500);
+
+        let names = [
+            QuantizeGrid::EighthTriplet,
+            QuantizeGrid::DottedQuarter,
+            QuantizeGrid::SixteenBars,
+        ]
+        .map(|grid| serde_json::to_value(grid).unwrap());
+        assert_eq!(names, ["1/8t", "1/4n.", "16m"].map(serde_json::Value::from));
+    }
+
+    #[test]
+    fn phrase_grids_align_to_the_phrase_origin() {
+        let clock = ManualClock::at_ms(5_100);
+        let mut scheduler = QuantizedScheduler::new(Arc::new(clock.clone()));
+        // Two-second bars from 1000ms: bar 3 starts at 5000ms.
+        let tempo = tempo_state(120.0, 1_000, Meter::default());
+        let two_bars = scheduler
+            .schedule(&tempo, Some(QuantizeGrid::TwoBars), "swap_scene".into(), None)
+            .unwrap();
+        assert_eq!((two_bars.execute_at_ms, two_bars.target.bar), (9_000, 5));
+
+        scheduler.set_phrase_origin(3);
+        clock.set_ms(1_010);
+        let phrase = scheduler
+            .schedule(&tempo, Some(QuantizeGrid::FourBars), "swap_scene".into(), None)
+            .unwrap();
+        assert_eq!((phrase.execute_at_ms, phrase.target.bar), (5_000, 3));
+        clock.set_ms(5_100);
+        let phrase = scheduler
+            .schedule(&tempo, Some(QuantizeGrid::FourBars), "apply_accent".into(), None)
+            .unwrap();
+        assert_eq!((phrase.execute_at_ms, phrase.target.bar), (13_000, 7));
+    }
+
+    #[test]
+    fn authored_markers_carry_their_cue_id_and_payload() {
+        let clock = ManualClock::at_ms(1_010);
+        let mut scheduler = QuantizedScheduler::new(Arc::new(clock.clone()));
+        let tempo = tempo_state(120.0, 1_000, Meter::default());
+        let marker = EngineCueMarker {
+            id: "m1".to_string(),
+            section: "verse-a".to_string(),
+            bar: 1,
+            beat: 1,
+            quantize: QuantizeGrid::Quarter,
+            action: CueAction::TriggerClip,
+            payload: serde_json::json!({ "clip": "intro-loop" }),
+        };
+
+        let scheduled = scheduler.submit(&tempo, ActionRequest::from_marker(&marker)).unwrap();
+        assert_eq!(scheduled.action, "trigger_clip");
+        assert_eq!(scheduled.cue, Some(CueAction::TriggerClip));
+        assert_eq!(scheduled.marker_id.as_deref(), Some("m1"));
+        assert_eq!(scheduled.payload["clip"], "intro-loop");
+
+        let preview = scheduler.schedule(&tempo, None, "swap_scene".into(), None).unwrap();
+        assert_eq!((preview.cue, preview.marker_id), (Some(CueAction::SwapScene), None));
+    }
+
+    fn marker(id: &str, bar: u32, beat: u32) -> EngineCueMarker {
+        EngineCueMarker {
+            id: id.to_string(),
+            section: "verse-a".to_string(),
+            bar,
+            beat,
+            quantize: QuantizeGrid::Whole,
+            action: CueAction::ApplyAccent,
+            payload: Value::Null,
+        }
+    }
+
+    #[test]
+    fn a_section_plays_its_markers_at_their_authored_positions() {
+        let clock = ManualClock::at_ms(1_010);
+        let mut scheduler = QuantizedScheduler::new(Arc::new(clock.clone()));
+        let tempo = tempo_state(120.0, 1_000, Meter::default());
+        let markers = [marker("m1", 1, 1), marker("m2", 1, 3), marker("m3", 3, 2)];
+
+        // The section starts on the next bar line at 3000ms; bars are 2000ms long.
+        let queued = scheduler.schedule_section(&tempo, &markers, &QuantizeGrid::Whole);
+        let placed = queued
+            .iter()
+            .map(|action| (action.execute_at_ms, action.quantize.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            placed,
+            vec![
+                (3_000, QuantizeGrid::Whole),
+                (4_000, QuantizeGrid::Half),
+                (7_500, QuantizeGrid::Quarter),
+            ]
+        );
+        assert_eq!(
+            queued[2].target,
+            GridTarget {
+                bar: 4,
+                beat: 2,
+                tick: 0
+            }
+        );
+
+        // A resync moves the whole section rather than snapping beats onto bar lines.
+        let mut engine = TempoEngine::new(Arc::new(clock.clone()));
+        engine.set_bpm(120.0, None);
+        engine.resync_downbeat(Some(1_100));
+        scheduler.follow_tempo(&engine.state());
+        let shifted = scheduler
+            .list()
+            .iter()
+            .map(|action| action.execute_at_ms)
+            .collect::<Vec<_>>();
+        assert_eq!(shifted, vec![3_100, 4_100, 7_600]);
+    }
+
+    #[test]
+    fn the_transport_starts_a_section_from_the_playhead() {
+        let clock = ManualClock::at_ms(0);
+        let mut scheduler = QuantizedScheduler::new(Arc::new(clock.clone()));
+        let mut transport = Transport::new(Arc::new(clock.clone()));
+        let tempo = tempo_state(120.0, 0, Meter::default());
+        let markers = [marker("m1", 1, 1), marker("m2", 2, 2), marker("m3", 3, 1)];
+        transport.locate(&tempo, 2).unwrap();
+        transport
+            .set_loop(
+                &tempo,
+                Some(LoopRegion {
+                    start_bar: 2,
+                    end_bar: 4,
+                }),
+            )
+            .unwrap();
+        transport.play(&tempo);
+
+        // Bar 1 is behind the playhead; the loop pass repeats bars 2 and 3 after 4000ms.
+        let first = scheduler.schedule_transport_pass(&tempo, &transport, &markers, 0);
+        let second = scheduler.schedule_transport_pass(&tempo, &transport, &markers, 1);
+        let times = |actions: &[ScheduledAction]| actions.iter().map(|action| action.execute_at_ms).collect::<Vec<_>>();
+        assert_eq!(times(&first), vec![500, 2_000]);
+        assert_eq!(times(&second), vec![4_500, 6_000]);
+    }
+
+    #[test]
+    fn a_queued_section_change_hands_over_on_its_boundary() {
+        let clock = ManualClock::at_ms(1_010);
+        let mut scheduler = QuantizedScheduler::new(Arc::new(clock.clone()));
+        let tempo = tempo_state(120.0, 0, Meter::default());
+        let verse = [
+            marker("v1", 1, 1),
+            marker("v2", 1, 3),
+            marker("v3", 2, 1),
+            marker("v4", 3, 1),
+        ];
+        let mut chorus = [marker("c1", 1, 1), marker("c2", 1, 2)];
+        for marker in &mut chorus {
+            marker.section = "chorus-a".to_string();
+        }
+        scheduler.schedule_section(&tempo, &verse, &QuantizeGrid::Whole);
+
+        clock.set_ms(2_500);
+        let change = scheduler.queue_section_change(
+            &tempo,
+            Some("verse-a".to_string()),
+            "chorus-a".to_string(),
+            QuantizeGrid::Whole,
+        );
+        assert_eq!((change.at.bar, change.execute_at_ms), (3, 4_000));
+        assert_eq!(scheduler.cancel_section_change(), Some(change.clone()));
+        assert_eq!(scheduler.section_change(), None);
+
+        scheduler.queue_section_change(&tempo, change.from.clone(), change.to.clone(), QuantizeGrid::Whole);
+        assert!(scheduler.take_due_section_change(200).is_none());
+        clock.set_ms(3_850);
+        let due = scheduler.take_due_section_change(200).expect("within the lead");
+        scheduler.switch_section(&tempo, &due, &chorus);
+
+        // Verse markers before the boundary play out; the rest give way to the chorus.
+        let queued = scheduler
+            .list()
+            .iter()
+            .map(|action| (action.marker_id.clone().unwrap(), action.execute_at_ms))
+            .collect::<Vec<_>>();
+        let expected = [("v1", 2_000), ("v2", 3_000), ("c1", 4_000), ("c2", 4_500)];
+        assert_eq!(queued, expected.map(|(id, at)| (id.to_string(), at)));
+    }
+
+    #[test]
+    fn a_pattern_repeats_its_hits_until_its_section_ends() {
+        let clock = ManualClock::at_ms(1_010);
+        let mut scheduler = QuantizedScheduler::new(Arc::new(clock.clone()));
+        let tempo = tempo_state(120.0, 0, Meter::default());
+        let accents = ActionPattern {
+            action: "apply_accent".to_string(),
+            payload: Value::Null,
+            section: Some("verse-a".to_string()),
+            steps: 4,
+            mask: 0b1111,
+            bars: vec![2, 4],
+            cycle_bars: None,
+            priority: 0,
+            origin: ActionOrigin::Auto,
+        };
+        let hits = |scheduler: &QuantizedScheduler| {
+            scheduler
+                .list()
+                .iter()
+                .map(|action| action.execute_at_ms)
+                .collect::<Vec<_>>()
+        };
+
+        // Step 0 is bar 2, so the cycle's second bar is bar 3 of the song.
+        let running = scheduler
+            .start_pattern(&tempo, accents.clone(), &QuantizeGrid::Whole)
+            .expect("valid pattern");
+        assert_eq!((running.start.bar, hits(&scheduler)), (2, vec![4_000]));
+
+        clock.set_ms(4_000);
+        let due = scheduler.pop_due(None);
+        assert_eq!(due[0].pattern_id, Some(running.id));
+        scheduler.refill_patterns(&tempo);
+        assert_eq!(hits(&scheduler), vec![4_500]);
+
+        // A transport jump withdraws the hit only until the next refill.
+        scheduler.withdraw_section("verse-a");
+        scheduler.refill_patterns(&tempo);
+        assert_eq!(hits(&scheduler), vec![4_500]);
+
+        let end = GridTarget {
+            bar: 3,
+            beat: 3,
+            tick: 0,
+        };
+        scheduler.end_section_patterns(&tempo, "verse-a", &end);
+        clock.set_ms(4_500);
+        scheduler.pop_due(None);
+        scheduler.refill_patterns(&tempo);
+        assert!(scheduler.is_empty() && scheduler.patterns().is_empty());
+
+        scheduler
+            .start_pattern(&tempo, accents.clone(), &QuantizeGrid::Whole)
+            .expect("valid pattern");
+        assert_eq!(hits(&scheduler), vec![8_000]);
+
+        // Cancelling by section stops the pattern, so no further hits come back.
+        scheduler.cancel_matching(Some("verse-a"), None);
+        scheduler.refill_patterns(&tempo);
+        assert!(scheduler.is_empty() && scheduler.patterns().is_empty());
+
+        let running = scheduler
+            .start_pattern(&tempo, accents, &QuantizeGrid::Whole)
+            .expect("valid pattern");
+        assert_eq!(hits(&scheduler), vec![8_000]);
+        assert_eq!(
+            scheduler.cancel_pattern(running.id).map(|pattern| pattern.id),
+            Some(running.id)
+        );
+        assert!(scheduler.is_empty());
+    }
 }
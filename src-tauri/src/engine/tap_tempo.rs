@@ -0,0 +1,269 @@
+use serde::{Deserialize, Serialize};
+
+const MAX_TAPS: usize = 8;
+const MIN_INTERVAL_MS: u64 = 80;
+const MAX_INTERVAL_MS: u64 = 3_000;
+/// Longest gap, in beats, that is read as missed taps rather than an outlier.
+const MAX_MISSED_BEATS: u64 = 3;
+/// Taps in the count that ends on a downbeat.
+pub const DOWNBEAT_COUNT: usize = 4;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TapTempoConfig {
+    /// Tapped tempos are folded by octaves into this range when they fit.
+    pub preferred_min_bpm: f64,
+    pub preferred_max_bpm: f64,
+    /// A gap longer than this starts a new tap session.
+    pub session_timeout_ms: u64,
+    /// Largest relative deviation from the reference interval that is kept.
+    pub outlier_tolerance: f64,
+    /// Only move the downbeat when the operator completes a count of four;
+    /// other taps change tempo without moving the bar.
+    pub downbeat_on_four_count: bool,
+}
+
+impl Default for TapTempoConfig {
+    fn default() -> Self {
+        Self {
+            preferred_min_bpm: 80.0,
+            preferred_max_bpm: 160.0,
+            session_timeout_ms: 2_500,
+            outlier_tolerance: 0.2,
+            downbeat_on_four_count: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TapEstimate {
+    pub bpm: f64,
+    pub confidence: f64,
+    /// Whether this tap completed a count of [`DOWNBEAT_COUNT`].
+    pub completes_count: bool,
+}
+
+/// Tap session: keeps the recent taps and turns them into a tempo.
+#[derive(Debug, Clone, Default)]
+pub struct TapTempo {
+    config: TapTempoConfig,
+    taps: Vec<u64>,
+    session_taps: usize,
+}
+
+impl TapTempo {
+    pub fn config(&self) -> &TapTempoConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: TapTempoConfig) {
+        self.config = config;
+    }
+
+    /// Taps in the current session, including ones outside the window.
+    pub fn session_taps(&self) -> usize {
+        self.session_taps
+    }
+
+    pub fn clear(&mut self) {
+        self.taps.clear();
+        self.session_taps = 0;
+    }
+
+    pub fn tap(&mut self, timestamp_ms: u64) -> Option<TapEstimate> {
+        let stale = self
+            .taps
+            .last()
+            .is_some_and(|last| timestamp_ms < *last || timestamp_ms - last > self.config.session_timeout_ms);
+        if stale {
+            self.clear();
+        }
+
+        self.taps.push(timestamp_ms);
+        self.session_taps += 1;
+        if self.taps.len() > MAX_TAPS {
+            let keep_from = self.taps.len() - MAX_TAPS;
+            self.taps.drain(0..keep_from);
+        }
+
+        let intervals = self
+            .taps
+            .windows(2)
+            .map(|pair| pair[1] - pair[0])
+            .filter(|interval| (MIN_INTERVAL_MS..=MAX_INTERVAL_MS).contains(interval))
+            .collect::<Vec<_>>();
+        let (beats, accepted) = self.beat_intervals(&intervals);
+        if beats.is_empty() {
+            return None;
+        }
+
+        let beat_ms = median_ms(&beats);
+        let kept = accepted as f64 / intervals.len() as f64;
+        Some(TapEstimate {
+            bpm: self.fold_octave(60_000.0 / beat_ms as f64),
+            confidence: (tap_confidence(&beats) * kept).clamp(0.2, 0.99),
+            completes_count: self.session_taps.is_multiple_of(DOWNBEAT_COUNT),
+        })
+    }
+
+    /// Splits intervals that span whole missed beats and drops the ones that
+    /// do not fit the reference beat. The reference is the lower median, so a
+    /// single doubled interval cannot pull it up.
+    /// Returns the beat intervals and how many tap intervals were accepted.
+    fn beat_intervals(&self, intervals: &[u64]) -> (Vec<u64>, usize) {
+        let mut sorted = intervals.to_vec();
+        sorted.sort_unstable();
+        let Some(&reference) = sorted.get(sorted.len().saturating_sub(1) / 2) else {
+            return (Vec::new(), 0);
+        };
+
+        let mut beats = Vec::new();
+        let mut accepted = 0;
+        for &interval in intervals {
+            let ratio = interval as f64 / reference as f64;
+            let spanned = ratio.round().max(1.0);
+            if spanned as u64 > MAX_MISSED_BEATS || (ratio / spanned - 1.0).abs() > self.config.outlier_tolerance {
+                continue;
+            }
+            let beat = (interval as f64 / spanned).round() as u64;
+            beats.extend(std::iter::repeat_n(beat, spanned as usize));
+            accepted += 1;
+        }
+        (beats, accepted)
+    }
+
+    /// Doubles or halves a tapped tempo into the preferred range, so tapping
+    /// half-time or on every eighth still lands on the intended beat.
+    fn fold_octave(&self, bpm: f64) -> f64 {
+        let (min, max) = (self.config.preferred_min_bpm, self.config.preferred_max_bpm);
+        if min <= 0.0 || max < min * 2.0 {
+            return bpm;
+        }
+        let mut folded = bpm;
+        while folded < min {
+            folded *= 2.0;
+        }
+        while folded > max {
+            folded /= 2.0;
+        }
+        folded
+    }
+}
+
+fn median_ms(values: &[u64]) -> u64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_unstable();
+
+    let mid = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        (sorted[mid - 1] + sorted[mid]) / 2
+    } else {
+        sorted[mid]
+    }
+}
+
+fn tap_confidence(intervals: &[u64]) -> f64 {
+    if intervals.len() == 1 {
+        return 0.35;
+    }
+
+    let mean = intervals.iter().map(|v| *v as f64).sum::<f64>() / intervals.len() as f64;
+    if mean <= f64::EPSILON {
+        return 0.2;
+    }
+
+    let variance = intervals
+        .iter()
+        .map(|value| {
+            let diff = *value as f64 - mean;
+            diff * diff
+        })
+        .sum::<f64>()
+        / intervals.len() as f64;
+
+    let std_dev = variance.sqrt();
+    let coefficient_of_variation = std_dev / mean;
+
+    let consistency = 1.0 - coefficient_of_variation;
+    let sample_weight = (intervals.len() as f64 / 6.0).clamp(0.4, 1.0);
+
+    (consistency * sample_weight).clamp(0.2, 0.99)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cmp::Ordering;
+
+    fn tap_all(tap_tempo: &mut TapTempo, taps: &[u64]) -> Option<TapEstimate> {
+        taps.iter().fold(None, |_, tap| tap_tempo.tap(*tap))
+    }
+
+    #[test]
+    fn missed_tap_is_read_as_two_beats() {
+        let mut tap_tempo = TapTempo::default();
+        let estimate = tap_all(&mut tap_tempo, &[0, 500, 1_000, 2_000, 2_500, 3_000]).unwrap();
+        assert_eq!(estimate.bpm, 120.0);
+        assert!(estimate.confidence > 0.9);
+    }
+
+    #[test]
+    fn stray_tap_is_rejected() {
+        let steady = tap_all(&mut TapTempo::default(), &[0, 500, 1_000, 1_500, 2_000, 2_500, 3_000]).unwrap();
+        let estimate = tap_all(&mut TapTempo::default(), &[0, 500, 1_000, 1_500, 1_800, 2_300, 2_800]).unwrap();
+        assert_eq!(estimate.bpm, 120.0);
+        assert!(estimate.confidence < steady.confidence);
+    }
+
+    #[test]
+    fn half_time_taps_fold_into_preferred_range() {
+        let mut tap_tempo = TapTempo::default();
+        // Tapping every other beat of a 140 BPM track.
+        let estimate = tap_all(&mut tap_tempo, &[0, 857, 1_714, 2_571]).unwrap();
+        assert!((estimate.bpm - 140.0).abs() < 0.1);
+
+        tap_tempo.set_config(TapTempoConfig {
+            preferred_min_bpm: 60.0,
+            preferred_max_bpm: 120.0,
+            ..TapTempoConfig::default()
+        });
+        tap_tempo.clear();
+        let estimate = tap_all(&mut tap_tempo, &[0, 857, 1_714, 2_571]).unwrap();
+        assert!((estimate.bpm - 70.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn stale_taps_start_a_new_session() {
+        let mut tap_tempo = TapTempo::default();
+        tap_all(&mut tap_tempo, &[0, 400, 800]);
+        assert!(tap_tempo.tap(10_000).is_none());
+        assert_eq!(tap_tempo.session_taps(), 1);
+
+        let estimate = tap_tempo.tap(10_500).unwrap();
+        assert_eq!(estimate.bpm, 120.0);
+    }
+
+    #[test]
+    fn every_fourth_tap_completes_a_count() {
+        let mut tap_tempo = TapTempo::default();
+        let completes = (0..8)
+            .filter_map(|index| tap_tempo.tap(index * 500))
+            .map(|estimate| estimate.completes_count)
+            .collect::<Vec<_>>();
+        assert_eq!(completes, vec![false, false, true, false, false, false, true]);
+    }
+
+    #[test]
+    fn median_supports_even_and_odd_inputs() {
+        assert_eq!(median_ms(&[400, 500, 600]), 500);
+        assert_eq!(median_ms(&[400, 500, 600, 700]), 550);
+    }
+
+    #[test]
+    fn confidence_improves_for_consistent_taps() {
+        let consistent = tap_confidence(&[500, 500, 500, 500]);
+        let jittery = tap_confidence(&[400, 540, 450, 610]);
+
+        assert_eq!(consistent.partial_cmp(&jittery), Some(Ordering::Greater));
+    }
+}
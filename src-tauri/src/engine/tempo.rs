@@ -1,5 +1,15 @@
 use serde::{Deserialize, Serialize};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::Arc;
+use thiserror::Error;
+
+use super::arbiter::{ArbiterPolicy, ArbiterStatus, TempoArbiter};
+use super::clock::{MonotonicClock, SharedClock};
+use super::tap_tempo::{TapTempo, TapTempoConfig};
+use super::tempo_log::{DriftReference, TempoLog, TempoLogEntry, TempoLogExport, TempoLogFormat, TempoLogKind};
+use super::tempo_map::{TempoEvent, TempoMap, TempoRamp, MAX_BPM, MIN_BPM};
+use crate::timeline::types::Meter;
+
+pub const TICKS_PER_BEAT: u32 = 960;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
@@ -11,6 +21,19 @@ pub enum TempoSource {
     Auto,
 }
 
+impl TempoSource {
+    /// Serialized name, for logs and status text.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TempoSource::Manual => "manual",
+            TempoSource::Tap => "tap",
+            TempoSource::Link => "link",
+            TempoSource::MidiClock => "midi_clock",
+            TempoSource::Auto => "auto",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct TempoState {
@@ -19,143 +42,545 @@ pub struct TempoState {
     pub downbeat_epoch_ms: u64,
     pub source: TempoSource,
     pub tap_count: usize,
+    pub meter: Meter,
+    /// Tempo automation; `bpm` is the tempo before its first event.
+    #[serde(default)]
+    pub tempo_map: TempoMap,
+}
+
+/// Musical position on the tempo grid. `bar` and `beat` are 1-based, with
+/// beats counted in the meter's denominator; `phase` is the progress through
+/// the bar in `0..1`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BeatPosition {
+    pub bar: i64,
+    pub beat: u32,
+    pub tick: u32,
+    pub phase: f64,
+}
+
+/// A point on the grid: 1-based `bar` and `beat` (in meter units) and a `tick`
+/// within the beat. Unlike a time, it stays put when the tempo changes.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct GridTarget {
+    pub bar: i64,
+    pub beat: u32,
+    pub tick: u32,
+}
+
+impl GridTarget {
+    /// The target `beats` quarter notes after the downbeat anchor, rounded to
+    /// the nearest tick.
+    pub fn at_beat(beats: f64, meter: &Meter) -> Self {
+        let ticks_per_bar = i64::from(meter.numerator) * i64::from(TICKS_PER_BEAT);
+        let ticks = (beats / meter.beat_unit() * f64::from(TICKS_PER_BEAT)).round() as i64;
+        let in_bar = ticks.rem_euclid(ticks_per_bar) as u32;
+        Self {
+            bar: ticks.div_euclid(ticks_per_bar) + 1,
+            beat: in_bar / TICKS_PER_BEAT + 1,
+            tick: in_bar % TICKS_PER_BEAT,
+        }
+    }
+
+    /// Quarter notes from the downbeat anchor.
+    pub fn beats(&self, meter: &Meter) -> f64 {
+        let ticks = (self.bar - 1) * i64::from(meter.numerator) * i64::from(TICKS_PER_BEAT)
+            + i64::from(self.beat.saturating_sub(1)) * i64::from(TICKS_PER_BEAT)
+            + i64::from(self.tick);
+        ticks as f64 / f64::from(TICKS_PER_BEAT) * meter.beat_unit()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum TempoError {
+    #[error("meter {0}/{1} is not supported")]
+    InvalidMeter(u32, u32),
+    #[error("tempo event {0} must follow the previous event with a bpm in 20..=300")]
+    InvalidTempoEvent(usize),
+}
+
+impl TempoState {
+    /// Quarter-note length in milliseconds.
+    pub fn beat_ms(&self) -> f64 {
+        60_000.0 / clamp_bpm(self.bpm)
+    }
+
+    pub fn bar_ms(&self) -> f64 {
+        self.beat_ms() * self.meter.bar_beats()
+    }
+
+    /// Quarter notes since the downbeat anchor, following the tempo map.
+    pub fn beat_at(&self, timestamp_ms: f64) -> f64 {
+        self.tempo_map
+            .beat_at_ms(self.bpm, timestamp_ms - self.downbeat_epoch_ms as f64)
+    }
+
+    /// Wall-clock time of a beat counted from the downbeat anchor.
+    pub fn time_at_beat(&self, beat: f64) -> f64 {
+        self.downbeat_epoch_ms as f64 + self.tempo_map.ms_at_beat(self.bpm, beat)
+    }
+
+    pub fn bpm_at(&self, timestamp_ms: u64) -> f64 {
+        self.tempo_map.bpm_at_beat(self.bpm, self.beat_at(timestamp_ms as f64))
+    }
+
+    /// The grid switched to a constant `bpm` at `timestamp_ms`, with the beat
+    /// position at that instant unchanged.
+    pub fn with_bpm_at(&self, bpm: f64, timestamp_ms: u64) -> TempoState {
+        let bpm = clamp_bpm(bpm);
+        let beat = self.beat_at(timestamp_ms as f64);
+        TempoState {
+            bpm,
+            downbeat_epoch_ms: anchor_ms(timestamp_ms as f64, beat, bpm),
+            tempo_map: TempoMap::default(),
+            ..self.clone()
+        }
+    }
+
+    /// Constant-tempo view of the grid around `timestamp_ms`, keeping the bar
+    /// phase there. For outputs such as MIDI clock that cannot follow a tempo map.
+    pub fn constant_at(&self, timestamp_ms: u64) -> TempoState {
+        if self.tempo_map.is_empty() {
+            return self.clone();
+        }
+        let bar_beats = self.meter.bar_beats();
+        let beat = self.beat_at(timestamp_ms as f64);
+        let bpm = self.tempo_map.bpm_at_beat(self.bpm, beat);
+        let into_bar_ms = beat.rem_euclid(bar_beats) * 60_000.0 / bpm;
+
+        TempoState {
+            bpm,
+            downbeat_epoch_ms: (timestamp_ms as f64 - into_bar_ms).round().max(0.0) as u64,
+            tempo_map: TempoMap::default(),
+            ..self.clone()
+        }
+    }
+
+    /// Whether both states place every beat at the same time.
+    pub fn same_grid(&self, other: &TempoState) -> bool {
+        self.bpm == other.bpm
+            && self.downbeat_epoch_ms == other.downbeat_epoch_ms
+            && self.meter == other.meter
+            && self.tempo_map == other.tempo_map
+    }
+
+    pub fn position_at(&self, timestamp_ms: u64) -> BeatPosition {
+        let bars = self.beat_at(timestamp_ms as f64) / self.meter.bar_beats();
+        let bar = bars.floor();
+        let phase = bars - bar;
+
+        let beat_position = phase * f64::from(self.meter.numerator);
+        let beat = (beat_position.floor() as u32).min(self.meter.numerator - 1);
+        let tick = (((beat_position - f64::from(beat)) * f64::from(TICKS_PER_BEAT)).floor() as u32).min(TICKS_PER_BEAT - 1);
+
+        BeatPosition {
+            bar: bar as i64 + 1,
+            beat: beat + 1,
+            tick,
+            phase,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct TempoEngine {
     state: TempoState,
-    taps: Vec<u64>,
+    taps: TapTempo,
+    arbiter: TempoArbiter,
+    log: TempoLog,
+    clock: SharedClock,
 }
 
 impl Default for TempoEngine {
     fn default() -> Self {
+        Self::new(Arc::new(MonotonicClock::default()))
+    }
+}
+
+impl TempoEngine {
+    pub fn new(clock: SharedClock) -> Self {
         Self {
             state: TempoState {
                 bpm: 120.0,
                 confidence: 1.0,
-                downbeat_epoch_ms: now_ms(),
+                downbeat_epoch_ms: clock.now_ms(),
                 source: TempoSource::Manual,
                 tap_count: 0,
+                meter: Meter::default(),
+                tempo_map: TempoMap::default(),
             },
-            taps: Vec::new(),
+            taps: TapTempo::default(),
+            arbiter: TempoArbiter::default(),
+            log: TempoLog::default(),
+            clock,
         }
     }
-}
 
-impl TempoEngine {
+    pub fn clock(&self) -> &SharedClock {
+        &self.clock
+    }
+
     pub fn state(&self) -> TempoState {
         self.state.clone()
     }
 
-    pub fn set_bpm(&mut self, bpm: f64) -> TempoState {
-        self.state.bpm = clamp_bpm(bpm);
+    pub fn position_at(&self, timestamp_ms: Option<u64>) -> BeatPosition {
+        self.state.position_at(timestamp_ms.unwrap_or_else(|| self.clock.now_ms()))
+    }
+
+    /// Changes the meter from the current bar line: that bar keeps its number
+    /// and start time, and bars from it on take the new length.
+    pub fn set_meter(&mut self, meter: Meter) -> Result<TempoState, TempoError> {
+        if !meter.is_valid() {
+            return Err(TempoError::InvalidMeter(meter.numerator, meter.denominator));
+        }
+        let now = self.clock.now_ms();
+        let bars = (self.state.beat_at(now as f64) / self.state.meter.bar_beats()).floor();
+        if bars > 0.0 {
+            let bar_start = bars * self.state.meter.bar_beats();
+            let bar_start_ms = self.state.time_at_beat(bar_start);
+            let (bpm, pending) = self.state.tempo_map.split_at(self.state.bpm, bar_start);
+            let beat = bars * meter.bar_beats();
+            let events = pending
+                .events()
+                .iter()
+                .map(|event| TempoEvent {
+                    beat: event.beat + beat,
+                    ..*event
+                })
+                .collect();
+            self.state.tempo_map = TempoMap::new(events)?;
+            self.state.bpm = bpm;
+            self.state.downbeat_epoch_ms = anchor_ms(bar_start_ms, beat, bpm);
+        }
+        self.state.meter = meter;
+        Ok(self.state.clone())
+    }
+
+    /// Replaces the tempo automation. Event beats count from the downbeat anchor.
+    pub fn set_tempo_map(&mut self, events: Vec<TempoEvent>) -> Result<TempoState, TempoError> {
+        let tempo_map = TempoMap::new(events)?;
+        let now = self.clock.now_ms();
+        let accepted = self.offer_manual(self.state.bpm, now);
+        if accepted {
+            self.state.tempo_map = tempo_map;
+            self.state.source = TempoSource::Manual;
+        }
+        self.log(TempoLogKind::TempoMap, TempoSource::Manual, 1.0, self.state.bpm, now, accepted);
+        Ok(self.state.clone())
+    }
+
+    /// Ramps to `target_bpm` over `bars` bars, starting on the next downbeat.
+    /// Automation after that downbeat is replaced. Fails, leaving the tempo
+    /// alone, when the ramp does not make a valid tempo map.
+    pub fn ramp_bpm(
+        &mut self,
+        target_bpm: f64,
+        bars: u32,
+        ramp: TempoRamp,
+        timestamp_ms: Option<u64>,
+    ) -> Result<TempoState, TempoError> {
+        let now = timestamp_ms.unwrap_or_else(|| self.clock.now_ms());
+        self.advance(Some(now));
+
+        let bar_beats = self.state.meter.bar_beats();
+        let start_beat = (self.state.beat_at(now as f64) / bar_beats).ceil().max(0.0) * bar_beats;
+        let start_bpm = self.state.tempo_map.bpm_at_beat(self.state.bpm, start_beat);
+        let (mut events, replaced): (Vec<TempoEvent>, Vec<TempoEvent>) = self
+            .state
+            .tempo_map
+            .events()
+            .iter()
+            .partition(|event| event.beat < start_beat);
+
+        // A ramp that is cut short still arrives at the tempo it had reached.
+        events.push(TempoEvent {
+            beat: start_beat,
+            bpm: start_bpm,
+            ramp: replaced.first().map_or(TempoRamp::Step, |event| event.ramp),
+        });
+        events.push(TempoEvent {
+            beat: start_beat + f64::from(bars.max(1)) * bar_beats,
+            bpm: clamp_bpm(target_bpm),
+            ramp,
+        });
+        let tempo_map = TempoMap::new(events)?;
+        if !self.offer_manual(target_bpm, now) {
+            self.log(TempoLogKind::Ramp, TempoSource::Manual, 1.0, target_bpm, now, false);
+            return Ok(self.state.clone());
+        }
+        self.state.tempo_map = tempo_map;
+        self.state.source = TempoSource::Manual;
+        self.log(TempoLogKind::Ramp, TempoSource::Manual, 1.0, target_bpm, now, true);
+        Ok(self.state.clone())
+    }
+
+    /// Folds tempo events that have already played into the base tempo so
+    /// `bpm` reports the latest reached tempo. Beat positions are unchanged.
+    pub fn advance(&mut self, timestamp_ms: Option<u64>) -> TempoState {
+        let now = timestamp_ms.unwrap_or_else(|| self.clock.now_ms());
+        let beat = self.state.beat_at(now as f64);
+        let mut map = self.state.tempo_map.clone();
+        if let Some(bpm) = map.settle(beat) {
+            let held_beat = map.events()[0].beat;
+            let held_at = self.state.time_at_beat(held_beat);
+            self.state.downbeat_epoch_ms = anchor_ms(held_at, held_beat, bpm);
+            self.state.bpm = bpm;
+            self.state.tempo_map = map;
+        }
+        self.state.clone()
+    }
+
+    /// Changes tempo at `timestamp_ms` without moving the current bar/beat
+    /// position; only beats after that point get longer or shorter.
+    pub fn set_bpm(&mut self, bpm: f64, timestamp_ms: Option<u64>) -> TempoState {
+        let now = timestamp_ms.unwrap_or_else(|| self.clock.now_ms());
+        if !self.offer_manual(bpm, now) {
+            self.log(TempoLogKind::SetBpm, TempoSource::Manual, 1.0, bpm, now, false);
+            return self.state.clone();
+        }
+        self.reanchor(bpm, now);
         self.state.confidence = 1.0;
         self.state.source = TempoSource::Manual;
         self.state.tap_count = 0;
         self.taps.clear();
+        self.log(TempoLogKind::SetBpm, TempoSource::Manual, 1.0, bpm, now, true);
         self.state.clone()
     }
 
-    pub fn nudge_bpm(&mut self, delta: f64) -> TempoState {
-        self.state.bpm = clamp_bpm(self.state.bpm + delta);
+    pub fn nudge_bpm(&mut self, delta: f64, timestamp_ms: Option<u64>) -> TempoState {
+        let now = timestamp_ms.unwrap_or_else(|| self.clock.now_ms());
+        let bpm = self.state.bpm_at(now) + delta;
+        if !self.offer_manual(bpm, now) {
+            self.log(TempoLogKind::Nudge, TempoSource::Manual, 1.0, bpm, now, false);
+            return self.state.clone();
+        }
+        self.reanchor(bpm, now);
         self.state.source = TempoSource::Manual;
+        self.log(TempoLogKind::Nudge, TempoSource::Manual, 1.0, bpm, now, true);
         self.state.clone()
     }
 
-    pub fn resync_downbeat(&mut self, timestamp_ms: Option<u64>) -> TempoState {
-        self.state.downbeat_epoch_ms = timestamp_ms.unwrap_or_else(now_ms);
+    /// Follows a tempo and downbeat published by an external clock such as a Link session.
+    pub fn sync_external(
+        &mut self,
+        source: TempoSource,
+        bpm: f64,
+        downbeat_epoch_ms: u64,
+        confidence: f64,
+    ) -> TempoState {
+        let now = self.clock.now_ms();
+        if !self.arbiter.offer(source.clone(), clamp_bpm(bpm), Some(downbeat_epoch_ms), confidence, now) {
+            self.log(TempoLogKind::External, source, confidence, bpm, now, false);
+            self.follow_leader(now);
+            return self.state.clone();
+        }
+        let blended = self.arbiter.blend(&source, clamp_bpm(bpm), confidence, now);
+
+        self.state.tempo_map = TempoMap::default();
+        self.state.bpm = clamp_bpm(blended);
+        self.state.confidence = confidence.clamp(0.0, 1.0);
+        self.state.downbeat_epoch_ms = downbeat_epoch_ms;
+        self.state.source = source.clone();
+        self.state.tap_count = 0;
+        self.taps.clear();
+        self.log(TempoLogKind::External, source, confidence, bpm, now, true);
         self.state.clone()
     }
 
-    pub fn tap_bpm(&mut self, timestamp_ms: Option<u64>) -> TempoState {
-        let tap = timestamp_ms.unwrap_or_else(now_ms);
-        self.taps.push(tap);
+    /// Follows an external beat that carries no bar information, such as
+    /// audio beat tracking. The downbeat is picked so bars stay where they were.
+    pub fn sync_external_beat(
+        &mut self,
+        source: TempoSource,
+        bpm: f64,
+        beat_epoch_ms: u64,
+        confidence: f64,
+    ) -> TempoState {
+        let beats_since_downbeat = self.state.beat_at(beat_epoch_ms as f64);
+        let beat_in_bar = beats_since_downbeat.round().rem_euclid(self.state.meter.bar_beats());
 
-        if self.taps.len() > 8 {
-            let keep_from = self.taps.len() - 8;
-            self.taps.drain(0..keep_from);
+        let beat_ms = 60_000.0 / clamp_bpm(bpm);
+        let downbeat_epoch_ms = (beat_epoch_ms as f64 - beat_in_bar * beat_ms).round().max(0.0) as u64;
+        self.sync_external(source, bpm, downbeat_epoch_ms, confidence)
+    }
+
+    /// Moves the downbeat anchor to `timestamp_ms`. Pending automation keeps its
+    /// timing relative to the new anchor.
+    pub fn resync_downbeat(&mut self, timestamp_ms: Option<u64>) -> TempoState {
+        let timestamp_ms = timestamp_ms.unwrap_or_else(|| self.clock.now_ms());
+        if !self.state.tempo_map.is_empty() {
+            let beat = self.state.beat_at(timestamp_ms as f64);
+            let (bpm, tempo_map) = self.state.tempo_map.split_at(self.state.bpm, beat);
+            self.state.bpm = bpm;
+            self.state.tempo_map = tempo_map;
         }
+        self.state.downbeat_epoch_ms = timestamp_ms;
+        let (source, confidence, bpm) = (self.state.source.clone(), self.state.confidence, self.state.bpm);
+        self.log(TempoLogKind::Resync, source, confidence, bpm, timestamp_ms, true);
+        self.state.clone()
+    }
 
-        let intervals = self
-            .taps
-            .windows(2)
-            .map(|pair| pair[1].saturating_sub(pair[0]))
-            .filter(|interval| (80..=3000).contains(interval))
-            .collect::<Vec<_>>();
+    pub fn tap_config(&self) -> TapTempoConfig {
+        self.taps.config().clone()
+    }
+
+    pub fn set_tap_config(&mut self, config: TapTempoConfig) -> TapTempoConfig {
+        self.taps.set_config(config);
+        self.tap_config()
+    }
 
-        if intervals.is_empty() {
-            self.state.tap_count = self.taps.len();
+    pub fn tap_bpm(&mut self, timestamp_ms: Option<u64>) -> TempoState {
+        let tap = timestamp_ms.unwrap_or_else(|| self.clock.now_ms());
+        let estimate = self.taps.tap(tap);
+        self.state.tap_count = self.taps.session_taps();
+        let Some(estimate) = estimate else {
+            self.log(TempoLogKind::Tap, TempoSource::Tap, 0.0, self.state.bpm, tap, false);
+            return self.state.clone();
+        };
+        if !self.arbiter.offer(TempoSource::Tap, estimate.bpm, None, estimate.confidence, tap) {
+            self.log(TempoLogKind::Tap, TempoSource::Tap, estimate.confidence, estimate.bpm, tap, false);
             return self.state.clone();
         }
 
-        let median_interval = median_ms(&intervals);
-        let tapped_bpm = 60_000.0 / median_interval as f64;
-        let confidence = tap_confidence(&intervals);
-
-        self.state.bpm = clamp_bpm(tapped_bpm);
-        self.state.confidence = confidence;
+        if self.taps.config().downbeat_on_four_count && !estimate.completes_count {
+            self.reanchor(estimate.bpm, tap);
+        } else {
+            self.state.tempo_map = TempoMap::default();
+            self.state.bpm = clamp_bpm(estimate.bpm);
+            self.state.downbeat_epoch_ms = tap;
+        }
+        self.state.confidence = estimate.confidence;
         self.state.source = TempoSource::Tap;
-        self.state.tap_count = self.taps.len();
-        self.state.downbeat_epoch_ms = tap;
+        self.log(TempoLogKind::Tap, TempoSource::Tap, estimate.confidence, estimate.bpm, tap, true);
         self.state.clone()
     }
-}
 
-fn now_ms() -> u64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map(|duration| duration.as_millis() as u64)
-        .unwrap_or(0)
-}
+    pub fn arbiter_policy(&self) -> ArbiterPolicy {
+        self.arbiter.policy().clone()
+    }
 
-fn clamp_bpm(bpm: f64) -> f64 {
-    bpm.clamp(20.0, 300.0)
-}
+    pub fn set_arbiter_policy(&mut self, policy: ArbiterPolicy) -> ArbiterStatus {
+        let now = self.clock.now_ms();
+        self.arbiter.set_policy(policy);
+        self.follow_leader(now);
+        self.arbiter.status(now)
+    }
 
-fn median_ms(values: &[u64]) -> u64 {
-    let mut sorted = values.to_vec();
-    sorted.sort_unstable();
+    pub fn arbiter_status(&mut self) -> ArbiterStatus {
+        let now = self.clock.now_ms();
+        self.follow_leader(now);
+        self.arbiter.status(now)
+    }
 
-    let mid = sorted.len() / 2;
-    if sorted.len() % 2 == 0 {
-        (sorted[mid - 1] + sorted[mid]) / 2
-    } else {
-        sorted[mid]
+    /// Re-runs the election so a source going stale hands over even when
+    /// nothing reports. Returns the new state when the leader changed.
+    pub fn elect(&mut self) -> Option<TempoState> {
+        self.follow_leader(self.clock.now_ms()).then(|| self.state.clone())
+    }
+
+    /// Keeps a source that only reports changes from going stale. Returns the
+    /// new state when this hands the grid to another source.
+    pub fn refresh_source(&mut self, source: &TempoSource, confidence: f64) -> Option<TempoState> {
+        let now = self.clock.now_ms();
+        self.arbiter.refresh(source, confidence, now);
+        self.follow_leader(now).then(|| self.state.clone())
+    }
+
+    /// Withdraws a source, for example when its input is stopped.
+    pub fn release_source(&mut self, source: &TempoSource) -> ArbiterStatus {
+        let now = self.clock.now_ms();
+        self.arbiter.release(source, now);
+        if *source == TempoSource::Tap {
+            self.taps.clear();
+            self.state.tap_count = 0;
+        }
+        self.follow_leader(now);
+        self.arbiter.status(now)
+    }
+
+    pub fn tempo_log(&self) -> &TempoLog {
+        &self.log
+    }
+
+    pub fn clear_tempo_log(&mut self) {
+        self.log.clear();
+    }
+
+    pub fn export_tempo_log(
+        &self,
+        format: TempoLogFormat,
+        reference: Option<&DriftReference>,
+    ) -> serde_json::Result<TempoLogExport> {
+        self.log.export(format, reference)
     }
-}
 
-fn tap_confidence(intervals: &[u64]) -> f64 {
-    if intervals.len() == 1 {
-        return 0.35;
+    fn log(
+        &mut self,
+        kind: TempoLogKind,
+        source: TempoSource,
+        confidence: f64,
+        requested_bpm: f64,
+        timestamp_ms: u64,
+        accepted: bool,
+    ) {
+        let entry = TempoLogEntry::new(kind, source, confidence, requested_bpm, &self.state, timestamp_ms);
+        self.log.record(if accepted { entry } else { entry.rejected() });
     }
 
-    let mean = intervals.iter().map(|v| *v as f64).sum::<f64>() / intervals.len() as f64;
-    if mean <= f64::EPSILON {
-        return 0.2;
+    /// Applies the grid of a newly elected leader: its last tempo and
+    /// downbeat, or for manual and tap its tempo from the running phase.
+    /// Returns whether the grid changed hands.
+    fn follow_leader(&mut self, now_ms: u64) -> bool {
+        let Some(grid) = self
+            .arbiter
+            .leading(now_ms)
+            .filter(|grid| grid.source != self.state.source)
+        else {
+            return false;
+        };
+        match grid.downbeat_epoch_ms {
+            Some(downbeat_epoch_ms) => {
+                self.state.tempo_map = TempoMap::default();
+                self.state.bpm = clamp_bpm(grid.bpm);
+                self.state.downbeat_epoch_ms = downbeat_epoch_ms;
+            }
+            None => self.reanchor(grid.bpm, now_ms),
+        }
+        self.state.confidence = grid.confidence.clamp(0.0, 1.0);
+        self.state.source = grid.source.clone();
+        self.log(TempoLogKind::Handover, grid.source, grid.confidence, grid.bpm, now_ms, true);
+        true
     }
 
-    let variance = intervals
-        .iter()
-        .map(|value| {
-            let diff = *value as f64 - mean;
-            diff * diff
-        })
-        .sum::<f64>()
-        / intervals.len() as f64;
+    /// Operator tempo changes replace any tapped tempo.
+    fn offer_manual(&mut self, bpm: f64, now_ms: u64) -> bool {
+        self.arbiter.release(&TempoSource::Tap, now_ms);
+        self.arbiter.offer(TempoSource::Manual, clamp_bpm(bpm), None, 1.0, now_ms)
+    }
 
-    let std_dev = variance.sqrt();
-    let coefficient_of_variation = std_dev / mean;
+    /// Switches to a constant `bpm` from `timestamp_ms`, moving the downbeat
+    /// anchor so the beat position at that instant is unchanged.
+    fn reanchor(&mut self, bpm: f64, timestamp_ms: u64) {
+        self.state = self.state.with_bpm_at(bpm, timestamp_ms);
+    }
+}
 
-    let consistency = 1.0 - coefficient_of_variation;
-    let sample_weight = (intervals.len() as f64 / 6.0).clamp(0.4, 1.0);
+/// Anchor time for beat 0 such that `beat` falls on `timestamp_ms` at `bpm`.
+fn anchor_ms(timestamp_ms: f64, beat: f64, bpm: f64) -> u64 {
+    (timestamp_ms - beat * 60_000.0 / bpm).round().max(0.0) as u64
+}
 
-    (consistency * sample_weight).clamp(0.2, 0.99)
+fn clamp_bpm(bpm: f64) -> f64 {
+    bpm.clamp(MIN_BPM, MAX_BPM)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::cmp::Ordering;
+    use crate::engine::clock::ManualClock;
 
     #[test]
     fn tapping_updates_bpm_and_source() {
@@ -169,24 +594,234 @@ mod tests {
     }
 
     #[test]
-    fn nudge_is_clamped() {
+    fn defaults_read_the_injected_clock() {
+        let clock = ManualClock::at_ms(5_000);
+        let mut tempo = TempoEngine::new(Arc::new(clock.clone()));
+        assert_eq!(tempo.state().downbeat_epoch_ms, 5_000);
+
+        tempo.tap_bpm(None);
+        clock.advance_ms(500);
+        let state = tempo.tap_bpm(None);
+        assert_eq!(state.bpm, 120.0);
+        assert_eq!(state.downbeat_epoch_ms, 5_500);
+        assert_eq!(tempo.position_at(None).bar, 1);
+    }
+
+    #[test]
+    fn external_beat_keeps_bar_alignment() {
         let mut tempo = TempoEngine::default();
-        tempo.set_bpm(290.0);
-        let state = tempo.nudge_bpm(100.0);
-        assert_eq!(state.bpm, 300.0);
+        tempo.set_bpm(120.0, None);
+        tempo.resync_downbeat(Some(10_000));
+        tempo.release_source(&TempoSource::Manual);
+
+        // A beat detected near the third beat of a bar, at a slightly faster tempo.
+        let state = tempo.sync_external_beat(TempoSource::Auto, 125.0, 11_010, 0.8);
+        assert_eq!(state.source, TempoSource::Auto);
+        assert_eq!(state.downbeat_epoch_ms, 11_010 - 960);
+    }
+
+    #[test]
+    fn glitching_source_does_not_take_over() {
+        let clock = ManualClock::at_ms(10_000);
+        let mut tempo = TempoEngine::new(Arc::new(clock.clone()));
+        tempo.set_bpm(120.0, None);
+
+        // A single MIDI clock burst is not enough to displace the operator's tempo.
+        let state = tempo.sync_external(TempoSource::MidiClock, 97.0, 10_000, 0.9);
+        assert_eq!((state.bpm, state.source), (120.0, TempoSource::Manual));
+
+        // A clock that keeps running takes charge once it has been live for a second.
+        for _ in 0..3 {
+            clock.advance_ms(500);
+            tempo.sync_external(TempoSource::MidiClock, 98.0, 10_000, 0.9);
+        }
+        let state = tempo.state();
+        assert_eq!((state.bpm, state.source), (98.0, TempoSource::MidiClock));
+
+        // When it stops, manual takes the grid back at its own tempo from the
+        // running phase, and operator changes apply again.
+        clock.advance_ms(5_000);
+        let now = 16_500.0;
+        let beat = tempo.state().beat_at(now);
+        let state = tempo.elect().expect("manual should take the grid back");
+        assert!((state.beat_at(now) - beat).abs() < 0.01);
+        assert_eq!((state.bpm, state.source), (120.0, TempoSource::Manual));
+        assert_eq!(tempo.elect(), None);
+        assert_eq!(tempo.arbiter_status().leader, Some(TempoSource::Manual));
+        assert_eq!(tempo.set_bpm(124.0, None).bpm, 124.0);
     }
 
     #[test]
-    fn median_supports_even_and_odd_inputs() {
-        assert_eq!(median_ms(&[400, 500, 600]), 500);
-        assert_eq!(median_ms(&[400, 500, 600, 700]), 550);
+    fn tempo_log_records_rejected_and_applied_changes() {
+        let clock = ManualClock::at_ms(10_000);
+        let mut tempo = TempoEngine::new(Arc::new(clock.clone()));
+        tempo.set_bpm(120.0, None);
+        tempo.sync_external(TempoSource::MidiClock, 97.0, 10_000, 0.9);
+        clock.advance_ms(250);
+        tempo.resync_downbeat(None);
+
+        let log = tempo
+            .tempo_log()
+            .entries()
+            .map(|entry| (entry.kind, entry.requested_bpm, entry.bpm, entry.accepted))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            log,
+            vec![
+                (TempoLogKind::SetBpm, 120.0, 120.0, true),
+                (TempoLogKind::External, 97.0, 120.0, false),
+                (TempoLogKind::Resync, 120.0, 120.0, true),
+            ]
+        );
+        assert_eq!(tempo.tempo_log().entries().last().map(|entry| entry.downbeat_epoch_ms), Some(10_250));
+    }
+
+    #[test]
+    fn position_counts_bars_and_beats_in_meter() {
+        let mut tempo = TempoEngine::new(Arc::new(ManualClock::at_ms(10_000)));
+        tempo.set_bpm(120.0, None);
+        tempo.resync_downbeat(Some(10_000));
+        tempo
+            .set_meter(Meter {
+                numerator: 7,
+                denominator: 8,
+            })
+            .unwrap();
+
+        // 7/8 at 120 BPM: 250ms eighths, 1750ms bars.
+        let position = tempo.position_at(Some(10_000 + 1_750 + 3 * 250 + 125));
+        assert_eq!(position.bar, 2);
+        assert_eq!(position.beat, 4);
+        assert_eq!(position.tick, TICKS_PER_BEAT / 2);
+        assert!((position.phase - 3.5 / 7.0).abs() < 1e-9);
+
+        let before = tempo.position_at(Some(9_000));
+        assert_eq!(before.bar, 0);
+    }
+
+    #[test]
+    fn a_meter_change_keeps_the_current_bar_in_place() {
+        let clock = ManualClock::at_ms(10_000);
+        let mut tempo = TempoEngine::new(Arc::new(clock.clone()));
+        tempo.set_bpm(120.0, None);
+        tempo.resync_downbeat(None);
+
+        // Halfway through bar 3 of 4/4, which started at 14_000.
+        clock.set_ms(15_000);
+        let state = tempo
+            .set_meter(Meter {
+                numerator: 3,
+                denominator: 4,
+            })
+            .unwrap();
+        let position = state.position_at(15_010);
+        assert_eq!((position.bar, position.beat), (3, 3));
+        assert_eq!(state.time_at_beat(GridTarget { bar: 3, beat: 1, tick: 0 }.beats(&state.meter)), 14_000.0);
+        // Bar 3 is now three beats long, so bar 4 starts at 15_500.
+        assert_eq!(state.time_at_beat(GridTarget { bar: 4, beat: 1, tick: 0 }.beats(&state.meter)), 15_500.0);
+    }
+
+    #[test]
+    fn invalid_meter_is_rejected() {
+        let mut tempo = TempoEngine::default();
+        let result = tempo.set_meter(Meter {
+            numerator: 5,
+            denominator: 3,
+        });
+        assert!(matches!(result, Err(TempoError::InvalidMeter(5, 3))));
+    }
+
+    #[test]
+    fn ramp_starts_on_next_downbeat_and_settles() {
+        let mut tempo = TempoEngine::default();
+        tempo.set_bpm(120.0, None);
+        tempo.resync_downbeat(Some(100_000));
+
+        let state = tempo.ramp_bpm(60.0, 1, TempoRamp::Linear, Some(100_500)).unwrap();
+        assert_eq!(state.tempo_map.events().len(), 2);
+        assert_eq!(state.time_at_beat(4.0), 102_000.0);
+        assert_eq!(state.bpm_at(102_000), 120.0);
+
+        // Halfway through the ramp the tempo is halfway between the endpoints.
+        let midpoint = state.time_at_beat(6.0).round() as u64;
+        assert!((state.bpm_at(midpoint) - 90.0).abs() < 0.1);
+
+        let ramp_end = state.time_at_beat(8.0);
+        let settled = tempo.advance(Some(ramp_end as u64 + 1_000));
+        assert_eq!(settled.bpm, 60.0);
+        assert!((settled.time_at_beat(10.0) - (ramp_end + 2_000.0)).abs() < 1.0);
+        assert_eq!(settled.position_at(ramp_end.ceil() as u64).bar, 3);
+    }
+
+    #[test]
+    fn an_invalid_ramp_fails_and_keeps_the_tempo_map() {
+        let mut tempo = TempoEngine::default();
+        tempo.set_bpm(120.0, None);
+        tempo.resync_downbeat(Some(100_000));
+        let ramping = tempo.ramp_bpm(60.0, 2, TempoRamp::Linear, Some(100_500)).unwrap();
+
+        let result = tempo.ramp_bpm(f64::NAN, 1, TempoRamp::Linear, Some(101_000));
+        assert!(matches!(result, Err(TempoError::InvalidTempoEvent(_))));
+        assert_eq!(tempo.state().tempo_map, ramping.tempo_map);
+    }
+
+    #[test]
+    fn nudge_mid_bar_keeps_beat_position() {
+        let mut tempo = TempoEngine::default();
+        tempo.set_bpm(120.0, None);
+        tempo.resync_downbeat(Some(10_000));
+
+        // Halfway through beat 2 of bar 2.
+        let at = 10_000 + 2_000 + 750;
+        let before = tempo.position_at(Some(at));
+        let state = tempo.nudge_bpm(10.0, Some(at));
+        let after = tempo.position_at(Some(at));
+
+        assert_eq!(state.bpm, 130.0);
+        assert_eq!((after.bar, after.beat), (before.bar, before.beat));
+        assert!((after.phase - before.phase).abs() < 1e-3);
+
+        // The rest of the bar plays at the new tempo: 2.5 beats of 60000/130 ms.
+        let next_bar = state.time_at_beat(8.0);
+        assert!((next_bar - (at as f64 + 2.5 * 60_000.0 / 130.0)).abs() < 1.0);
     }
 
     #[test]
-    fn confidence_improves_for_consistent_taps() {
-        let consistent = tap_confidence(&[500, 500, 500, 500]);
-        let jittery = tap_confidence(&[400, 540, 450, 610]);
+    fn set_bpm_during_a_ramp_continues_from_the_current_beat() {
+        let mut tempo = TempoEngine::default();
+        tempo.set_bpm(120.0, None);
+        tempo.resync_downbeat(Some(100_000));
+        let ramping = tempo.ramp_bpm(60.0, 1, TempoRamp::Linear, Some(100_000)).unwrap();
 
-        assert_eq!(consistent.partial_cmp(&jittery), Some(Ordering::Greater));
+        let at = ramping.time_at_beat(2.5).round() as u64;
+        let state = tempo.set_bpm(100.0, Some(at));
+        assert!(state.tempo_map.is_empty());
+        assert!((state.beat_at(at as f64) - ramping.beat_at(at as f64)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn four_count_moves_the_downbeat_only_on_the_last_tap() {
+        let mut tempo = TempoEngine::new(Arc::new(ManualClock::at_ms(10_000)));
+        tempo.set_tap_config(TapTempoConfig {
+            downbeat_on_four_count: true,
+            ..TapTempoConfig::default()
+        });
+        tempo.resync_downbeat(Some(10_100));
+
+        for tap in [20_000, 20_500, 21_000] {
+            let state = tempo.tap_bpm(Some(tap));
+            assert_ne!(state.downbeat_epoch_ms, tap);
+        }
+        let state = tempo.tap_bpm(Some(21_500));
+        assert_eq!(state.downbeat_epoch_ms, 21_500);
+        assert_eq!(state.tap_count, 4);
+    }
+
+    #[test]
+    fn nudge_is_clamped() {
+        let mut tempo = TempoEngine::default();
+        tempo.set_bpm(290.0, None);
+        let state = tempo.nudge_bpm(100.0, None);
+        assert_eq!(state.bpm, 300.0);
     }
 }
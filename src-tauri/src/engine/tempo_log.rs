@@ -0,0 +1,398 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+use super::tempo::{TempoSource, TempoState};
+
+pub const DEFAULT_LOG_CAPACITY: usize = 4_096;
+
+/// Grids whose beats sit closer than this count as unchanged.
+const UNCHANGED_GRID_MS: f64 = 1.0;
+/// Requested tempos closer than this count as the same request.
+const UNCHANGED_BPM: f64 = 0.01;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TempoLogKind {
+    SetBpm,
+    Nudge,
+    Ramp,
+    TempoMap,
+    Tap,
+    Resync,
+    External,
+    /// The arbiter handed the grid to another source.
+    Handover,
+}
+
+impl TempoLogKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TempoLogKind::SetBpm => "set_bpm",
+            TempoLogKind::Nudge => "nudge",
+            TempoLogKind::Ramp => "ramp",
+            TempoLogKind::TempoMap => "tempo_map",
+            TempoLogKind::Tap => "tap",
+            TempoLogKind::Resync => "resync",
+            TempoLogKind::External => "external",
+            TempoLogKind::Handover => "handover",
+        }
+    }
+}
+
+/// One tempo change request and the grid it left behind. `bpm` and
+/// `downbeat_epoch_ms` describe the constant-tempo grid at `timestamp_ms`
+/// after the request was handled.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TempoLogEntry {
+    pub timestamp_ms: u64,
+    pub kind: TempoLogKind,
+    /// Source that made the request, which may differ from the one leading.
+    pub source: TempoSource,
+    pub confidence: f64,
+    pub requested_bpm: f64,
+    pub bpm: f64,
+    pub downbeat_epoch_ms: u64,
+    /// False when the arbiter kept another source in charge.
+    pub accepted: bool,
+}
+
+impl TempoLogEntry {
+    /// Entry for a request from `source`, reading the grid from `state`.
+    pub fn new(
+        kind: TempoLogKind,
+        source: TempoSource,
+        confidence: f64,
+        requested_bpm: f64,
+        state: &TempoState,
+        timestamp_ms: u64,
+    ) -> Self {
+        let grid = state.constant_at(timestamp_ms);
+        Self {
+            timestamp_ms,
+            kind,
+            source,
+            confidence,
+            requested_bpm,
+            bpm: grid.bpm,
+            downbeat_epoch_ms: grid.downbeat_epoch_ms,
+            accepted: true,
+        }
+    }
+
+    pub fn rejected(self) -> Self {
+        Self {
+            accepted: false,
+            ..self
+        }
+    }
+
+    /// Whether `next` repeats this request without moving the grid, as a
+    /// steady external clock does on every beat.
+    fn repeated_by(&self, next: &TempoLogEntry) -> bool {
+        if self.kind != next.kind
+            || self.source != next.source
+            || self.accepted != next.accepted
+            || (self.requested_bpm - next.requested_bpm).abs() >= UNCHANGED_BPM
+            || (self.bpm - next.bpm).abs() >= UNCHANGED_BPM
+        {
+            return false;
+        }
+        let at_ms = next.timestamp_ms as f64;
+        let beat_ms = 60_000.0 / next.bpm;
+        let beats = |entry: &TempoLogEntry| (at_ms - entry.downbeat_epoch_ms as f64) * entry.bpm / 60_000.0;
+        ((beats(self) - beats(next)) * beat_ms).abs() < UNCHANGED_GRID_MS
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TempoLogFormat {
+    Json,
+    Csv,
+}
+
+/// Beat times from an offline analysis, such as Essentia's `beats`, in
+/// seconds from the start of the audio. `origin_ms` is the engine clock time
+/// at which the audio started.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DriftReference {
+    pub beats: Vec<f64>,
+    pub origin_ms: u64,
+}
+
+/// How far the engine's beat grid sat from the reference beats. Offsets are
+/// signed: positive means the reference beat came after the grid beat.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DriftReport {
+    pub compared_beats: usize,
+    /// Reference beats before the first log entry, which have no grid to compare with.
+    pub skipped_beats: usize,
+    pub mean_offset_ms: f64,
+    pub mean_abs_offset_ms: f64,
+    pub rms_offset_ms: f64,
+    pub max_abs_offset_ms: f64,
+    /// Mean of engine BPM minus the BPM implied by consecutive reference beats.
+    pub mean_bpm_error: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TempoLogExport {
+    pub format: TempoLogFormat,
+    pub content: String,
+    pub drift: Option<DriftReport>,
+}
+
+/// Bounded history of tempo changes; the oldest entries are dropped first.
+/// A request that repeats the previous one from the same source and leaves
+/// the grid where it was is not recorded again.
+#[derive(Debug, Clone)]
+pub struct TempoLog {
+    entries: VecDeque<TempoLogEntry>,
+    capacity: usize,
+}
+
+impl Default for TempoLog {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_LOG_CAPACITY)
+    }
+}
+
+impl TempoLog {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            capacity: capacity.max(1),
+        }
+    }
+
+    pub fn record(&mut self, entry: TempoLogEntry) {
+        if self.entries.back().is_some_and(|last| last.repeated_by(&entry)) {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &TempoLogEntry> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(&self.entries)
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv =
+            String::from("timestamp_ms,kind,source,confidence,requested_bpm,bpm,downbeat_epoch_ms,accepted\n");
+        for entry in &self.entries {
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{},{},{},{}",
+                entry.timestamp_ms,
+                entry.kind.as_str(),
+                entry.source.as_str(),
+                entry.confidence,
+                entry.requested_bpm,
+                entry.bpm,
+                entry.downbeat_epoch_ms,
+                entry.accepted
+            );
+        }
+        csv
+    }
+
+    /// Compares each reference beat with the nearest beat of the grid in
+    /// effect at that time. The grid is taken as constant between entries.
+    pub fn drift(&self, reference: &DriftReference) -> DriftReport {
+        let mut offsets = Vec::new();
+        let mut bpm_errors = Vec::new();
+        let mut skipped_beats = 0;
+        let mut previous_ms: Option<f64> = None;
+
+        for seconds in &reference.beats {
+            let at_ms = reference.origin_ms as f64 + seconds * 1_000.0;
+            let Some(grid) = self.grid_at(at_ms) else {
+                skipped_beats += 1;
+                continue;
+            };
+            let beat_ms = 60_000.0 / grid.bpm;
+            let beat = (at_ms - grid.downbeat_epoch_ms as f64) / beat_ms;
+            offsets.push((beat - beat.round()) * beat_ms);
+
+            if let Some(previous_ms) = previous_ms.filter(|previous_ms| at_ms > *previous_ms) {
+                bpm_errors.push(grid.bpm - 60_000.0 / (at_ms - previous_ms));
+            }
+            previous_ms = Some(at_ms);
+        }
+
+        let count = offsets.len().max(1) as f64;
+        DriftReport {
+            compared_beats: offsets.len(),
+            skipped_beats,
+            mean_offset_ms: offsets.iter().sum::<f64>() / count,
+            mean_abs_offset_ms: offsets.iter().map(|offset| offset.abs()).sum::<f64>() / count,
+            rms_offset_ms: (offsets.iter().map(|offset| offset * offset).sum::<f64>() / count).sqrt(),
+            max_abs_offset_ms: offsets.iter().fold(0.0, |max, offset| offset.abs().max(max)),
+            mean_bpm_error: bpm_errors.iter().sum::<f64>() / bpm_errors.len().max(1) as f64,
+        }
+    }
+
+    pub fn export(
+        &self,
+        format: TempoLogFormat,
+        reference: Option<&DriftReference>,
+    ) -> serde_json::Result<TempoLogExport> {
+        let content = match format {
+            TempoLogFormat::Json => self.to_json()?,
+            TempoLogFormat::Csv => self.to_csv(),
+        };
+        Ok(TempoLogExport {
+            format,
+            content,
+            drift: reference.map(|reference| self.drift(reference)),
+        })
+    }
+
+    fn grid_at(&self, at_ms: f64) -> Option<&TempoLogEntry> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|entry| entry.timestamp_ms as f64 <= at_ms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::tempo::TempoEngine;
+
+    fn state(bpm: f64, downbeat_epoch_ms: u64) -> TempoState {
+        let mut state = TempoEngine::default().state();
+        state.bpm = bpm;
+        state.downbeat_epoch_ms = downbeat_epoch_ms;
+        state
+    }
+
+    #[test]
+    fn log_drops_oldest_entries_past_capacity() {
+        let mut log = TempoLog::with_capacity(2);
+        // Each tap moves the downbeat onto itself.
+        for timestamp_ms in [1_000, 2_000, 3_000] {
+            log.record(TempoLogEntry::new(
+                TempoLogKind::Tap,
+                TempoSource::Tap,
+                0.9,
+                120.0,
+                &state(120.0, timestamp_ms),
+                timestamp_ms,
+            ));
+        }
+        let timestamps = log.entries().map(|entry| entry.timestamp_ms).collect::<Vec<_>>();
+        assert_eq!(timestamps, vec![2_000, 3_000]);
+
+        let csv = log.to_csv();
+        assert_eq!(csv.lines().count(), 3);
+        assert_eq!(csv.lines().nth(1), Some("2000,tap,tap,0.9,120,120,2000,true"));
+    }
+
+    #[test]
+    fn repeated_requests_that_keep_the_grid_are_coalesced() {
+        let mut log = TempoLog::default();
+        let clock = |bpm, timestamp_ms| {
+            TempoLogEntry::new(
+                TempoLogKind::External,
+                TempoSource::MidiClock,
+                0.9,
+                bpm,
+                &state(bpm, 1_000),
+                timestamp_ms,
+            )
+        };
+        // A steady clock reports every beat; only the first report is kept.
+        for beat in 0..64 {
+            log.record(clock(120.0, 1_000 + beat * 500));
+        }
+        assert_eq!(log.len(), 1);
+
+        // Rejected updates from another source are kept once, then coalesced.
+        let held = state(120.0, 1_000);
+        for timestamp_ms in [33_000, 33_500] {
+            log.record(
+                TempoLogEntry::new(
+                    TempoLogKind::External,
+                    TempoSource::Auto,
+                    0.4,
+                    90.0,
+                    &held,
+                    timestamp_ms,
+                )
+                .rejected(),
+            );
+        }
+        assert_eq!(log.len(), 2);
+
+        // A tempo change from the clock is a new entry.
+        log.record(clock(120.0, 34_000));
+        log.record(clock(121.0, 34_500));
+        log.record(clock(121.0, 35_000));
+        let kept = log
+            .entries()
+            .map(|entry| (entry.timestamp_ms, entry.source.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kept,
+            vec![
+                (1_000, TempoSource::MidiClock),
+                (33_000, TempoSource::Auto),
+                (34_000, TempoSource::MidiClock),
+                (34_500, TempoSource::MidiClock),
+            ]
+        );
+    }
+
+    #[test]
+    fn drift_measures_reference_beats_against_the_grid_in_effect() {
+        let mut log = TempoLog::default();
+        let grid = state(120.0, 10_000);
+        log.record(TempoLogEntry::new(
+            TempoLogKind::SetBpm,
+            TempoSource::Manual,
+            1.0,
+            120.0,
+            &grid,
+            10_000,
+        ));
+        // A rejected update leaves the grid as it was.
+        log.record(TempoLogEntry::new(TempoLogKind::External, TempoSource::Auto, 0.4, 90.0, &grid, 10_500).rejected());
+
+        // Audio started at 9s; its beats land 10ms late on a 120 BPM grid from 10s.
+        let reference = DriftReference {
+            beats: vec![0.5, 1.01, 1.51, 2.01, 2.51],
+            origin_ms: 9_000,
+        };
+        let report = log.drift(&reference);
+        assert_eq!((report.compared_beats, report.skipped_beats), (4, 1));
+        assert!((report.mean_offset_ms - 10.0).abs() < 1e-6);
+        assert!((report.max_abs_offset_ms - 10.0).abs() < 1e-6);
+        assert!(report.mean_bpm_error.abs() < 1e-6);
+    }
+}
@@ -0,0 +1,337 @@
+use serde::{Deserialize, Serialize};
+
+use super::tempo::TempoError;
+
+pub const MIN_BPM: f64 = 20.0;
+pub const MAX_BPM: f64 = 300.0;
+
+/// How the tempo travels from the previous event to this one.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TempoRamp {
+    /// Hold the previous tempo, then jump on the event.
+    #[default]
+    Step,
+    Linear,
+    EaseIn,
+    EaseOut,
+}
+
+/// A tempo target at `beat` quarter notes after the downbeat anchor.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TempoEvent {
+    pub beat: f64,
+    pub bpm: f64,
+    #[serde(default)]
+    pub ramp: TempoRamp,
+}
+
+/// Tempo automation relative to the downbeat anchor. The tempo before the first
+/// event is the engine's base tempo, and the first event ramps from beat 0.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(transparent)]
+pub struct TempoMap {
+    events: Vec<TempoEvent>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    start_beat: f64,
+    end_beat: f64,
+    start_bpm: f64,
+    end_bpm: f64,
+    ramp: TempoRamp,
+}
+
+impl TempoMap {
+    pub fn new(events: Vec<TempoEvent>) -> Result<Self, TempoError> {
+        let mut previous = 0.0;
+        for (index, event) in events.iter().enumerate() {
+            let ordered = if index == 0 {
+                event.beat >= previous
+            } else {
+                event.beat > previous
+            };
+            if !event.beat.is_finite() || !ordered || !(MIN_BPM..=MAX_BPM).contains(&event.bpm) {
+                return Err(TempoError::InvalidTempoEvent(index));
+            }
+            previous = event.beat;
+        }
+        Ok(Self { events })
+    }
+
+    pub fn events(&self) -> &[TempoEvent] {
+        &self.events
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn bpm_at_beat(&self, base_bpm: f64, beat: f64) -> f64 {
+        match self.segment_at(base_bpm, beat) {
+            Some(segment) => segment.bpm_at(beat),
+            None => self.events.last().map_or(base_bpm, |event| event.bpm),
+        }
+    }
+
+    /// Milliseconds from the anchor (beat 0) to `beat`; negative before the anchor.
+    pub fn ms_at_beat(&self, base_bpm: f64, beat: f64) -> f64 {
+        if beat <= 0.0 {
+            return beat * beat_ms(base_bpm);
+        }
+
+        let mut elapsed = 0.0;
+        for segment in self.segments(base_bpm) {
+            if beat <= segment.end_beat {
+                return elapsed + segment.ms_to(beat);
+            }
+            elapsed += segment.duration_ms();
+        }
+
+        let (last_beat, last_bpm) = self.tail(base_bpm);
+        elapsed + (beat - last_beat) * beat_ms(last_bpm)
+    }
+
+    /// Inverse of [`TempoMap::ms_at_beat`].
+    pub fn beat_at_ms(&self, base_bpm: f64, ms: f64) -> f64 {
+        if ms <= 0.0 {
+            return ms / beat_ms(base_bpm);
+        }
+
+        let mut elapsed = 0.0;
+        for segment in self.segments(base_bpm) {
+            let duration = segment.duration_ms();
+            if ms <= elapsed + duration {
+                return segment.beat_after(ms - elapsed);
+            }
+            elapsed += duration;
+        }
+
+        let (last_beat, last_bpm) = self.tail(base_bpm);
+        last_beat + (ms - elapsed) / beat_ms(last_bpm)
+    }
+
+    /// Splits the map at `beat`, returning the tempo there and the events after
+    /// it re-expressed relative to `beat`.
+    pub fn split_at(&self, base_bpm: f64, beat: f64) -> (f64, TempoMap) {
+        let bpm = self.bpm_at_beat(base_bpm, beat);
+        let events = self
+            .events
+            .iter()
+            .filter(|event| event.beat > beat)
+            .map(|event| TempoEvent {
+                beat: event.beat - beat,
+                ..*event
+            })
+            .collect();
+        (bpm, TempoMap { events })
+    }
+
+    /// Drops events that `beat` has already passed, keeping the latest one as a
+    /// held step so a ramp that follows it still starts from the right point.
+    /// Returns the tempo of that held event.
+    pub fn settle(&mut self, beat: f64) -> Option<f64> {
+        let passed = self.events.iter().take_while(|event| event.beat <= beat).count();
+        if passed == 0 {
+            return None;
+        }
+        self.events.drain(..passed - 1);
+        let held = &mut self.events[0];
+        held.ramp = TempoRamp::Step;
+        Some(held.bpm)
+    }
+
+    fn tail(&self, base_bpm: f64) -> (f64, f64) {
+        self.events
+            .last()
+            .map_or((0.0, base_bpm), |event| (event.beat, event.bpm))
+    }
+
+    fn segment_at(&self, base_bpm: f64, beat: f64) -> Option<Segment> {
+        if beat < 0.0 {
+            return Some(Segment::constant(beat, 0.0, base_bpm));
+        }
+        self.segments(base_bpm).find(|segment| beat < segment.end_beat)
+    }
+
+    fn segments(&self, base_bpm: f64) -> impl Iterator<Item = Segment> + '_ {
+        let mut start = (0.0, base_bpm);
+        self.events.iter().map(move |event| {
+            let segment = Segment {
+                start_beat: start.0,
+                end_beat: event.beat,
+                start_bpm: start.1,
+                end_bpm: event.bpm,
+                ramp: event.ramp,
+            };
+            start = (event.beat, event.bpm);
+            segment
+        })
+    }
+}
+
+impl Segment {
+    fn constant(start_beat: f64, end_beat: f64, bpm: f64) -> Self {
+        Self {
+            start_beat,
+            end_beat,
+            start_bpm: bpm,
+            end_bpm: bpm,
+            ramp: TempoRamp::Step,
+        }
+    }
+
+    fn length(&self) -> f64 {
+        self.end_beat - self.start_beat
+    }
+
+    fn is_constant(&self) -> bool {
+        self.ramp == TempoRamp::Step || self.start_bpm == self.end_bpm || self.length() <= 0.0
+    }
+
+    fn bpm_at(&self, beat: f64) -> f64 {
+        if self.is_constant() {
+            return self.start_bpm;
+        }
+        let t = ((beat - self.start_beat) / self.length()).clamp(0.0, 1.0);
+        let shaped = match self.ramp {
+            TempoRamp::Step | TempoRamp::Linear => t,
+            TempoRamp::EaseIn => t * t,
+            TempoRamp::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+        };
+        self.start_bpm + (self.end_bpm - self.start_bpm) * shaped
+    }
+
+    fn duration_ms(&self) -> f64 {
+        self.ms_to(self.end_beat)
+    }
+
+    /// Integrates beat length over `start_beat..beat`.
+    fn ms_to(&self, beat: f64) -> f64 {
+        let span = beat - self.start_beat;
+        if self.is_constant() {
+            return span * beat_ms(self.start_bpm);
+        }
+        if self.ramp == TempoRamp::Linear {
+            let slope = (self.end_bpm - self.start_bpm) / self.length();
+            return 60_000.0 / slope * (self.bpm_at(beat) / self.start_bpm).ln();
+        }
+
+        // Simpson's rule; the curves are smooth quadratics so this is exact to well under a microsecond.
+        const STEPS: usize = 64;
+        let h = span / STEPS as f64;
+        let sum = (0..=STEPS)
+            .map(|step| {
+                let weight = match step {
+                    0 | STEPS => 1.0,
+                    odd if odd % 2 == 1 => 4.0,
+                    _ => 2.0,
+                };
+                weight * beat_ms(self.bpm_at(self.start_beat + step as f64 * h))
+            })
+            .sum::<f64>();
+        sum * h / 3.0
+    }
+
+    /// Beat reached `ms` after the start of the segment.
+    fn beat_after(&self, ms: f64) -> f64 {
+        if self.is_constant() {
+            return self.start_beat + ms / beat_ms(self.start_bpm);
+        }
+        if self.ramp == TempoRamp::Linear {
+            let slope = (self.end_bpm - self.start_bpm) / self.length();
+            return self.start_beat + self.start_bpm * ((ms * slope / 60_000.0).exp() - 1.0) / slope;
+        }
+
+        let (mut low, mut high) = (self.start_beat, self.end_beat);
+        for _ in 0..60 {
+            let mid = (low + high) / 2.0;
+            if self.ms_to(mid) < ms {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        (low + high) / 2.0
+    }
+}
+
+fn beat_ms(bpm: f64) -> f64 {
+    60_000.0 / bpm
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ramp(events: &[(f64, f64, TempoRamp)]) -> TempoMap {
+        TempoMap::new(
+            events
+                .iter()
+                .map(|&(beat, bpm, ramp)| TempoEvent { beat, bpm, ramp })
+                .collect(),
+        )
+        .expect("valid tempo map")
+    }
+
+    #[test]
+    fn empty_map_is_constant_tempo() {
+        let map = TempoMap::default();
+        assert_eq!(map.ms_at_beat(120.0, 4.0), 2_000.0);
+        assert_eq!(map.beat_at_ms(120.0, -500.0), -1.0);
+    }
+
+    #[test]
+    fn linear_ramp_integrates_beat_lengths() {
+        // 120 -> 60 BPM over 4 beats: 60000 / slope * ln(end / start) with slope -15.
+        let map = ramp(&[(4.0, 60.0, TempoRamp::Linear)]);
+        let expected = 60_000.0 / -15.0 * 0.5f64.ln();
+        assert!((map.ms_at_beat(120.0, 4.0) - expected).abs() < 1e-6);
+        assert!((map.ms_at_beat(120.0, 6.0) - expected - 2_000.0).abs() < 1e-6);
+        assert_eq!(map.bpm_at_beat(120.0, 2.0), 90.0);
+    }
+
+    #[test]
+    fn beat_and_time_round_trip_across_ramps() {
+        let map = ramp(&[
+            (4.0, 120.0, TempoRamp::Step),
+            (8.0, 140.0, TempoRamp::EaseIn),
+            (12.0, 100.0, TempoRamp::EaseOut),
+            (16.0, 90.0, TempoRamp::Linear),
+        ]);
+        for beat in [-2.0, 0.5, 3.9, 5.0, 8.0, 10.25, 13.0, 20.0] {
+            let ms = map.ms_at_beat(120.0, beat);
+            assert!((map.beat_at_ms(120.0, ms) - beat).abs() < 1e-6, "beat {beat}");
+        }
+    }
+
+    #[test]
+    fn events_must_move_forward() {
+        let events = vec![
+            TempoEvent {
+                beat: 4.0,
+                bpm: 120.0,
+                ramp: TempoRamp::Linear,
+            },
+            TempoEvent {
+                beat: 4.0,
+                bpm: 130.0,
+                ramp: TempoRamp::Step,
+            },
+        ];
+        assert!(matches!(TempoMap::new(events), Err(TempoError::InvalidTempoEvent(1))));
+    }
+
+    #[test]
+    fn settle_keeps_the_passed_event_as_a_hold() {
+        let mut map = ramp(&[(4.0, 100.0, TempoRamp::Step), (8.0, 140.0, TempoRamp::Linear)]);
+        let before = map.ms_at_beat(120.0, 6.0) - map.ms_at_beat(120.0, 5.0);
+
+        assert_eq!(map.settle(5.0), Some(100.0));
+        assert_eq!(map.events().len(), 2);
+        let after = map.ms_at_beat(100.0, 6.0) - map.ms_at_beat(100.0, 5.0);
+        assert!((before - after).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,401 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use thiserror::Error;
+
+use super::clock::{MonotonicClock, SharedClock};
+use super::tempo::{GridTarget, TempoState};
+use crate::timeline::types::Meter;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TransportState {
+    #[default]
+    Stopped,
+    Playing,
+    Paused,
+}
+
+/// What caused a [`TransportChange`].
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TransportChangeKind {
+    Play,
+    Pause,
+    Stop,
+    Locate,
+    /// The loop region was set or cleared.
+    Loop,
+    /// The playhead jumped from the loop end back to its start.
+    Wrap,
+}
+
+/// Bars `start_bar` up to, not including, `end_bar`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct LoopRegion {
+    pub start_bar: i64,
+    pub end_bar: i64,
+}
+
+impl LoopRegion {
+    /// Start and end as song positions in quarter notes.
+    fn beats(&self, meter: &Meter) -> (f64, f64) {
+        (bar_start(self.start_bar, meter), bar_start(self.end_bar, meter))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TransportStatus {
+    pub state: TransportState,
+    /// Playhead as bar, beat and tick from the start of the song.
+    pub position: GridTarget,
+    /// Playhead in quarter notes from the start of the song.
+    pub beats: f64,
+    /// Times the playhead has wrapped since playback last started or located.
+    pub pass: u32,
+    pub loop_region: Option<LoopRegion>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TransportChange {
+    pub kind: TransportChangeKind,
+    pub previous: TransportState,
+    pub status: TransportStatus,
+    pub at_ms: u64,
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum TransportError {
+    #[error("bar {0} is before the start of the song")]
+    InvalidBar(i64),
+    #[error("loop region must end after it starts, got bars {0}..{1}")]
+    InvalidLoop(i64, i64),
+}
+
+/// DAW-style play/pause/stop over the tempo grid. While playing, the playhead
+/// advances with the grid's beats, so it follows tempo changes; a loop region
+/// wraps it back once it reaches the loop end.
+#[derive(Debug, Clone)]
+pub struct Transport {
+    state: TransportState,
+    /// Song position in quarter notes at `anchor_grid_beat`.
+    anchor_beats: f64,
+    /// Grid beat, counted from the downbeat anchor, at which playback reached `anchor_beats`.
+    anchor_grid_beat: f64,
+    /// Where the last play started; stop returns here.
+    return_beats: f64,
+    /// Wraps already reported by [`Transport::poll`].
+    reported_pass: u32,
+    loop_region: Option<LoopRegion>,
+    /// Grid the anchor was measured on.
+    tempo: Option<TempoState>,
+    clock: SharedClock,
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Self::new(Arc::new(MonotonicClock::default()))
+    }
+}
+
+impl Transport {
+    pub fn new(clock: SharedClock) -> Self {
+        Self {
+            state: TransportState::Stopped,
+            anchor_beats: 0.0,
+            anchor_grid_beat: 0.0,
+            return_beats: 0.0,
+            reported_pass: 0,
+            loop_region: None,
+            tempo: None,
+            clock,
+        }
+    }
+
+    pub fn state(&self) -> TransportState {
+        self.state
+    }
+
+    pub fn loop_region(&self) -> Option<LoopRegion> {
+        self.loop_region
+    }
+
+    pub fn status(&self, tempo: &TempoState) -> TransportStatus {
+        let (beats, pass) = self.position_at(self.grid_beat_now(tempo), &tempo.meter);
+        TransportStatus {
+            state: self.state,
+            position: GridTarget::at_beat(beats, &tempo.meter),
+            beats,
+            pass,
+            loop_region: self.loop_region,
+        }
+    }
+
+    /// Starts or resumes playback from the playhead. `None` when already playing.
+    pub fn play(&mut self, tempo: &TempoState) -> Option<TransportChange> {
+        if self.state == TransportState::Playing {
+            return None;
+        }
+        self.return_beats = self.anchor_beats;
+        self.anchor(tempo, self.anchor_beats);
+        Some(self.change(TransportChangeKind::Play, TransportState::Playing, tempo))
+    }
+
+    /// Holds the playhead where it is. `None` unless playing.
+    pub fn pause(&mut self, tempo: &TempoState) -> Option<TransportChange> {
+        if self.state != TransportState::Playing {
+            return None;
+        }
+        self.hold(tempo);
+        Some(self.change(TransportChangeKind::Pause, TransportState::Paused, tempo))
+    }
+
+    /// Stops and returns the playhead to where playback started, or to bar 1
+    /// when already stopped.
+    pub fn stop(&mut self, tempo: &TempoState) -> TransportChange {
+        self.anchor_beats = if self.state == TransportState::Stopped {
+            0.0
+        } else {
+            self.return_beats
+        };
+        self.reported_pass = 0;
+        self.change(TransportChangeKind::Stop, TransportState::Stopped, tempo)
+    }
+
+    /// Moves the playhead to the start of `bar`. Playback carries on from there.
+    pub fn locate(&mut self, tempo: &TempoState, bar: i64) -> Result<TransportChange, TransportError> {
+        if bar < 1 {
+            return Err(TransportError::InvalidBar(bar));
+        }
+        self.anchor(tempo, bar_start(bar, &tempo.meter));
+        Ok(self.change(TransportChangeKind::Locate, self.state, tempo))
+    }
+
+    /// Sets or clears the loop region. The current pass restarts from the playhead.
+    pub fn set_loop(
+        &mut self,
+        tempo: &TempoState,
+        region: Option<LoopRegion>,
+    ) -> Result<TransportChange, TransportError> {
+        if let Some(region) = region {
+            if region.start_bar < 1 || region.end_bar <= region.start_bar {
+                return Err(TransportError::InvalidLoop(region.start_bar, region.end_bar));
+            }
+        }
+        self.hold(tempo);
+        self.loop_region = region;
+        Ok(self.change(TransportChangeKind::Loop, self.state, tempo))
+    }
+
+    /// Reports a loop wrap once per pass while playing.
+    pub fn poll(&mut self, tempo: &TempoState) -> Option<TransportChange> {
+        if self.state != TransportState::Playing {
+            return None;
+        }
+        let (_, pass) = self.position_at(self.grid_beat_now(tempo), &tempo.meter);
+        if pass <= self.reported_pass {
+            return None;
+        }
+        self.reported_pass = pass;
+        Some(self.change(TransportChangeKind::Wrap, self.state, tempo))
+    }
+
+    /// Keeps the playhead moving without a jump across a tempo change or resync.
+    pub fn follow_tempo(&mut self, tempo: &TempoState) {
+        let Some(previous) = self.tempo.replace(tempo.clone()) else {
+            return;
+        };
+        if self.state == TransportState::Playing && !previous.same_grid(tempo) {
+            let now_ms = self.clock.now_ms() as f64;
+            self.anchor_grid_beat += tempo.beat_at(now_ms) - previous.beat_at(now_ms);
+        }
+    }
+
+    /// Grid beat at which the playhead reaches song position `beats` on loop
+    /// `pass`: pass 0 runs from the play position to the loop end, later
+    /// passes cover the loop region. `None` when not playing or when the
+    /// position is not reached on that pass.
+    pub fn grid_beat_at(&self, beats: f64, pass: u32, meter: &Meter) -> Option<f64> {
+        if self.state != TransportState::Playing || (pass == 0 && beats < self.anchor_beats - 1e-9) {
+            return None;
+        }
+        let Some((start, end)) = self.active_loop(meter) else {
+            return (pass == 0).then_some(self.anchor_grid_beat + beats - self.anchor_beats);
+        };
+        if beats >= end - 1e-9 {
+            return None;
+        }
+        if pass == 0 {
+            return Some(self.anchor_grid_beat + beats - self.anchor_beats);
+        }
+        if beats < start - 1e-9 {
+            return None;
+        }
+        let first_wrap = self.anchor_grid_beat + end - self.anchor_beats;
+        Some(first_wrap + f64::from(pass - 1) * (end - start) + beats - start)
+    }
+
+    /// Re-anchors playback so the playhead is at `beats` now.
+    fn anchor(&mut self, tempo: &TempoState, beats: f64) {
+        self.tempo = Some(tempo.clone());
+        self.anchor_beats = beats;
+        self.anchor_grid_beat = self.grid_beat_now(tempo);
+        self.reported_pass = 0;
+    }
+
+    /// Pins the playhead at its current position.
+    fn hold(&mut self, tempo: &TempoState) {
+        let (beats, _) = self.position_at(self.grid_beat_now(tempo), &tempo.meter);
+        self.anchor(tempo, beats);
+    }
+
+    fn grid_beat_now(&self, tempo: &TempoState) -> f64 {
+        tempo.beat_at(self.clock.now_ms() as f64)
+    }
+
+    /// Loop bounds in quarter notes, when the playhead will run into them.
+    fn active_loop(&self, meter: &Meter) -> Option<(f64, f64)> {
+        self.loop_region
+            .map(|region| region.beats(meter))
+            .filter(|(_, end)| self.anchor_beats < *end - 1e-9)
+    }
+
+    /// Song position and loop pass at a grid beat.
+    fn position_at(&self, grid_beat: f64, meter: &Meter) -> (f64, u32) {
+        if self.state != TransportState::Playing {
+            return (self.anchor_beats, 0);
+        }
+        let beats = self.anchor_beats + (grid_beat - self.anchor_grid_beat).max(0.0);
+        match self.active_loop(meter) {
+            Some((start, end)) if beats >= end => {
+                let length = end - start;
+                let past_end = beats - end;
+                (start + past_end % length, (past_end / length).floor() as u32 + 1)
+            }
+            _ => (beats, 0),
+        }
+    }
+
+    fn change(&mut self, kind: TransportChangeKind, state: TransportState, tempo: &TempoState) -> TransportChange {
+        let previous = self.state;
+        self.state = state;
+        TransportChange {
+            kind,
+            previous,
+            status: self.status(tempo),
+            at_ms: self.clock.now_ms(),
+        }
+    }
+}
+
+fn bar_start(bar: i64, meter: &Meter) -> f64 {
+    (bar - 1) as f64 * meter.bar_beats()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::clock::ManualClock;
+    use crate::engine::tempo::TempoEngine;
+
+    fn setup() -> (ManualClock, Transport, TempoState) {
+        let clock = ManualClock::at_ms(0);
+        let mut tempo = TempoEngine::new(Arc::new(clock.clone()));
+        tempo.set_bpm(120.0, None);
+        let tempo = tempo.resync_downbeat(Some(0));
+        (clock.clone(), Transport::new(Arc::new(clock)), tempo)
+    }
+
+    #[test]
+    fn play_pause_and_stop_move_the_playhead_like_a_daw() {
+        let (clock, mut transport, tempo) = setup();
+
+        let change = transport.play(&tempo).expect("starts");
+        assert_eq!(
+            (change.previous, change.status.state),
+            (TransportState::Stopped, TransportState::Playing)
+        );
+        assert!(transport.play(&tempo).is_none());
+
+        clock.set_ms(1_000);
+        assert_eq!(
+            transport.status(&tempo).position,
+            GridTarget {
+                bar: 1,
+                beat: 3,
+                tick: 0
+            }
+        );
+        let paused = transport.pause(&tempo).expect("pauses");
+        assert_eq!(paused.kind, TransportChangeKind::Pause);
+
+        // The playhead holds while paused and resumes from the same spot.
+        clock.set_ms(5_000);
+        assert_eq!(transport.status(&tempo).beats, 2.0);
+        transport.play(&tempo);
+        clock.set_ms(5_500);
+        assert_eq!(transport.status(&tempo).beats, 3.0);
+
+        // Stop returns to where playback last started, and a second stop to bar 1.
+        assert_eq!(transport.stop(&tempo).status.beats, 2.0);
+        assert_eq!(transport.stop(&tempo).status.beats, 0.0);
+    }
+
+    #[test]
+    fn a_loop_region_wraps_the_playhead_and_reports_each_pass() {
+        let (clock, mut transport, tempo) = setup();
+        transport.locate(&tempo, 2).unwrap();
+        transport
+            .set_loop(
+                &tempo,
+                Some(LoopRegion {
+                    start_bar: 2,
+                    end_bar: 4,
+                }),
+            )
+            .unwrap();
+        transport.play(&tempo);
+
+        // Bars 2-3 take 4000ms at 120 BPM.
+        clock.set_ms(4_500);
+        let status = transport.status(&tempo);
+        assert_eq!(
+            (status.position, status.pass),
+            (
+                GridTarget {
+                    bar: 2,
+                    beat: 2,
+                    tick: 0
+                },
+                1
+            )
+        );
+        assert_eq!(
+            transport.poll(&tempo).map(|change| change.kind),
+            Some(TransportChangeKind::Wrap)
+        );
+        assert!(transport.poll(&tempo).is_none());
+
+        // Song positions map onto grid beats pass by pass.
+        assert_eq!(transport.grid_beat_at(6.0, 0, &tempo.meter), Some(2.0));
+        assert_eq!(transport.grid_beat_at(6.0, 2, &tempo.meter), Some(18.0));
+        assert_eq!(transport.grid_beat_at(12.0, 1, &tempo.meter), None);
+        assert_eq!(transport.locate(&tempo, 0), Err(TransportError::InvalidBar(0)));
+    }
+
+    #[test]
+    fn the_playhead_does_not_jump_on_a_resync() {
+        let (clock, mut transport, tempo) = setup();
+        let mut engine = TempoEngine::new(Arc::new(clock.clone()));
+        engine.set_bpm(120.0, None);
+        transport.follow_tempo(&tempo);
+        transport.play(&tempo);
+
+        clock.set_ms(1_250);
+        engine.resync_downbeat(Some(1_250));
+        transport.follow_tempo(&engine.state());
+        assert_eq!(transport.status(&engine.state()).beats, 2.5);
+    }
+}
@@ -1,23 +1,149 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use std::sync::Mutex;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::fs::{File, OpenOptions};
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, LockResult, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
 
+use engine::arbiter::{ArbiterPolicy, ArbiterStatus};
+use engine::auto_tempo::{read_wav_mono, AutoTempoFollower, BeatTracker, TempoEstimate};
+use engine::clock::{MonotonicClock, SharedClock};
+use engine::conflict::{ActionOrigin, ConflictDecision, ConflictPolicy};
+use engine::dispatch::{self, ActionDispatcher, ActionSink, DispatchSignal, DispatchStats, DispatchedAction};
+use engine::link::{LinkConfig, LinkReceiver, LinkSession, LinkStatus, NodeId};
 use engine::media::{DecodeBackend, MediaRuntime, RendererBackend, RuntimeCapabilities};
-use engine::scheduler::{QuantizedScheduler, ScheduledAction};
-use engine::tempo::{TempoEngine, TempoState};
-use tauri::State;
+use engine::midi_clock::{
+    DeviceSource, MidiByteSource, MidiClockFollower, MidiClockGenerator, MidiClockUpdate, WriterSink,
+};
+use engine::osc::{
+    self, OscBroadcaster, OscCommand, OscConfig, OscMessage, OscOutputConfig, OscOutputStatus, OscQuery, OscServer,
+    OscStatus, OSC_PORT,
+};
+use engine::pattern::{ActionPattern, RunningPattern};
+use engine::scheduler::{ActionRequest, QuantizedScheduler, ScheduledAction, SectionChange, SectionPlayback};
+use engine::tap_tempo::TapTempoConfig;
+use engine::tempo::{BeatPosition, GridTarget, TempoEngine, TempoSource, TempoState};
+use engine::tempo_log::{DriftReference, TempoLogExport, TempoLogFormat};
+use engine::tempo_map::{TempoEvent, TempoRamp};
+use engine::transport::{LoopRegion, Transport, TransportChange, TransportChangeKind, TransportStatus};
+use fftron_sync_lib::{engine, timeline};
+use tauri::{AppHandle, Emitter, Manager, State};
 use timeline::runtime_adapter::TimelineRuntimeAdapter;
-use timeline::types::{CueAction, EngineCueMarker, QuantizeGrid, TheatreExportBundle};
+use timeline::setlist::{SectionFollow, Setlist, SetlistStatus};
+use timeline::types::{EngineCueMarker, Meter, QuantizeGrid, TheatreExportBundle};
 
-mod engine;
-mod timeline;
+const LINK_POLL_INTERVAL: Duration = Duration::from_millis(10);
+const MIDI_CLOCK_OUTPUT_INTERVAL: Duration = Duration::from_millis(1);
+/// How far ahead MIDI clock messages are queued, so the sink's writer thread
+/// can wait for their exact time even when the output loop wakes late.
+const MIDI_CLOCK_OUTPUT_LEAD_US: u64 = 3_000;
+const OSC_OUTPUT_INTERVAL: Duration = Duration::from_millis(2);
+const ACTION_DISPATCHED_EVENT: &str = "scheduler://action-dispatched";
+const TRANSPORT_CHANGED_EVENT: &str = "transport://state-changed";
+const TRANSPORT_POLL_INTERVAL: Duration = Duration::from_millis(5);
+const SETLIST_CHANGED_EVENT: &str = "setlist://section-started";
+const SECTION_CHANGED_EVENT: &str = "timeline://section-changed";
+/// How far ahead of its boundary a section change hands the queue over.
+const SECTION_LEAD_MS: u64 = 200;
+
+struct MidiClockInput {
+    follower: MidiClockFollower,
+    /// Set, under the input lock, when the input is stopped.
+    stopped: Arc<AtomicBool>,
+    reader: Option<JoinHandle<()>>,
+}
+
+struct MidiClockOutput {
+    generator: MidiClockGenerator,
+    sink: WriterSink,
+}
+
+struct AutoTempo {
+    tracker: BeatTracker,
+    follower: AutoTempoFollower,
+}
 
 struct AppState {
+    clock: SharedClock,
     timeline_runtime: Mutex<TimelineRuntimeAdapter>,
     tempo: Mutex<TempoEngine>,
     scheduler: Mutex<QuantizedScheduler>,
+    transport: Mutex<Transport>,
     media_runtime: Mutex<MediaRuntime>,
+    link: Mutex<Option<LinkSession>>,
+    midi_clock_input: Mutex<Option<MidiClockInput>>,
+    midi_clock_output: Mutex<Option<MidiClockOutput>>,
+    auto_tempo: Mutex<Option<AutoTempo>>,
+    osc: Mutex<Option<OscServer>>,
+    osc_output: Mutex<Option<OscBroadcaster>>,
+    dispatcher: Mutex<Option<ActionDispatcher>>,
+    dispatch_signal: DispatchSignal,
+    /// Bumped for every dispatch thread, so a stopped thread exits even when
+    /// dispatch is restarted before it wakes.
+    dispatch_generation: AtomicU64,
+}
+
+impl AppState {
+    /// Locks the scheduler; unlocking wakes the dispatch thread when the next
+    /// due action changed.
+    fn lock_scheduler(&self) -> LockResult<SchedulerGuard<'_>> {
+        let signal = &self.dispatch_signal;
+        self.scheduler
+            .lock()
+            .map(|scheduler| SchedulerGuard::new(scheduler, signal))
+            .map_err(|err| PoisonError::new(SchedulerGuard::new(err.into_inner(), signal)))
+    }
+}
+
+struct SchedulerGuard<'a> {
+    scheduler: MutexGuard<'a, QuantizedScheduler>,
+    next_due_ms: Option<u64>,
+    signal: &'a DispatchSignal,
+}
+
+impl<'a> SchedulerGuard<'a> {
+    fn new(scheduler: MutexGuard<'a, QuantizedScheduler>, signal: &'a DispatchSignal) -> Self {
+        Self {
+            next_due_ms: scheduler.next_due_ms(),
+            scheduler,
+            signal,
+        }
+    }
+}
+
+impl Deref for SchedulerGuard<'_> {
+    type Target = QuantizedScheduler;
+
+    fn deref(&self) -> &QuantizedScheduler {
+        &self.scheduler
+    }
+}
+
+impl DerefMut for SchedulerGuard<'_> {
+    fn deref_mut(&mut self) -> &mut QuantizedScheduler {
+        &mut self.scheduler
+    }
+}
+
+impl Drop for SchedulerGuard<'_> {
+    fn drop(&mut self) {
+        if self.scheduler.next_due_ms() != self.next_due_ms {
+            self.signal.notify();
+        }
+    }
+}
+
+/// Emits dispatched actions to the webview.
+struct TauriEventSink(AppHandle);
+
+impl ActionSink for TauriEventSink {
+    fn deliver(&mut self, action: &DispatchedAction) -> Result<(), String> {
+        self.0
+            .emit(ACTION_DISPATCHED_EVENT, action.clone())
+            .map_err(|err| err.to_string())
+    }
 }
 
 #[tauri::command]
@@ -33,6 +159,11 @@ fn import_theatre_bundle(
     state: State<'_, AppState>,
 ) -> Result<usize, String> {
     timeline::validate_bundle(&bundle).map_err(|err| err.to_string())?;
+    let tempo_state = {
+        let mut tempo = state.tempo.lock().map_err(|err| err.to_string())?;
+        tempo.set_meter(bundle.meter).map_err(|err| err.to_string())?
+    };
+    follow_tempo(&state, &tempo_state)?;
     let mut runtime = state
         .timeline_runtime
         .lock()
@@ -49,7 +180,28 @@ fn activate_timeline_section(section: String, state: State<'_, AppState>) -> Res
     if !runtime.sections().contains(&section) {
         return Err(timeline::TimelineError::UnknownSection(section).to_string());
     }
-    Ok(runtime.activate_section(section))
+    let previous = runtime.active_section().map(str::to_string);
+    let marker_count = runtime.activate_section(section.clone());
+    if runtime.sequencer().current().is_some() {
+        // A hand-picked section restarts the follow count from the bar it came in on.
+        let tempo_state = current_tempo(&state)?;
+        let bar = tempo_state.position_at(state.clock.now_ms()).bar;
+        runtime.sequencer_mut().start(section.clone(), bar);
+    }
+    drop(runtime);
+
+    if let Some(previous) = previous.filter(|previous| *previous != section) {
+        let tempo_state = current_tempo(&state)?;
+        let now = GridTarget::at_beat(tempo_state.beat_at(state.clock.now_ms() as f64), &tempo_state.meter);
+        let mut scheduler = state.lock_scheduler().map_err(|err| err.to_string())?;
+        scheduler.end_section_patterns(&tempo_state, &previous, &now);
+    }
+
+    let mut output = state.osc_output.lock().map_err(|err| err.to_string())?;
+    if let Some(broadcaster) = output.as_mut() {
+        broadcaster.send_section(&section, marker_count);
+    }
+    Ok(marker_count)
 }
 
 #[tauri::command]
@@ -65,27 +217,91 @@ fn list_timeline_markers(
 }
 
 #[tauri::command]
-fn get_tempo_state(state: State<'_, AppState>) -> Result<TempoState, String> {
-    let tempo = state.tempo.lock().map_err(|err| err.to_string())?;
-    Ok(tempo.state())
+fn get_clock_time(state: State<'_, AppState>) -> u64 {
+    state.clock.now_ms()
 }
 
 #[tauri::command]
-fn set_bpm(bpm: f64, state: State<'_, AppState>) -> Result<TempoState, String> {
+fn get_tempo_state(state: State<'_, AppState>) -> Result<TempoState, String> {
     let mut tempo = state.tempo.lock().map_err(|err| err.to_string())?;
-    Ok(tempo.set_bpm(bpm))
+    Ok(tempo.advance(None))
 }
 
 #[tauri::command]
-fn nudge_bpm(delta: f64, state: State<'_, AppState>) -> Result<TempoState, String> {
-    let mut tempo = state.tempo.lock().map_err(|err| err.to_string())?;
-    Ok(tempo.nudge_bpm(delta))
+fn set_bpm(bpm: f64, timestamp_ms: Option<u64>, state: State<'_, AppState>) -> Result<TempoState, String> {
+    let (tempo_state, proposal) = {
+        let mut tempo = state.tempo.lock().map_err(|err| err.to_string())?;
+        let now = timestamp_ms.unwrap_or_else(|| state.clock.now_ms());
+        let proposal = tempo.state().with_bpm_at(bpm, now);
+        (tempo.set_bpm(bpm, Some(now)), proposal)
+    };
+    follow_tempo(&state, &tempo_state)?;
+    propose_link_tempo(&state, &proposal)?;
+    Ok(tempo_state)
+}
+
+#[tauri::command]
+fn nudge_bpm(delta: f64, timestamp_ms: Option<u64>, state: State<'_, AppState>) -> Result<TempoState, String> {
+    let (tempo_state, proposal) = {
+        let mut tempo = state.tempo.lock().map_err(|err| err.to_string())?;
+        let now = timestamp_ms.unwrap_or_else(|| state.clock.now_ms());
+        let current = tempo.state();
+        let proposal = current.with_bpm_at(current.bpm_at(now) + delta, now);
+        (tempo.nudge_bpm(delta, Some(now)), proposal)
+    };
+    follow_tempo(&state, &tempo_state)?;
+    propose_link_tempo(&state, &proposal)?;
+    Ok(tempo_state)
+}
+
+#[tauri::command]
+fn ramp_bpm(
+    target_bpm: f64,
+    bars: u32,
+    ramp: Option<TempoRamp>,
+    timestamp_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<TempoState, String> {
+    let tempo_state = {
+        let mut tempo = state.tempo.lock().map_err(|err| err.to_string())?;
+        tempo
+            .ramp_bpm(target_bpm, bars, ramp.unwrap_or(TempoRamp::Linear), timestamp_ms)
+            .map_err(|err| err.to_string())?
+    };
+    follow_tempo(&state, &tempo_state)?;
+    Ok(tempo_state)
+}
+
+#[tauri::command]
+fn set_tempo_map(events: Vec<TempoEvent>, state: State<'_, AppState>) -> Result<TempoState, String> {
+    let tempo_state = {
+        let mut tempo = state.tempo.lock().map_err(|err| err.to_string())?;
+        tempo.set_tempo_map(events).map_err(|err| err.to_string())?
+    };
+    follow_tempo(&state, &tempo_state)?;
+    Ok(tempo_state)
 }
 
 #[tauri::command]
 fn tap_bpm(timestamp_ms: Option<u64>, state: State<'_, AppState>) -> Result<TempoState, String> {
+    let tempo_state = {
+        let mut tempo = state.tempo.lock().map_err(|err| err.to_string())?;
+        tempo.tap_bpm(timestamp_ms)
+    };
+    follow_tempo(&state, &tempo_state)?;
+    Ok(tempo_state)
+}
+
+#[tauri::command]
+fn get_tap_tempo_config(state: State<'_, AppState>) -> Result<TapTempoConfig, String> {
+    let tempo = state.tempo.lock().map_err(|err| err.to_string())?;
+    Ok(tempo.tap_config())
+}
+
+#[tauri::command]
+fn set_tap_tempo_config(config: TapTempoConfig, state: State<'_, AppState>) -> Result<TapTempoConfig, String> {
     let mut tempo = state.tempo.lock().map_err(|err| err.to_string())?;
-    Ok(tempo.tap_bpm(timestamp_ms))
+    Ok(tempo.set_tap_config(config))
 }
 
 #[tauri::command]
@@ -93,21 +309,391 @@ fn resync_downbeat(
     timestamp_ms: Option<u64>,
     state: State<'_, AppState>,
 ) -> Result<TempoState, String> {
+    let tempo_state = {
+        let mut tempo = state.tempo.lock().map_err(|err| err.to_string())?;
+        tempo.resync_downbeat(timestamp_ms)
+    };
+    follow_tempo(&state, &tempo_state)?;
+    Ok(tempo_state)
+}
+
+#[tauri::command]
+fn get_tempo_arbiter_status(state: State<'_, AppState>) -> Result<ArbiterStatus, String> {
+    let (status, tempo_state) = {
+        let mut tempo = state.tempo.lock().map_err(|err| err.to_string())?;
+        (tempo.arbiter_status(), tempo.state())
+    };
+    follow_tempo(&state, &tempo_state)?;
+    Ok(status)
+}
+
+#[tauri::command]
+fn get_tempo_arbiter_policy(state: State<'_, AppState>) -> Result<ArbiterPolicy, String> {
+    let tempo = state.tempo.lock().map_err(|err| err.to_string())?;
+    Ok(tempo.arbiter_policy())
+}
+
+#[tauri::command]
+fn set_tempo_arbiter_policy(policy: ArbiterPolicy, state: State<'_, AppState>) -> Result<ArbiterStatus, String> {
+    let (status, tempo_state) = {
+        let mut tempo = state.tempo.lock().map_err(|err| err.to_string())?;
+        (tempo.set_arbiter_policy(policy), tempo.state())
+    };
+    follow_tempo(&state, &tempo_state)?;
+    Ok(status)
+}
+
+#[tauri::command]
+fn release_tempo_source(source: TempoSource, state: State<'_, AppState>) -> Result<ArbiterStatus, String> {
+    let (status, tempo_state) = {
+        let mut tempo = state.tempo.lock().map_err(|err| err.to_string())?;
+        (tempo.release_source(&source), tempo.state())
+    };
+    follow_tempo(&state, &tempo_state)?;
+    Ok(status)
+}
+
+#[tauri::command]
+fn export_tempo_log(
+    format: TempoLogFormat,
+    reference: Option<DriftReference>,
+    state: State<'_, AppState>,
+) -> Result<TempoLogExport, String> {
+    let tempo = state.tempo.lock().map_err(|err| err.to_string())?;
+    tempo
+        .export_tempo_log(format, reference.as_ref())
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn clear_tempo_log(state: State<'_, AppState>) -> Result<(), String> {
     let mut tempo = state.tempo.lock().map_err(|err| err.to_string())?;
-    Ok(tempo.resync_downbeat(timestamp_ms))
+    tempo.clear_tempo_log();
+    Ok(())
+}
+
+#[tauri::command]
+fn set_meter(numerator: u32, denominator: u32, state: State<'_, AppState>) -> Result<TempoState, String> {
+    let meter = Meter { numerator, denominator };
+    let tempo_state = {
+        let mut tempo = state.tempo.lock().map_err(|err| err.to_string())?;
+        tempo.set_meter(meter).map_err(|err| err.to_string())?
+    };
+    follow_tempo(&state, &tempo_state)?;
+
+    if let Some(session) = state.link.lock().map_err(|err| err.to_string())?.as_mut() {
+        session.set_quantum_beats(meter.bar_beats());
+    }
+    if let Some(input) = state.midi_clock_input.lock().map_err(|err| err.to_string())?.as_mut() {
+        input.follower.set_meter(&meter);
+    }
+    Ok(tempo_state)
+}
+
+#[tauri::command]
+fn get_beat_position(timestamp_ms: Option<u64>, state: State<'_, AppState>) -> Result<BeatPosition, String> {
+    let tempo = state.tempo.lock().map_err(|err| err.to_string())?;
+    Ok(tempo.position_at(timestamp_ms))
+}
+
+#[tauri::command]
+fn enable_link(app: AppHandle, state: State<'_, AppState>) -> Result<LinkStatus, String> {
+    let mut link = state.link.lock().map_err(|err| err.to_string())?;
+    if let Some(session) = link.as_ref() {
+        return Ok(session.status());
+    }
+
+    let tempo_state = {
+        let tempo = state.tempo.lock().map_err(|err| err.to_string())?;
+        tempo.state()
+    };
+    let config = LinkConfig {
+        quantum_beats: tempo_state.meter.bar_beats(),
+        ..LinkConfig::default()
+    };
+    let (session, receiver) =
+        LinkSession::bind(config, &tempo_state, state.clock.now_us()).map_err(|err| err.to_string())?;
+    let status = session.status();
+    let node_id = session.node_id();
+    *link = Some(session);
+
+    thread::spawn(move || run_link_loop(app, node_id, receiver));
+    Ok(status)
+}
+
+#[tauri::command]
+fn disable_link(state: State<'_, AppState>) -> Result<(), String> {
+    let tempo_state = {
+        let mut tempo = state.tempo.lock().map_err(|err| err.to_string())?;
+        tempo.release_source(&TempoSource::Link);
+        tempo.state()
+    };
+    follow_tempo(&state, &tempo_state)?;
+    let mut link = state.link.lock().map_err(|err| err.to_string())?;
+    match link.take() {
+        Some(session) => session.leave().map_err(|err| err.to_string()),
+        None => Ok(()),
+    }
+}
+
+#[tauri::command]
+fn get_link_status(state: State<'_, AppState>) -> Result<Option<LinkStatus>, String> {
+    let link = state.link.lock().map_err(|err| err.to_string())?;
+    Ok(link.as_ref().map(LinkSession::status))
+}
+
+#[tauri::command]
+fn start_midi_clock_input(device: String, app: AppHandle, state: State<'_, AppState>) -> Result<(), String> {
+    let mut input = state.midi_clock_input.lock().map_err(|err| err.to_string())?;
+    if input.is_some() {
+        return Err("midi clock input is already running".to_string());
+    }
+
+    let meter = {
+        let tempo = state.tempo.lock().map_err(|err| err.to_string())?;
+        tempo.state().meter
+    };
+    let file = File::open(&device).map_err(|err| format!("{device}: {err}"))?;
+    let mut follower = MidiClockFollower::default();
+    follower.set_meter(&meter);
+    let stopped = Arc::new(AtomicBool::new(false));
+
+    let clock = state.clock.clone();
+    let source = DeviceSource::new(file, move || clock.now_us());
+    let reader_stopped = stopped.clone();
+    let reader = thread::spawn(move || run_midi_clock_input(app, source, reader_stopped));
+    *input = Some(MidiClockInput {
+        follower,
+        stopped,
+        reader: Some(reader),
+    });
+    Ok(())
+}
+
+#[tauri::command]
+fn stop_midi_clock_input(state: State<'_, AppState>) -> Result<(), String> {
+    let input = {
+        let mut input = state.midi_clock_input.lock().map_err(|err| err.to_string())?;
+        input.take().inspect(|input| input.stopped.store(true, Ordering::SeqCst))
+    };
+    // The reader notices within one device poll and closes the device as it
+    // exits; the source is released after, so it cannot offer a last update.
+    // Without `poll` a read only returns on the next byte, so it is left to exit then.
+    if let Some(reader) = input.and_then(|input| input.reader).filter(|_| cfg!(unix)) {
+        reader.join().map_err(|_| "midi clock reader panicked".to_string())?;
+    }
+    let tempo_state = {
+        let mut tempo = state.tempo.lock().map_err(|err| err.to_string())?;
+        tempo.release_source(&TempoSource::MidiClock);
+        tempo.state()
+    };
+    follow_tempo(&state, &tempo_state)
+}
+
+#[tauri::command]
+fn get_midi_clock_status(state: State<'_, AppState>) -> Result<Option<MidiClockUpdate>, String> {
+    let input = state.midi_clock_input.lock().map_err(|err| err.to_string())?;
+    Ok(input.as_ref().map(|input| input.follower.update()))
+}
+
+#[tauri::command]
+fn start_midi_clock_output(device: String, app: AppHandle, state: State<'_, AppState>) -> Result<(), String> {
+    let tempo_state = {
+        let tempo = state.tempo.lock().map_err(|err| err.to_string())?;
+        tempo.state()
+    };
+
+    let mut output = state.midi_clock_output.lock().map_err(|err| err.to_string())?;
+    if output.is_some() {
+        return Err("midi clock output is already running".to_string());
+    }
+
+    let file = OpenOptions::new()
+        .write(true)
+        .open(&device)
+        .map_err(|err| format!("{device}: {err}"))?;
+    let mut clock_output = MidiClockOutput {
+        generator: MidiClockGenerator::default(),
+        sink: WriterSink::spawn(file, state.clock.clone()).map_err(|err| err.to_string())?,
+    };
+    clock_output
+        .generator
+        .start(&tempo_state, state.clock.now_us(), &mut clock_output.sink)
+        .map_err(|err| err.to_string())?;
+    *output = Some(clock_output);
+
+    thread::spawn(move || run_midi_clock_output(app));
+    Ok(())
+}
+
+#[tauri::command]
+fn stop_midi_clock_output(state: State<'_, AppState>) -> Result<(), String> {
+    let mut output = state.midi_clock_output.lock().map_err(|err| err.to_string())?;
+    match output.take() {
+        Some(mut clock_output) => clock_output
+            .generator
+            .stop(state.clock.now_us(), &mut clock_output.sink)
+            .map_err(|err| err.to_string()),
+        None => Ok(()),
+    }
+}
+
+#[tauri::command]
+fn push_auto_tempo_samples(
+    samples: Vec<f32>,
+    sample_rate: u32,
+    timestamp_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<Option<TempoEstimate>, String> {
+    let (estimate, accepted) = {
+        let mut auto_tempo = state.auto_tempo.lock().map_err(|err| err.to_string())?;
+        let started = auto_tempo
+            .as_ref()
+            .is_none_or(|auto_tempo| auto_tempo.tracker.sample_rate() != sample_rate);
+        if started {
+            let duration_ms = samples.len() as u64 * 1_000 / u64::from(sample_rate.max(1));
+            let origin_ms = timestamp_ms.unwrap_or_else(|| state.clock.now_ms().saturating_sub(duration_ms));
+            *auto_tempo = Some(AutoTempo {
+                tracker: BeatTracker::new(sample_rate, origin_ms),
+                follower: AutoTempoFollower::default(),
+            });
+        }
+
+        let Some(auto_tempo) = auto_tempo.as_mut() else {
+            return Ok(None);
+        };
+        auto_tempo.tracker.push_samples(&samples);
+        let estimate = auto_tempo.tracker.estimate();
+        let accepted = estimate
+            .as_ref()
+            .and_then(|estimate| auto_tempo.follower.offer(estimate));
+        (estimate, accepted)
+    };
+
+    let tempo_state = {
+        let mut tempo = state.tempo.lock().map_err(|err| err.to_string())?;
+        // Offered like any other source; the operator keeps the grid until they release it.
+        if let Some(accepted) = accepted {
+            tempo.sync_external_beat(TempoSource::Auto, accepted.bpm, accepted.beat_epoch_ms, accepted.confidence);
+        } else if let Some(estimate) = &estimate {
+            tempo.refresh_source(&TempoSource::Auto, estimate.confidence);
+        }
+        tempo.state()
+    };
+    follow_tempo(&state, &tempo_state)?;
+    Ok(estimate)
+}
+
+#[tauri::command]
+fn reset_auto_tempo(state: State<'_, AppState>) -> Result<(), String> {
+    let tempo_state = {
+        let mut tempo = state.tempo.lock().map_err(|err| err.to_string())?;
+        tempo.release_source(&TempoSource::Auto);
+        tempo.state()
+    };
+    follow_tempo(&state, &tempo_state)?;
+    let mut auto_tempo = state.auto_tempo.lock().map_err(|err| err.to_string())?;
+    *auto_tempo = None;
+    Ok(())
+}
+
+#[tauri::command]
+fn analyze_tempo_file(path: String) -> Result<Option<TempoEstimate>, String> {
+    let file = File::open(&path).map_err(|err| format!("{path}: {err}"))?;
+    let (sample_rate, samples) = read_wav_mono(file).map_err(|err| format!("{path}: {err}"))?;
+    let mut tracker = BeatTracker::new(sample_rate, 0);
+    tracker.push_samples(&samples);
+    Ok(tracker.estimate())
+}
+
+#[tauri::command]
+fn start_osc_server(
+    port: Option<u16>,
+    reply_port: Option<u16>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<OscStatus, String> {
+    let mut osc = state.osc.lock().map_err(|err| err.to_string())?;
+    if let Some(server) = osc.as_ref() {
+        return Ok(server.status());
+    }
+
+    let config = OscConfig {
+        port: port.unwrap_or(OSC_PORT),
+        reply_port,
+    };
+    let server = OscServer::bind(config).map_err(|err| err.to_string())?;
+    let status = server.status();
+    *osc = Some(server);
+
+    thread::spawn(move || run_osc_server(app));
+    Ok(status)
+}
+
+#[tauri::command]
+fn stop_osc_server(state: State<'_, AppState>) -> Result<(), String> {
+    let mut osc = state.osc.lock().map_err(|err| err.to_string())?;
+    *osc = None;
+    Ok(())
+}
+
+#[tauri::command]
+fn get_osc_status(state: State<'_, AppState>) -> Result<Option<OscStatus>, String> {
+    let osc = state.osc.lock().map_err(|err| err.to_string())?;
+    Ok(osc.as_ref().map(OscServer::status))
+}
+
+#[tauri::command]
+fn start_osc_output(
+    config: OscOutputConfig,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<OscOutputStatus, String> {
+    let mut output = state.osc_output.lock().map_err(|err| err.to_string())?;
+    let running = output.is_some();
+    let broadcaster = OscBroadcaster::bind(config).map_err(|err| err.to_string())?;
+    let status = broadcaster.status();
+    *output = Some(broadcaster);
+
+    if !running {
+        thread::spawn(move || run_osc_output(app));
+    }
+    Ok(status)
+}
+
+#[tauri::command]
+fn stop_osc_output(state: State<'_, AppState>) -> Result<(), String> {
+    let mut output = state.osc_output.lock().map_err(|err| err.to_string())?;
+    *output = None;
+    Ok(())
+}
+
+#[tauri::command]
+fn get_osc_output_status(state: State<'_, AppState>) -> Result<Option<OscOutputStatus>, String> {
+    let output = state.osc_output.lock().map_err(|err| err.to_string())?;
+    Ok(output.as_ref().map(OscBroadcaster::status))
 }
 
 #[tauri::command]
 fn set_quantization(grid: QuantizeGrid, state: State<'_, AppState>) -> Result<QuantizeGrid, String> {
-    let mut scheduler = state.scheduler.lock().map_err(|err| err.to_string())?;
+    let mut scheduler = state.lock_scheduler().map_err(|err| err.to_string())?;
     Ok(scheduler.set_grid(grid))
 }
 
+#[tauri::command]
+fn set_phrase_origin(bar: i64, state: State<'_, AppState>) -> Result<i64, String> {
+    let mut scheduler = state.lock_scheduler().map_err(|err| err.to_string())?;
+    Ok(scheduler.set_phrase_origin(bar))
+}
+
 #[tauri::command]
 fn queue_preview_action(
     action: String,
     section: Option<String>,
     quantize: Option<QuantizeGrid>,
+    priority: Option<i32>,
+    origin: Option<ActionOrigin>,
+    payload: Option<serde_json::Value>,
     state: State<'_, AppState>,
 ) -> Result<ScheduledAction, String> {
     let tempo_state = {
@@ -115,20 +701,27 @@ fn queue_preview_action(
         tempo.state()
     };
 
-    let mut scheduler = state.scheduler.lock().map_err(|err| err.to_string())?;
-    Ok(scheduler.schedule(
-        now_ms(),
-        tempo_state.bpm,
-        tempo_state.downbeat_epoch_ms,
-        quantize,
-        action,
-        section,
-    ))
+    let mut scheduler = state.lock_scheduler().map_err(|err| err.to_string())?;
+    scheduler
+        .submit(
+            &tempo_state,
+            ActionRequest {
+                payload: payload.unwrap_or_default(),
+                section,
+                quantize,
+                priority: priority.unwrap_or_default(),
+                origin: origin.unwrap_or(ActionOrigin::Manual),
+                ..ActionRequest::named(action)
+            },
+        )
+        .map_err(|err| err.to_string())
 }
 
 #[tauri::command]
 fn queue_section_markers(
     section: Option<String>,
+    playback: Option<SectionPlayback>,
+    start: Option<QuantizeGrid>,
     state: State<'_, AppState>,
 ) -> Result<usize, String> {
     let markers = {
@@ -144,34 +737,156 @@ fn queue_section_markers(
         tempo.state()
     };
 
-    let mut scheduler = state.scheduler.lock().map_err(|err| err.to_string())?;
-    for marker in &markers {
-        scheduler.schedule(
-            now_ms(),
-            tempo_state.bpm,
-            tempo_state.downbeat_epoch_ms,
-            Some(marker.quantize.clone()),
-            cue_action_name(&marker.action),
-            Some(marker.section.clone()),
-        );
-    }
+    let mut scheduler = state.lock_scheduler().map_err(|err| err.to_string())?;
+    let queued = match playback.unwrap_or_default() {
+        SectionPlayback::Sequence => {
+            let start = start.unwrap_or(QuantizeGrid::Whole);
+            scheduler.schedule_section(&tempo_state, &markers, &start).len()
+        }
+        SectionPlayback::NextBoundary => markers
+            .iter()
+            .filter(|marker| scheduler.submit(&tempo_state, ActionRequest::from_marker(marker)).is_ok())
+            .count(),
+    };
 
-    Ok(markers.len())
+    Ok(queued)
 }
 
 #[tauri::command]
 fn list_scheduled_actions(state: State<'_, AppState>) -> Result<Vec<ScheduledAction>, String> {
-    let scheduler = state.scheduler.lock().map_err(|err| err.to_string())?;
+    let scheduler = state.lock_scheduler().map_err(|err| err.to_string())?;
     Ok(scheduler.list())
 }
 
+#[tauri::command]
+fn cancel_scheduled_action(id: u64, state: State<'_, AppState>) -> Result<Option<ScheduledAction>, String> {
+    let mut scheduler = state.lock_scheduler().map_err(|err| err.to_string())?;
+    Ok(scheduler.remove(id))
+}
+
+#[tauri::command]
+fn cancel_scheduled_actions(
+    section: Option<String>,
+    action: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<Vec<ScheduledAction>, String> {
+    if section.is_none() && action.is_none() {
+        return Err("cancel needs a section or an action; use flush_scheduled_actions to clear the queue".to_string());
+    }
+    let mut scheduler = state.lock_scheduler().map_err(|err| err.to_string())?;
+    Ok(scheduler.cancel_matching(section.as_deref(), action.as_deref()))
+}
+
+#[tauri::command]
+fn replace_scheduled_action(
+    id: u64,
+    action: String,
+    section: Option<String>,
+    payload: Option<serde_json::Value>,
+    state: State<'_, AppState>,
+) -> Result<ScheduledAction, String> {
+    let mut scheduler = state.lock_scheduler().map_err(|err| err.to_string())?;
+    scheduler
+        .replace(id, action, section, payload.unwrap_or_default())
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn flush_scheduled_actions(state: State<'_, AppState>) -> Result<Vec<ScheduledAction>, String> {
+    let mut scheduler = state.lock_scheduler().map_err(|err| err.to_string())?;
+    Ok(scheduler.flush())
+}
+
+#[tauri::command]
+fn panic_scheduler(state: State<'_, AppState>) -> Result<ScheduledAction, String> {
+    let mut scheduler = state.lock_scheduler().map_err(|err| err.to_string())?;
+    Ok(scheduler.panic())
+}
+
+#[tauri::command]
+fn start_action_pattern(
+    pattern: ActionPattern,
+    start: Option<QuantizeGrid>,
+    state: State<'_, AppState>,
+) -> Result<RunningPattern, String> {
+    let tempo_state = current_tempo(&state)?;
+    let mut scheduler = state.lock_scheduler().map_err(|err| err.to_string())?;
+    scheduler
+        .start_pattern(&tempo_state, pattern, &start.unwrap_or(QuantizeGrid::Whole))
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn list_action_patterns(state: State<'_, AppState>) -> Result<Vec<RunningPattern>, String> {
+    let scheduler = state.lock_scheduler().map_err(|err| err.to_string())?;
+    Ok(scheduler.patterns())
+}
+
+#[tauri::command]
+fn cancel_action_pattern(id: u64, state: State<'_, AppState>) -> Result<Option<RunningPattern>, String> {
+    let mut scheduler = state.lock_scheduler().map_err(|err| err.to_string())?;
+    Ok(scheduler.cancel_pattern(id))
+}
+
+#[tauri::command]
+fn get_conflict_policy(state: State<'_, AppState>) -> Result<ConflictPolicy, String> {
+    let scheduler = state.lock_scheduler().map_err(|err| err.to_string())?;
+    Ok(scheduler.conflict_policy())
+}
+
+#[tauri::command]
+fn set_conflict_policy(policy: ConflictPolicy, state: State<'_, AppState>) -> Result<ConflictPolicy, String> {
+    let mut scheduler = state.lock_scheduler().map_err(|err| err.to_string())?;
+    Ok(scheduler.set_conflict_policy(policy))
+}
+
+#[tauri::command]
+fn get_conflict_decisions(state: State<'_, AppState>) -> Result<Vec<ConflictDecision>, String> {
+    let scheduler = state.lock_scheduler().map_err(|err| err.to_string())?;
+    Ok(scheduler.conflict_decisions())
+}
+
 #[tauri::command]
 fn pop_due_actions(
     timestamp_ms: Option<u64>,
     state: State<'_, AppState>,
 ) -> Result<Vec<ScheduledAction>, String> {
-    let mut scheduler = state.scheduler.lock().map_err(|err| err.to_string())?;
-    Ok(scheduler.pop_due(timestamp_ms.unwrap_or_else(now_ms)))
+    // Held throughout so the dispatch thread cannot start or stop and miss these actions.
+    let mut dispatcher = state.dispatcher.lock().map_err(|err| err.to_string())?;
+    let due = {
+        // The scheduler stays locked until OSC output knows what left the queue.
+        let mut scheduler = state.lock_scheduler().map_err(|err| err.to_string())?;
+        let due = scheduler.pop_due(timestamp_ms);
+        let mut output = state.osc_output.lock().map_err(|err| err.to_string())?;
+        if let Some(broadcaster) = output.as_mut() {
+            broadcaster.send_actions(&due);
+        }
+        due
+    };
+    // A running dispatcher emits and times the flushed actions like its own.
+    if let Some(dispatcher) = dispatcher.as_mut() {
+        dispatcher.dispatch(due.clone());
+    }
+    Ok(due)
+}
+
+#[tauri::command]
+fn start_action_dispatch(app: AppHandle) -> Result<DispatchStats, String> {
+    spawn_action_dispatch(&app)
+}
+
+#[tauri::command]
+fn stop_action_dispatch(state: State<'_, AppState>) -> Result<(), String> {
+    let mut dispatcher = state.dispatcher.lock().map_err(|err| err.to_string())?;
+    *dispatcher = None;
+    state.dispatch_signal.notify();
+    Ok(())
+}
+
+#[tauri::command]
+fn get_dispatch_stats(state: State<'_, AppState>) -> Result<Option<DispatchStats>, String> {
+    let dispatcher = state.dispatcher.lock().map_err(|err| err.to_string())?;
+    Ok(dispatcher.as_ref().map(ActionDispatcher::stats))
 }
 
 #[tauri::command]
@@ -207,44 +922,773 @@ fn set_renderer_backend(
     runtime.set_renderer(backend).map_err(|err| err.to_string())
 }
 
-fn cue_action_name(action: &CueAction) -> String {
-    match action {
-        CueAction::TriggerClip => "trigger_clip".to_string(),
-        CueAction::ApplyAccent => "apply_accent".to_string(),
-        CueAction::SwapScene => "swap_scene".to_string(),
+/// Keeps queued actions on their beats after the tempo grid changes.
+fn follow_tempo(state: &AppState, tempo_state: &TempoState) -> Result<(), String> {
+    let mut scheduler = state.lock_scheduler().map_err(|err| err.to_string())?;
+    scheduler.follow_tempo(tempo_state);
+    drop(scheduler);
+    let mut transport = state.transport.lock().map_err(|err| err.to_string())?;
+    transport.follow_tempo(tempo_state);
+    Ok(())
+}
+
+fn current_tempo(state: &AppState) -> Result<TempoState, String> {
+    let tempo = state.tempo.lock().map_err(|err| err.to_string())?;
+    Ok(tempo.state())
+}
+
+#[tauri::command]
+fn get_transport_status(state: State<'_, AppState>) -> Result<TransportStatus, String> {
+    let tempo_state = current_tempo(&state)?;
+    let transport = state.transport.lock().map_err(|err| err.to_string())?;
+    Ok(transport.status(&tempo_state))
+}
+
+#[tauri::command]
+fn transport_play(app: AppHandle, state: State<'_, AppState>) -> Result<TransportStatus, String> {
+    let tempo_state = current_tempo(&state)?;
+    let change = {
+        let mut transport = state.transport.lock().map_err(|err| err.to_string())?;
+        transport.play(&tempo_state)
+    };
+    apply_transport_change(&app, &state, &tempo_state, change)
+}
+
+#[tauri::command]
+fn transport_pause(app: AppHandle, state: State<'_, AppState>) -> Result<TransportStatus, String> {
+    let tempo_state = current_tempo(&state)?;
+    let change = {
+        let mut transport = state.transport.lock().map_err(|err| err.to_string())?;
+        transport.pause(&tempo_state)
+    };
+    apply_transport_change(&app, &state, &tempo_state, change)
+}
+
+#[tauri::command]
+fn transport_stop(app: AppHandle, state: State<'_, AppState>) -> Result<TransportStatus, String> {
+    let tempo_state = current_tempo(&state)?;
+    let change = {
+        let mut transport = state.transport.lock().map_err(|err| err.to_string())?;
+        transport.stop(&tempo_state)
+    };
+    apply_transport_change(&app, &state, &tempo_state, Some(change))
+}
+
+#[tauri::command]
+fn transport_locate(bar: i64, app: AppHandle, state: State<'_, AppState>) -> Result<TransportStatus, String> {
+    let tempo_state = current_tempo(&state)?;
+    let change = {
+        let mut transport = state.transport.lock().map_err(|err| err.to_string())?;
+        transport.locate(&tempo_state, bar).map_err(|err| err.to_string())?
+    };
+    apply_transport_change(&app, &state, &tempo_state, Some(change))
+}
+
+#[tauri::command]
+fn transport_set_loop(
+    region: Option<LoopRegion>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<TransportStatus, String> {
+    let tempo_state = current_tempo(&state)?;
+    let change = {
+        let mut transport = state.transport.lock().map_err(|err| err.to_string())?;
+        transport.set_loop(&tempo_state, region).map_err(|err| err.to_string())?
+    };
+    apply_transport_change(&app, &state, &tempo_state, Some(change))
+}
+
+/// Requeues the active section from the new playhead and reports the change.
+/// A jump withdraws the section's pending actions first; a loop wrap queues the
+/// pass after the one just started, so the queue stays a pass ahead.
+fn apply_transport_change(
+    app: &AppHandle,
+    state: &AppState,
+    tempo_state: &TempoState,
+    change: Option<TransportChange>,
+) -> Result<TransportStatus, String> {
+    let transport = state.transport.lock().map_err(|err| err.to_string())?.clone();
+    let Some(change) = change else {
+        return Ok(transport.status(tempo_state));
+    };
+
+    let (section, markers) = {
+        let runtime = state
+            .timeline_runtime
+            .lock()
+            .map_err(|err| err.to_string())?;
+        let section = runtime.active_section().map(str::to_string);
+        let markers = runtime.list_markers(section.clone());
+        (section, markers)
+    };
+    if let Some(section) = section {
+        let mut scheduler = state.lock_scheduler().map_err(|err| err.to_string())?;
+        let passes = if change.kind == TransportChangeKind::Wrap {
+            change.status.pass + 1..=change.status.pass + 1
+        } else {
+            scheduler.withdraw_section(&section);
+            0..=1
+        };
+        for pass in passes {
+            scheduler.schedule_transport_pass(tempo_state, &transport, &markers, pass);
+        }
     }
+
+    app.emit(TRANSPORT_CHANGED_EVENT, change.clone())
+        .map_err(|err| err.to_string())?;
+    Ok(change.status)
+}
+
+#[tauri::command]
+fn get_setlist_status(state: State<'_, AppState>) -> Result<SetlistStatus, String> {
+    let runtime = state
+        .timeline_runtime
+        .lock()
+        .map_err(|err| err.to_string())?;
+    Ok(runtime.sequencer().status())
+}
+
+#[tauri::command]
+fn set_setlist(setlist: Setlist, state: State<'_, AppState>) -> Result<SetlistStatus, String> {
+    let mut runtime = state
+        .timeline_runtime
+        .lock()
+        .map_err(|err| err.to_string())?;
+    runtime.set_setlist(setlist).map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn set_follow_action(
+    section: String,
+    follow: Option<SectionFollow>,
+    state: State<'_, AppState>,
+) -> Result<SetlistStatus, String> {
+    let mut runtime = state
+        .timeline_runtime
+        .lock()
+        .map_err(|err| err.to_string())?;
+    runtime
+        .set_follow_action(section, follow)
+        .map_err(|err| err.to_string())
 }
 
-fn now_ms() -> u64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map(|duration| duration.as_millis() as u64)
-        .unwrap_or(0)
+#[tauri::command]
+fn override_next_section(section: Option<String>, state: State<'_, AppState>) -> Result<SetlistStatus, String> {
+    let mut runtime = state
+        .timeline_runtime
+        .lock()
+        .map_err(|err| err.to_string())?;
+    if let Some(section) = &section {
+        if !runtime.sections().contains(section) {
+            return Err(timeline::TimelineError::UnknownSection(section.clone()).to_string());
+        }
+    }
+    runtime.sequencer_mut().override_next(section);
+    Ok(runtime.sequencer().status())
+}
+
+#[tauri::command]
+fn start_setlist(section: Option<String>, app: AppHandle, state: State<'_, AppState>) -> Result<SetlistStatus, String> {
+    let tempo_state = current_tempo(&state)?;
+    let mut runtime = state
+        .timeline_runtime
+        .lock()
+        .map_err(|err| err.to_string())?;
+    let setlist = runtime.sequencer().setlist().clone();
+    let section = section
+        .or_else(|| setlist.sections.first().cloned())
+        .ok_or_else(|| "setlist is empty".to_string())?;
+    if !runtime.sections().contains(&section) {
+        return Err(timeline::TimelineError::UnknownSection(section).to_string());
+    }
+
+    // Sections start on a bar line, or on the setlist's phrase grid.
+    let grid = match setlist.quantize.phrase_bars() {
+        Some(_) => setlist.quantize,
+        None => QuantizeGrid::Whole,
+    };
+    let start = {
+        let scheduler = state.lock_scheduler().map_err(|err| err.to_string())?;
+        scheduler.next_boundary(&tempo_state, &grid)
+    };
+    let from = runtime.active_section().map(str::to_string);
+    runtime.sequencer_mut().start(section.clone(), start.bar);
+    drop(runtime);
+    let change = SectionChange::new(from, section, grid, start, &tempo_state);
+    enter_section(&app, &state, &tempo_state, change)?;
+    get_setlist_status(state)
+}
+
+#[tauri::command]
+fn stop_setlist(state: State<'_, AppState>) -> Result<SetlistStatus, String> {
+    let mut runtime = state
+        .timeline_runtime
+        .lock()
+        .map_err(|err| err.to_string())?;
+    runtime.sequencer_mut().stop();
+    Ok(runtime.sequencer().status())
+}
+
+#[tauri::command]
+fn queue_section_change(
+    section: String,
+    quantize: Option<QuantizeGrid>,
+    state: State<'_, AppState>,
+) -> Result<SectionChange, String> {
+    let tempo_state = current_tempo(&state)?;
+    let from = {
+        let runtime = state
+            .timeline_runtime
+            .lock()
+            .map_err(|err| err.to_string())?;
+        if !runtime.sections().contains(&section) {
+            return Err(timeline::TimelineError::UnknownSection(section).to_string());
+        }
+        runtime.active_section().map(str::to_string)
+    };
+    let mut scheduler = state.lock_scheduler().map_err(|err| err.to_string())?;
+    let quantize = quantize.unwrap_or(QuantizeGrid::Whole);
+    Ok(scheduler.queue_section_change(&tempo_state, from, section, quantize))
+}
+
+#[tauri::command]
+fn get_section_change(state: State<'_, AppState>) -> Result<Option<SectionChange>, String> {
+    let scheduler = state.lock_scheduler().map_err(|err| err.to_string())?;
+    Ok(scheduler.section_change())
+}
+
+#[tauri::command]
+fn cancel_section_change(state: State<'_, AppState>) -> Result<Option<SectionChange>, String> {
+    let mut scheduler = state.lock_scheduler().map_err(|err| err.to_string())?;
+    Ok(scheduler.cancel_section_change())
+}
+
+/// Activates the incoming section of `change` and hands the queue over to it
+/// at the change boundary. The outgoing section is whichever is active now, not
+/// the one active when the change was queued.
+fn enter_section(
+    app: &AppHandle,
+    state: &AppState,
+    tempo_state: &TempoState,
+    mut change: SectionChange,
+) -> Result<(), String> {
+    let (marker_count, markers, setlist) = {
+        let mut runtime = state
+            .timeline_runtime
+            .lock()
+            .map_err(|err| err.to_string())?;
+        change.from = runtime.active_section().map(str::to_string);
+        let marker_count = runtime.activate_section(change.to.clone());
+        let markers = runtime.list_markers(Some(change.to.clone()));
+        let sequencer = runtime.sequencer();
+        (marker_count, markers, sequencer.current().map(|_| sequencer.status()))
+    };
+    {
+        let mut scheduler = state.lock_scheduler().map_err(|err| err.to_string())?;
+        scheduler.switch_section(tempo_state, &change, &markers);
+    }
+    {
+        let mut output = state.osc_output.lock().map_err(|err| err.to_string())?;
+        if let Some(broadcaster) = output.as_mut() {
+            broadcaster.send_section_change(&change, marker_count);
+        }
+    }
+    app.emit(SECTION_CHANGED_EVENT, change)
+        .map_err(|err| err.to_string())?;
+    if let Some(status) = setlist {
+        app.emit(SETLIST_CHANGED_EVENT, status)
+            .map_err(|err| err.to_string())?;
+    }
+    Ok(())
+}
+
+/// Carries out a queued section change just ahead of its boundary. A running
+/// setlist counts the incoming section's bars from there.
+fn apply_section_change(app: &AppHandle, state: &AppState, tempo_state: &TempoState) -> Result<(), String> {
+    let change = {
+        let mut scheduler = state.lock_scheduler().map_err(|err| err.to_string())?;
+        scheduler.take_due_section_change(SECTION_LEAD_MS)
+    };
+    let Some(change) = change else {
+        return Ok(());
+    };
+    {
+        let mut runtime = state
+            .timeline_runtime
+            .lock()
+            .map_err(|err| err.to_string())?;
+        if runtime.sequencer().current().is_some() {
+            runtime.sequencer_mut().start(change.to.clone(), change.at.bar);
+        }
+    }
+    enter_section(app, state, tempo_state, change)
+}
+
+/// Starts the next setlist section once the playing one has run its bars.
+fn advance_setlist(app: &AppHandle, state: &AppState, tempo_state: &TempoState) -> Result<(), String> {
+    let now_ms = state.clock.now_ms();
+    let change = {
+        let mut runtime = state
+            .timeline_runtime
+            .lock()
+            .map_err(|err| err.to_string())?;
+        let Some(due_bar) = runtime.sequencer().due_bar() else {
+            return Ok(());
+        };
+        let due = GridTarget {
+            bar: due_bar,
+            beat: 1,
+            tick: 0,
+        };
+        let due_ms = tempo_state.time_at_beat(due.beats(&tempo_state.meter));
+        if due_ms > (now_ms + SECTION_LEAD_MS) as f64 {
+            return Ok(());
+        }
+        // A section that overran while advance was off comes in on the next bar, not in the past.
+        let bar = if due_ms < now_ms as f64 {
+            tempo_state.position_at(now_ms).bar + 1
+        } else {
+            due_bar
+        };
+        let from = runtime.sequencer().current().map(str::to_string);
+        let Some(section) = runtime.sequencer_mut().advance(bar) else {
+            return Ok(());
+        };
+        let quantize = runtime.sequencer().setlist().quantize.clone();
+        let start = GridTarget { bar, beat: 1, tick: 0 };
+        SectionChange::new(from, section, quantize, start, tempo_state)
+    };
+    enter_section(app, state, tempo_state, change)
+}
+
+/// Reports loop wraps as they happen, keeps the looped section queued, carries
+/// out queued section changes, advances the setlist and keeps patterns playing.
+fn run_transport_watch(app: AppHandle) {
+    let state = app.state::<AppState>();
+    loop {
+        thread::sleep(TRANSPORT_POLL_INTERVAL);
+        // A source that went quiet hands over here, as nothing else reports.
+        let Ok(handed_over) = state.tempo.lock().map(|mut tempo| tempo.elect()) else {
+            return;
+        };
+        if let Some(tempo_state) = handed_over {
+            let _ = follow_tempo(&state, &tempo_state);
+        }
+        let Ok(tempo_state) = current_tempo(&state) else {
+            return;
+        };
+        let Ok(change) = state.transport.lock().map(|mut transport| transport.poll(&tempo_state)) else {
+            return;
+        };
+        if change.is_some() {
+            let _ = apply_transport_change(&app, &state, &tempo_state, change);
+        }
+        let _ = apply_section_change(&app, &state, &tempo_state);
+        let _ = advance_setlist(&app, &state, &tempo_state);
+        let _ = state
+            .lock_scheduler()
+            .map(|mut scheduler| scheduler.refill_patterns(&tempo_state));
+    }
+}
+
+fn propose_link_tempo(state: &AppState, tempo_state: &TempoState) -> Result<(), String> {
+    let mut link = state.link.lock().map_err(|err| err.to_string())?;
+    match link.as_mut() {
+        Some(session) => session
+            .propose(tempo_state, state.clock.now_us())
+            .map_err(|err| err.to_string()),
+        None => Ok(()),
+    }
+}
+
+/// Runs the session `node_id` until it is left; a session joined after it
+/// has a new node id and its own loop. Waiting on the receiver paces the loop
+/// and stamps pings and pongs as they arrive.
+fn run_link_loop(app: AppHandle, node_id: NodeId, receiver: LinkReceiver) {
+    let state = app.state::<AppState>();
+    loop {
+        let datagram = match receiver.receive(LINK_POLL_INTERVAL, || state.clock.now_us()) {
+            Ok(datagram) => datagram,
+            Err(_) => {
+                thread::sleep(LINK_POLL_INTERVAL);
+                None
+            }
+        };
+        let now_us = state.clock.now_us();
+
+        let (adopted, has_peers) = {
+            let Ok(mut link) = state.link.lock() else {
+                return;
+            };
+            let Some(session) = link.as_mut().filter(|session| session.node_id() == node_id) else {
+                return;
+            };
+            let handled = datagram.is_some_and(|datagram| session.handle(&datagram).unwrap_or(false));
+            let polled = session.poll(now_us).ok().flatten().is_some();
+            let adopted = (handled || polled).then(|| (session.timeline().bpm(), session.downbeat_epoch_ms(now_us)));
+            (adopted, session.status().peer_count > 0)
+        };
+
+        let Ok(mut tempo) = state.tempo.lock() else {
+            return;
+        };
+        let followed = match adopted {
+            Some((bpm, downbeat_epoch_ms)) => Some(tempo.sync_external(TempoSource::Link, bpm, downbeat_epoch_ms, 1.0)),
+            None if has_peers => tempo.refresh_source(&TempoSource::Link, 1.0),
+            None => None,
+        };
+        drop(tempo);
+        if let Some(tempo_state) = followed {
+            let _ = follow_tempo(&state, &tempo_state);
+        }
+    }
+}
+
+fn spawn_action_dispatch(app: &AppHandle) -> Result<DispatchStats, String> {
+    let state = app.state::<AppState>();
+    let mut dispatcher = state.dispatcher.lock().map_err(|err| err.to_string())?;
+    if let Some(dispatcher) = dispatcher.as_ref() {
+        return Ok(dispatcher.stats());
+    }
+
+    let action_dispatcher = ActionDispatcher::new(state.clock.clone(), Box::new(TauriEventSink(app.clone())));
+    let stats = action_dispatcher.stats();
+    *dispatcher = Some(action_dispatcher);
+    let generation = state.dispatch_generation.fetch_add(1, Ordering::SeqCst) + 1;
+
+    let app = app.clone();
+    thread::Builder::new()
+        .name("fftron-dispatch".to_string())
+        .spawn(move || run_action_dispatch(app, generation))
+        .map_err(|err| err.to_string())?;
+    Ok(stats)
+}
+
+/// Sleeps until the next queued action is due and dispatches it on time, so
+/// cue timing follows the engine clock rather than webview timers. An empty
+/// queue blocks on the dispatch signal until something is queued.
+fn run_action_dispatch(app: AppHandle, generation: u64) {
+    let state = app.state::<AppState>();
+    let raised = dispatch::raise_thread_priority().is_ok();
+    if let Ok(mut dispatcher) = state.dispatcher.lock() {
+        if let Some(dispatcher) = dispatcher.as_mut() {
+            dispatcher.set_raised_priority(raised);
+        }
+    }
+    while state.dispatch_generation.load(Ordering::SeqCst) == generation {
+        let now_us = state.clock.now_us();
+        // Locked directly: popping moves the head, and this thread is the one to wake.
+        let Ok((due, next_due_ms)) = state.scheduler.lock().map(|mut scheduler| {
+            let due = scheduler.pop_until(now_us / 1_000);
+            // Still holding the scheduler, so OSC output does not take these for cancelled.
+            if !due.is_empty() {
+                if let Ok(mut output) = state.osc_output.lock() {
+                    if let Some(broadcaster) = output.as_mut() {
+                        broadcaster.send_actions(&due);
+                    }
+                }
+            }
+            (due, scheduler.next_due_ms())
+        }) else {
+            return;
+        };
+
+        {
+            let Ok(mut dispatcher) = state.dispatcher.lock() else {
+                return;
+            };
+            let Some(dispatcher) = dispatcher.as_mut() else {
+                return;
+            };
+            if !due.is_empty() {
+                dispatcher.dispatch(due);
+            }
+        }
+
+        let next_due_us = next_due_ms.map(|next_due_ms| next_due_ms * 1_000);
+        state.dispatch_signal.wait_until(state.clock.as_ref(), next_due_us);
+    }
+}
+
+fn run_osc_server(app: AppHandle) {
+    let state = app.state::<AppState>();
+    loop {
+        let datagram = {
+            let Ok(mut osc) = state.osc.lock() else {
+                return;
+            };
+            let Some(server) = osc.as_mut() else {
+                return;
+            };
+            // Windows reports ICMP errors from earlier replies here; keep listening.
+            server.recv().ok().flatten()
+        };
+        let Some((from, messages)) = datagram else {
+            continue;
+        };
+
+        let replies = match messages {
+            Ok(messages) => messages
+                .iter()
+                .filter_map(|message| handle_osc_message(app.state::<AppState>(), message))
+                .collect::<Vec<_>>(),
+            Err(err) => vec![osc::error_reply("", &err.to_string())],
+        };
+
+        let Ok(mut osc) = state.osc.lock() else {
+            return;
+        };
+        let Some(server) = osc.as_mut() else {
+            return;
+        };
+        for reply in &replies {
+            let _ = server.reply(from, reply);
+        }
+    }
+}
+
+fn handle_osc_message(state: State<'_, AppState>, message: &OscMessage) -> Option<OscMessage> {
+    let reply = match OscCommand::parse(message) {
+        Ok(Some(command)) => run_osc_command(command, state),
+        Ok(None) => return None,
+        Err(err) => Err(err.to_string()),
+    };
+    Some(reply.unwrap_or_else(|err| osc::error_reply(&message.address, &err)))
+}
+
+/// Runs an OSC command through the matching Tauri command.
+fn run_osc_command(command: OscCommand, state: State<'_, AppState>) -> Result<OscMessage, String> {
+    let reply = match command {
+        OscCommand::SetBpm(bpm) => osc::tempo_reply(&set_bpm(bpm, None, state)?),
+        OscCommand::NudgeBpm(delta) => osc::tempo_reply(&nudge_bpm(delta, None, state)?),
+        OscCommand::Tap => osc::tempo_reply(&tap_bpm(None, state)?),
+        OscCommand::Resync => osc::tempo_reply(&resync_downbeat(None, state)?),
+        OscCommand::ActivateSection(section) => {
+            let marker_count = activate_timeline_section(section.clone(), state)?;
+            osc::section_reply(Some(&section), marker_count)
+        }
+        OscCommand::QueueSectionChange { section, quantize } => {
+            osc::section_change_reply(&queue_section_change(section, quantize, state)?)
+        }
+        OscCommand::QueueCue {
+            action,
+            section,
+            quantize,
+        } => osc::queued_reply(&queue_preview_action(action, section, quantize, None, None, None, state)?),
+        OscCommand::Panic => osc::queued_reply(&panic_scheduler(state)?),
+        OscCommand::Query(OscQuery::Tempo) => osc::tempo_reply(&get_tempo_state(state)?),
+        OscCommand::Query(OscQuery::Position) => osc::position_reply(&get_beat_position(None, state)?),
+        OscCommand::Query(OscQuery::Section) => {
+            let runtime = state.timeline_runtime.lock().map_err(|err| err.to_string())?;
+            osc::section_reply(runtime.active_section(), runtime.list_markers(None).len())
+        }
+        OscCommand::Query(OscQuery::Queue) => osc::queue_reply(&list_scheduled_actions(state)?),
+    };
+    Ok(reply)
+}
+
+fn run_osc_output(app: AppHandle) {
+    let state = app.state::<AppState>();
+    loop {
+        thread::sleep(OSC_OUTPUT_INTERVAL);
+
+        let Ok(tempo) = state.tempo.lock().map(|tempo| tempo.state()) else {
+            return;
+        };
+        let Ok(scheduler) = state.lock_scheduler() else {
+            return;
+        };
+        let Ok(mut output) = state.osc_output.lock() else {
+            return;
+        };
+        let Some(broadcaster) = output.as_mut() else {
+            return;
+        };
+        let now = state.clock.now_ms();
+        broadcaster.send_beats(&tempo, now);
+        broadcaster.send_queued_actions(scheduler.queued(), now);
+    }
+}
+
+/// Feeds the input's follower until the device ends or `stopped` is set.
+/// The flag is read under the input lock, so a stopped reader never feeds the
+/// input started after it.
+fn run_midi_clock_input<S: MidiByteSource>(app: AppHandle, mut source: S, stopped: Arc<AtomicBool>) {
+    let state = app.state::<AppState>();
+    while let Ok(Some((timestamp_us, bytes))) = source.next_chunk() {
+        let update = {
+            let Ok(mut input) = state.midi_clock_input.lock() else {
+                return;
+            };
+            let Some(input) = input.as_mut().filter(|_| !stopped.load(Ordering::SeqCst)) else {
+                return;
+            };
+            input.follower.feed(timestamp_us, &bytes)
+        };
+
+        let Some(MidiClockUpdate { bpm: Some(bpm), confidence, downbeat_epoch_ms, .. }) = update else {
+            continue;
+        };
+        let followed = state.tempo.lock().map(|mut tempo| {
+            let downbeat_epoch_ms = downbeat_epoch_ms.unwrap_or(tempo.state().downbeat_epoch_ms);
+            tempo.sync_external(TempoSource::MidiClock, bpm, downbeat_epoch_ms, confidence)
+        });
+        if let Ok(tempo_state) = followed {
+            let _ = follow_tempo(&state, &tempo_state);
+        }
+    }
+
+    // The device ended on its own: clear the input unless it was stopped meanwhile.
+    {
+        let Ok(mut input) = state.midi_clock_input.lock() else {
+            return;
+        };
+        if stopped.load(Ordering::SeqCst) {
+            return;
+        }
+        *input = None;
+    }
+    let released = state.tempo.lock().map(|mut tempo| {
+        tempo.release_source(&TempoSource::MidiClock);
+        tempo.state()
+    });
+    if let Ok(tempo_state) = released {
+        let _ = follow_tempo(&state, &tempo_state);
+    }
+}
+
+fn run_midi_clock_output(app: AppHandle) {
+    let state = app.state::<AppState>();
+    let _ = dispatch::raise_thread_priority();
+    loop {
+        thread::sleep(MIDI_CLOCK_OUTPUT_INTERVAL);
+
+        let now_us = state.clock.now_us();
+        let Ok(tempo) = state.tempo.lock().map(|tempo| tempo.state().constant_at(now_us / 1_000)) else {
+            return;
+        };
+        let Ok(mut output) = state.midi_clock_output.lock() else {
+            return;
+        };
+        let Some(clock_output) = output.as_mut() else {
+            return;
+        };
+        if clock_output
+            .generator
+            .render(&tempo, now_us + MIDI_CLOCK_OUTPUT_LEAD_US, &mut clock_output.sink)
+            .is_err()
+        {
+            if let Some(clock_output) = output.take() {
+                let _ = clock_output.sink.cancel();
+            }
+            return;
+        }
+    }
 }
 
 fn main() {
+    let clock: SharedClock = Arc::new(MonotonicClock::default());
     tauri::Builder::default()
         .manage(AppState {
+            clock: clock.clone(),
             timeline_runtime: Mutex::new(TimelineRuntimeAdapter::default()),
-            tempo: Mutex::new(TempoEngine::default()),
-            scheduler: Mutex::new(QuantizedScheduler::default()),
+            tempo: Mutex::new(TempoEngine::new(clock.clone())),
+            scheduler: Mutex::new(QuantizedScheduler::new(clock.clone())),
+            transport: Mutex::new(Transport::new(clock)),
             media_runtime: Mutex::new(MediaRuntime::default()),
+            link: Mutex::new(None),
+            midi_clock_input: Mutex::new(None),
+            midi_clock_output: Mutex::new(None),
+            auto_tempo: Mutex::new(None),
+            osc: Mutex::new(None),
+            osc_output: Mutex::new(None),
+            dispatcher: Mutex::new(None),
+            dispatch_signal: DispatchSignal::default(),
+            dispatch_generation: AtomicU64::new(0),
+        })
+        .setup(|app| {
+            spawn_action_dispatch(app.handle())?;
+            let handle = app.handle().clone();
+            thread::Builder::new()
+                .name("fftron-transport".to_string())
+                .spawn(move || run_transport_watch(handle))?;
+            Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             import_theatre_bundle,
             validate_theatre_bundle,
             activate_timeline_section,
             list_timeline_markers,
+            get_clock_time,
             get_tempo_state,
             set_bpm,
             nudge_bpm,
+            ramp_bpm,
+            set_tempo_map,
             tap_bpm,
+            get_tap_tempo_config,
+            set_tap_tempo_config,
             resync_downbeat,
+            get_tempo_arbiter_status,
+            get_tempo_arbiter_policy,
+            set_tempo_arbiter_policy,
+            release_tempo_source,
+            export_tempo_log,
+            clear_tempo_log,
+            set_meter,
+            get_beat_position,
+            enable_link,
+            disable_link,
+            get_link_status,
+            start_midi_clock_input,
+            stop_midi_clock_input,
+            get_midi_clock_status,
+            start_midi_clock_output,
+            stop_midi_clock_output,
+            push_auto_tempo_samples,
+            reset_auto_tempo,
+            analyze_tempo_file,
+            start_osc_server,
+            stop_osc_server,
+            get_osc_status,
+            start_osc_output,
+            stop_osc_output,
+            get_osc_output_status,
             set_quantization,
+            set_phrase_origin,
             queue_preview_action,
             queue_section_markers,
             list_scheduled_actions,
+            cancel_scheduled_action,
+            cancel_scheduled_actions,
+            replace_scheduled_action,
+            flush_scheduled_actions,
+            panic_scheduler,
+            start_action_pattern,
+            list_action_patterns,
+            cancel_action_pattern,
+            get_conflict_policy,
+            set_conflict_policy,
+            get_conflict_decisions,
             pop_due_actions,
+            start_action_dispatch,
+            stop_action_dispatch,
+            get_dispatch_stats,
+            get_transport_status,
+            transport_play,
+            transport_pause,
+            transport_stop,
+            transport_locate,
+            transport_set_loop,
+            get_setlist_status,
+            set_setlist,
+            set_follow_action,
+            override_next_section,
+            start_setlist,
+            stop_setlist,
+            queue_section_change,
+            get_section_change,
+            cancel_section_change,
             detect_runtime_capabilities,
             set_decode_backend,
             set_renderer_backend
@@ -17,6 +17,7 @@ pub fn compile_bundle(bundle: TheatreExportBundle) -> CompiledTimeline {
     CompiledTimeline {
         version: bundle.version,
         fps: bundle.fps,
+        meter: bundle.meter,
         sections,
         markers,
         envelopes: bundle.envelope_templates,
@@ -26,13 +27,14 @@ pub fn compile_bundle(bundle: TheatreExportBundle) -> CompiledTimeline {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::timeline::types::{CueAction, EngineCueMarker, EngineEnvelopeTemplate, EngineSequence, QuantizeGrid, TheatreExportBundle};
+    use crate::timeline::types::{CueAction, EngineCueMarker, EngineEnvelopeTemplate, EngineSequence, Meter, QuantizeGrid, TheatreExportBundle};
 
     #[test]
     fn compile_bundle_sorts_markers_and_collects_sections() {
         let bundle = TheatreExportBundle {
             version: "1.0.0".to_string(),
             fps: 60,
+            meter: Meter::default(),
             sequences: vec![EngineSequence {
                 id: "seq-1".to_string(),
                 name: "Main".to_string(),
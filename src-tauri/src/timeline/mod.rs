@@ -1,5 +1,6 @@
 pub mod compiler;
 pub mod runtime_adapter;
+pub mod setlist;
 pub mod types;
 
 use thiserror::Error;
@@ -15,8 +16,10 @@ pub enum TimelineError {
     MissingSequence,
     #[error("cue markers are required")]
     MissingMarkers,
-    #[error("marker '{0}' has invalid beat; expected 1..=4")]
-    InvalidBeat(String),
+    #[error("meter {0}/{1} is not supported")]
+    InvalidMeter(u32, u32),
+    #[error("marker '{0}' has invalid beat; expected 1..={1}")]
+    InvalidBeat(String, u32),
     #[error("section '{0}' was not found")]
     UnknownSection(String),
 }
@@ -38,9 +41,14 @@ pub fn validate_bundle(bundle: &TheatreExportBundle) -> Result<(), TimelineError
         return Err(TimelineError::MissingMarkers);
     }
 
+    let meter = bundle.meter;
+    if !meter.is_valid() {
+        return Err(TimelineError::InvalidMeter(meter.numerator, meter.denominator));
+    }
+
     for marker in &bundle.cue_markers {
-        if !(1..=4).contains(&marker.beat) {
-            return Err(TimelineError::InvalidBeat(marker.id.clone()));
+        if !(1..=meter.numerator).contains(&marker.beat) {
+            return Err(TimelineError::InvalidBeat(marker.id.clone(), meter.numerator));
         }
     }
 
@@ -50,13 +58,14 @@ pub fn validate_bundle(bundle: &TheatreExportBundle) -> Result<(), TimelineError
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::timeline::types::{CueAction, EngineCueMarker, EngineSequence, QuantizeGrid, TheatreExportBundle};
+    use crate::timeline::types::{CueAction, EngineCueMarker, EngineSequence, Meter, QuantizeGrid, TheatreExportBundle};
 
     #[test]
     fn reject_invalid_beat() {
         let bundle = TheatreExportBundle {
             version: "1.0.0".to_string(),
             fps: 60,
+            meter: Meter::default(),
             sequences: vec![EngineSequence {
                 id: "s1".to_string(),
                 name: "Main".to_string(),
@@ -75,6 +84,41 @@ mod tests {
         };
 
         let error = validate_bundle(&bundle).expect_err("bundle should be invalid");
-        assert!(matches!(error, TimelineError::InvalidBeat(_)));
+        assert!(matches!(error, TimelineError::InvalidBeat(_, 4)));
+    }
+
+    #[test]
+    fn beat_range_follows_meter() {
+        let mut bundle = TheatreExportBundle {
+            version: "1.0.0".to_string(),
+            fps: 60,
+            meter: Meter {
+                numerator: 7,
+                denominator: 8,
+            },
+            sequences: vec![EngineSequence {
+                id: "s1".to_string(),
+                name: "Main".to_string(),
+                section: "verse-a".to_string(),
+            }],
+            cue_markers: vec![EngineCueMarker {
+                id: "m1".to_string(),
+                section: "verse-a".to_string(),
+                bar: 1,
+                beat: 7,
+                quantize: QuantizeGrid::Eighth,
+                action: CueAction::ApplyAccent,
+                payload: serde_json::json!({}),
+            }],
+            envelope_templates: vec![],
+        };
+        assert!(validate_bundle(&bundle).is_ok());
+
+        bundle.meter = Meter {
+            numerator: 3,
+            denominator: 4,
+        };
+        let error = validate_bundle(&bundle).expect_err("beat 7 is outside 3/4");
+        assert!(matches!(error, TimelineError::InvalidBeat(_, 3)));
     }
 }
@@ -1,10 +1,13 @@
 use super::compiler::compile_bundle;
+use super::setlist::{FollowAction, SectionFollow, SectionSequencer, Setlist, SetlistStatus};
 use super::types::{CompiledTimeline, EngineCueMarker, TheatreExportBundle};
+use super::TimelineError;
 
 #[derive(Default)]
 pub struct TimelineRuntimeAdapter {
     compiled: Option<CompiledTimeline>,
     active_section: Option<String>,
+    sequencer: SectionSequencer,
 }
 
 impl TimelineRuntimeAdapter {
@@ -38,23 +41,67 @@ impl TimelineRuntimeAdapter {
         }
     }
 
+    pub fn active_section(&self) -> Option<&str> {
+        self.active_section.as_deref()
+    }
+
     pub fn sections(&self) -> Vec<String> {
         self.compiled
             .as_ref()
             .map(|compiled| compiled.sections.clone())
             .unwrap_or_default()
     }
+
+    pub fn sequencer(&self) -> &SectionSequencer {
+        &self.sequencer
+    }
+
+    pub fn sequencer_mut(&mut self) -> &mut SectionSequencer {
+        &mut self.sequencer
+    }
+
+    pub fn set_setlist(&mut self, setlist: Setlist) -> Result<SetlistStatus, TimelineError> {
+        self.ensure_sections(setlist.sections.iter())?;
+        self.sequencer.set_setlist(setlist);
+        Ok(self.sequencer.status())
+    }
+
+    pub fn set_follow_action(
+        &mut self,
+        section: String,
+        follow: Option<SectionFollow>,
+    ) -> Result<SetlistStatus, TimelineError> {
+        self.ensure_sections(std::iter::once(&section))?;
+        if let Some(SectionFollow {
+            action: FollowAction::Random { choices },
+            ..
+        }) = &follow
+        {
+            self.ensure_sections(choices.iter().map(|choice| &choice.section))?;
+        }
+        self.sequencer.set_follow_action(section, follow);
+        Ok(self.sequencer.status())
+    }
+
+    fn ensure_sections<'a>(&self, mut names: impl Iterator<Item = &'a String>) -> Result<(), TimelineError> {
+        let sections = self.sections();
+        match names.find(|name| !sections.contains(name)) {
+            Some(unknown) => Err(TimelineError::UnknownSection(unknown.clone())),
+            None => Ok(()),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::timeline::types::{CueAction, EngineCueMarker, EngineEnvelopeTemplate, EngineSequence, QuantizeGrid, TheatreExportBundle};
+    use crate::timeline::types::{CueAction, EngineCueMarker, EngineEnvelopeTemplate, EngineSequence, Meter, QuantizeGrid, TheatreExportBundle};
 
     fn fixture_bundle() -> TheatreExportBundle {
         TheatreExportBundle {
             version: "1.0.0".to_string(),
             fps: 60,
+            meter: Meter::default(),
             sequences: vec![EngineSequence {
                 id: "s1".to_string(),
                 name: "Main".to_string(),
@@ -105,4 +152,28 @@ mod tests {
         assert_eq!(markers.len(), 1);
         assert_eq!(markers[0].id, "m2");
     }
+
+    #[test]
+    fn setlists_and_follow_actions_only_name_known_sections() {
+        let mut adapter = TimelineRuntimeAdapter::default();
+        adapter.import_bundle(fixture_bundle());
+
+        let setlist = Setlist {
+            sections: vec!["verse-a".to_string(), "bridge".to_string()],
+            ..Setlist::default()
+        };
+        assert!(matches!(
+            adapter.set_setlist(setlist),
+            Err(TimelineError::UnknownSection(section)) if section == "bridge"
+        ));
+
+        let follow = SectionFollow {
+            after_bars: 8,
+            action: FollowAction::Next,
+        };
+        let status = adapter
+            .set_follow_action("verse-a".to_string(), Some(follow.clone()))
+            .expect("known section");
+        assert_eq!(status.follow_actions.get("verse-a"), Some(&follow));
+    }
 }
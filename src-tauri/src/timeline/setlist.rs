@@ -0,0 +1,308 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::types::QuantizeGrid;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct WeightedSection {
+    pub section: String,
+    pub weight: u32,
+}
+
+/// Where a section goes once it has played its bars.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FollowAction {
+    /// The section after it in the setlist; the last one holds.
+    Next,
+    /// The same section again.
+    Loop,
+    /// One of `choices`, picked in proportion to its weight.
+    Random { choices: Vec<WeightedSection> },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SectionFollow {
+    pub after_bars: u32,
+    pub action: FollowAction,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Setlist {
+    /// Running order for `next` follow actions.
+    pub sections: Vec<String>,
+    /// Phrase grids round a section's bars up to whole phrases; other grids advance on the bar.
+    pub quantize: QuantizeGrid,
+    /// Off holds the playing section until it is changed by hand.
+    pub auto_advance: bool,
+}
+
+impl Default for Setlist {
+    fn default() -> Self {
+        Self {
+            sections: Vec::new(),
+            quantize: QuantizeGrid::Whole,
+            auto_advance: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SetlistStatus {
+    pub setlist: Setlist,
+    pub follow_actions: BTreeMap<String, SectionFollow>,
+    /// Section the setlist is playing, if it is running.
+    pub current: Option<String>,
+    /// Bar the current section started on.
+    pub started_bar: Option<i64>,
+    /// Section the follow action picked, or the operator's override.
+    pub next: Option<String>,
+    pub overridden: bool,
+    /// Bar on which `next` starts; `None` while holding.
+    pub due_bar: Option<i64>,
+}
+
+/// Runs a setlist: tracks the playing section and decides where it goes next.
+/// Positions are grid bars; the caller starts each section on time.
+#[derive(Debug, Clone)]
+pub struct SectionSequencer {
+    setlist: Setlist,
+    follow_actions: BTreeMap<String, SectionFollow>,
+    current: Option<String>,
+    started_bar: i64,
+    next: Option<String>,
+    overridden: bool,
+    rng: u64,
+}
+
+impl Default for SectionSequencer {
+    fn default() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+        Self::with_seed(seed)
+    }
+}
+
+impl SectionSequencer {
+    /// A sequencer whose random follow actions repeat for the same seed.
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            setlist: Setlist::default(),
+            follow_actions: BTreeMap::new(),
+            current: None,
+            started_bar: 1,
+            next: None,
+            overridden: false,
+            rng: seed,
+        }
+    }
+
+    pub fn setlist(&self) -> &Setlist {
+        &self.setlist
+    }
+
+    pub fn set_setlist(&mut self, setlist: Setlist) {
+        self.setlist = setlist;
+        self.decide();
+    }
+
+    pub fn follow_actions(&self) -> &BTreeMap<String, SectionFollow> {
+        &self.follow_actions
+    }
+
+    /// Sets or clears a section's follow action. A pending pick for the playing
+    /// section is made again unless the operator overrode it.
+    pub fn set_follow_action(&mut self, section: String, follow: Option<SectionFollow>) {
+        let playing = self.current.as_ref() == Some(&section);
+        match follow {
+            Some(follow) => self.follow_actions.insert(section, follow),
+            None => self.follow_actions.remove(&section),
+        };
+        if playing {
+            self.decide();
+        }
+    }
+
+    pub fn current(&self) -> Option<&str> {
+        self.current.as_deref()
+    }
+
+    /// Marks `section` as playing from `bar` and picks where it goes next.
+    pub fn start(&mut self, section: String, bar: i64) {
+        self.current = Some(section);
+        self.started_bar = bar;
+        self.overridden = false;
+        self.decide();
+    }
+
+    pub fn stop(&mut self) {
+        self.current = None;
+        self.next = None;
+        self.overridden = false;
+    }
+
+    /// Replaces the pick for the next section; `None` hands it back to the follow action.
+    pub fn override_next(&mut self, section: Option<String>) {
+        self.overridden = section.is_some();
+        match section {
+            Some(section) => self.next = Some(section),
+            None => self.decide(),
+        }
+    }
+
+    /// Bar on which the next section is due, rounded up to whole phrases of the
+    /// setlist grid. `None` while holding.
+    pub fn due_bar(&self) -> Option<i64> {
+        if !self.setlist.auto_advance || self.next.is_none() {
+            return None;
+        }
+        let follow = self.follow_actions.get(self.current.as_ref()?)?;
+        let phrase = i64::from(self.setlist.quantize.phrase_bars().unwrap_or(1));
+        let bars = (i64::from(follow.after_bars.max(1)) + phrase - 1) / phrase * phrase;
+        Some(self.started_bar + bars)
+    }
+
+    /// Starts the picked section on `bar` and returns it.
+    pub fn advance(&mut self, bar: i64) -> Option<String> {
+        let next = self.next.take()?;
+        self.start(next.clone(), bar);
+        Some(next)
+    }
+
+    pub fn status(&self) -> SetlistStatus {
+        SetlistStatus {
+            setlist: self.setlist.clone(),
+            follow_actions: self.follow_actions.clone(),
+            current: self.current.clone(),
+            started_bar: self.current.as_ref().map(|_| self.started_bar),
+            next: self.next.clone(),
+            overridden: self.overridden,
+            due_bar: self.due_bar(),
+        }
+    }
+
+    fn decide(&mut self) {
+        if self.overridden {
+            return;
+        }
+        let Some(current) = self.current.clone() else {
+            self.next = None;
+            return;
+        };
+        self.next = match self.follow_actions.get(&current).map(|follow| follow.action.clone()) {
+            Some(FollowAction::Next) => {
+                let sections = &self.setlist.sections;
+                let position = sections.iter().position(|section| *section == current);
+                position.and_then(|index| sections.get(index + 1)).cloned()
+            }
+            Some(FollowAction::Loop) => Some(current),
+            Some(FollowAction::Random { choices }) => self.pick(&choices),
+            None => None,
+        };
+    }
+
+    fn pick(&mut self, choices: &[WeightedSection]) -> Option<String> {
+        let total = choices.iter().map(|choice| u64::from(choice.weight)).sum::<u64>();
+        if total == 0 {
+            return None;
+        }
+        let mut roll = self.roll() % total;
+        choices
+            .iter()
+            .find(|choice| {
+                let hit = roll < u64::from(choice.weight);
+                roll = roll.saturating_sub(u64::from(choice.weight));
+                hit
+            })
+            .map(|choice| choice.section.clone())
+    }
+
+    /// SplitMix64.
+    fn roll(&mut self) -> u64 {
+        self.rng = self.rng.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn follow(after_bars: u32, action: FollowAction) -> Option<SectionFollow> {
+        Some(SectionFollow { after_bars, action })
+    }
+
+    #[test]
+    fn a_setlist_advances_in_order_on_whole_phrases() {
+        let mut sequencer = SectionSequencer::with_seed(7);
+        sequencer.set_setlist(Setlist {
+            sections: vec!["intro".into(), "verse-a".into(), "chorus-a".into()],
+            quantize: QuantizeGrid::FourBars,
+            auto_advance: true,
+        });
+        sequencer.set_follow_action("intro".into(), follow(6, FollowAction::Next));
+        sequencer.set_follow_action("verse-a".into(), follow(8, FollowAction::Next));
+        sequencer.start("intro".into(), 1);
+
+        // Six bars round up to two four-bar phrases.
+        assert_eq!(sequencer.due_bar(), Some(9));
+        assert_eq!(sequencer.advance(9).as_deref(), Some("verse-a"));
+        assert_eq!(sequencer.due_bar(), Some(17));
+        assert_eq!(sequencer.advance(17).as_deref(), Some("chorus-a"));
+
+        // The last section has no follow action, so it holds.
+        assert_eq!(sequencer.status().next, None);
+        assert_eq!(sequencer.due_bar(), None);
+    }
+
+    #[test]
+    fn follow_actions_loop_pick_by_weight_and_yield_to_overrides() {
+        let mut sequencer = SectionSequencer::with_seed(42);
+        let choices = vec![
+            WeightedSection {
+                section: "chorus-a".into(),
+                weight: 3,
+            },
+            WeightedSection {
+                section: "chorus-b".into(),
+                weight: 1,
+            },
+            WeightedSection {
+                section: "never".into(),
+                weight: 0,
+            },
+        ];
+        sequencer.set_follow_action("verse-a".into(), follow(4, FollowAction::Random { choices }));
+        sequencer.set_follow_action("chorus-a".into(), follow(4, FollowAction::Loop));
+
+        let mut picks = BTreeMap::new();
+        for _ in 0..400 {
+            sequencer.start("verse-a".into(), 1);
+            *picks.entry(sequencer.status().next.unwrap()).or_insert(0) += 1;
+        }
+        assert_eq!(picks.len(), 2);
+        assert!(picks["chorus-a"] > picks["chorus-b"] * 2);
+
+        sequencer.start("chorus-a".into(), 5);
+        assert_eq!(sequencer.status().next.as_deref(), Some("chorus-a"));
+        sequencer.override_next(Some("outro".into()));
+        sequencer.set_follow_action("chorus-a".into(), follow(8, FollowAction::Loop));
+        let status = sequencer.status();
+        assert_eq!(
+            (status.next.as_deref(), status.overridden, status.due_bar),
+            (Some("outro"), true, Some(13))
+        );
+
+        sequencer.override_next(None);
+        assert_eq!(sequencer.status().next.as_deref(), Some("chorus-a"));
+    }
+}
@@ -20,14 +20,82 @@ pub enum TheatreUsage {
 pub enum QuantizeGrid {
     #[serde(rename = "1n")]
     Whole,
+    #[serde(rename = "1/2n.")]
+    DottedHalf,
     #[serde(rename = "1/2n")]
     Half,
+    #[serde(rename = "1/4n.")]
+    DottedQuarter,
     #[serde(rename = "1/4n")]
     Quarter,
+    #[serde(rename = "1/4t")]
+    QuarterTriplet,
+    #[serde(rename = "1/8n.")]
+    DottedEighth,
     #[serde(rename = "1/8n")]
     Eighth,
+    #[serde(rename = "1/8t")]
+    EighthTriplet,
     #[serde(rename = "1/16n")]
     Sixteenth,
+    #[serde(rename = "1/32n")]
+    ThirtySecond,
+    /// Phrase grids, counted in bars from the phrase origin.
+    #[serde(rename = "2m")]
+    TwoBars,
+    #[serde(rename = "4m")]
+    FourBars,
+    #[serde(rename = "8m")]
+    EightBars,
+    #[serde(rename = "16m")]
+    SixteenBars,
+}
+
+impl QuantizeGrid {
+    /// Length in bars of a phrase grid.
+    pub fn phrase_bars(&self) -> Option<u32> {
+        match self {
+            QuantizeGrid::TwoBars => Some(2),
+            QuantizeGrid::FourBars => Some(4),
+            QuantizeGrid::EightBars => Some(8),
+            QuantizeGrid::SixteenBars => Some(16),
+            _ => None,
+        }
+    }
+}
+
+/// Time signature. Tempo is always counted in quarter notes, so a bar spans
+/// `numerator * 4 / denominator` of them.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Meter {
+    pub numerator: u32,
+    pub denominator: u32,
+}
+
+impl Default for Meter {
+    fn default() -> Self {
+        Self {
+            numerator: 4,
+            denominator: 4,
+        }
+    }
+}
+
+impl Meter {
+    pub fn is_valid(&self) -> bool {
+        (1..=32).contains(&self.numerator) && matches!(self.denominator, 1 | 2 | 4 | 8 | 16 | 32)
+    }
+
+    /// Length of one counted beat in quarter notes.
+    pub fn beat_unit(&self) -> f64 {
+        4.0 / f64::from(self.denominator.max(1))
+    }
+
+    /// Length of one bar in quarter notes.
+    pub fn bar_beats(&self) -> f64 {
+        f64::from(self.numerator) * self.beat_unit()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -38,6 +106,26 @@ pub enum CueAction {
     SwapScene,
 }
 
+impl CueAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CueAction::TriggerClip => "trigger_clip",
+            CueAction::ApplyAccent => "apply_accent",
+            CueAction::SwapScene => "swap_scene",
+        }
+    }
+
+    /// The cue an action name such as `trigger_clip` stands for, if any.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "trigger_clip" => Some(CueAction::TriggerClip),
+            "apply_accent" => Some(CueAction::ApplyAccent),
+            "swap_scene" => Some(CueAction::SwapScene),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct EngineSequence {
     pub id: String,
@@ -72,6 +160,8 @@ pub struct EngineEnvelopeTemplate {
 pub struct TheatreExportBundle {
     pub version: String,
     pub fps: u32,
+    #[serde(default)]
+    pub meter: Meter,
     pub sequences: Vec<EngineSequence>,
     pub cue_markers: Vec<EngineCueMarker>,
     pub envelope_templates: Vec<EngineEnvelopeTemplate>,
@@ -81,6 +171,7 @@ pub struct TheatreExportBundle {
 pub struct CompiledTimeline {
     pub version: String,
     pub fps: u32,
+    pub meter: Meter,
     pub sections: Vec<String>,
     pub markers: Vec<EngineCueMarker>,
     pub envelopes: Vec<EngineEnvelopeTemplate>,
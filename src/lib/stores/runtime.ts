@@ -65,7 +65,9 @@ export const tempoState = writable<TempoState>({
   confidence: 1,
   downbeatEpochMs: Date.now(),
   source: 'manual',
-  tapCount: 0
+  tapCount: 0,
+  meter: { numerator: 4, denominator: 4 },
+  tempoMap: []
 });
 
 export const detectedTempo = writable<DetectedTempoState>({
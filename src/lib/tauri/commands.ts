@@ -1,16 +1,77 @@
 import { browser } from '$app/environment';
 import { invoke } from '@tauri-apps/api/core';
-import type { DecodeBackend, RendererBackend, RuntimeCapabilities, ScheduledAction, TempoState } from '$lib/types/engine';
-import type { EngineCueMarker, QuantizeGrid, TheatreExportBundle } from '$lib/types/timeline';
+import { listen, type UnlistenFn } from '@tauri-apps/api/event';
+import type {
+  ActionOrigin,
+  ActionPattern,
+  ArbiterPolicy,
+  ArbiterStatus,
+  BeatPosition,
+  ConflictDecision,
+  ConflictPolicy,
+  DecodeBackend,
+  DispatchedAction,
+  DispatchStats,
+  DriftReference,
+  GridTarget,
+  LinkStatus,
+  LoopRegion,
+  OscOutputConfig,
+  OscOutputStatus,
+  OscStatus,
+  MidiClockStatus,
+  RendererBackend,
+  RunningPattern,
+  RuntimeCapabilities,
+  ScheduledAction,
+  SectionChange,
+  SectionPlayback,
+  TapTempoConfig,
+  TempoEstimate,
+  TempoEvent,
+  TempoLogExport,
+  TempoLogFormat,
+  TempoRamp,
+  TempoSource,
+  TempoState,
+  TransportChange,
+  TransportState,
+  TransportStatus
+} from '$lib/types/engine';
+import type {
+  CueAction,
+  EngineCueMarker,
+  Meter,
+  QuantizeGrid,
+  SectionFollow,
+  Setlist,
+  SetlistStatus,
+  TheatreExportBundle
+} from '$lib/types/timeline';
 
 interface LocalRuntimeState {
   runtimeCapabilities: RuntimeCapabilities;
   tempo: TempoState;
   quantizeGrid: QuantizeGrid;
+  phraseOriginBar: number;
   markers: EngineCueMarker[];
   activeSection: string;
   scheduledActions: ScheduledAction[];
   nextActionId: number;
+  conflictPolicy: ConflictPolicy;
+  tapConfig: TapTempoConfig;
+  transport: LocalTransport;
+  setlist: SetlistStatus;
+  sectionChange: SectionChange | null;
+  patterns: RunningPattern[];
+}
+
+interface LocalTransport {
+  state: TransportState;
+  anchorBeats: number;
+  anchorMs: number;
+  returnBeats: number;
+  loopRegion: LoopRegion | null;
 }
 
 const localState: LocalRuntimeState = {
@@ -28,13 +89,36 @@ const localState: LocalRuntimeState = {
     confidence: 1,
     downbeatEpochMs: Date.now(),
     source: 'manual',
-    tapCount: 0
+    tapCount: 0,
+    meter: { numerator: 4, denominator: 4 },
+    tempoMap: []
   },
   quantizeGrid: '1/4n',
+  phraseOriginBar: 1,
   markers: [],
   activeSection: 'verse-a',
   scheduledActions: [],
-  nextActionId: 1
+  nextActionId: 1,
+  conflictPolicy: { exclusiveActions: ['swap_scene'] },
+  tapConfig: {
+    preferredMinBpm: 80,
+    preferredMaxBpm: 160,
+    sessionTimeoutMs: 2500,
+    outlierTolerance: 0.2,
+    downbeatOnFourCount: false
+  },
+  transport: { state: 'stopped', anchorBeats: 0, anchorMs: 0, returnBeats: 0, loopRegion: null },
+  setlist: {
+    setlist: { sections: [], quantize: '1n', autoAdvance: true },
+    followActions: {},
+    current: null,
+    startedBar: null,
+    next: null,
+    overridden: false,
+    dueBar: null
+  },
+  sectionChange: null,
+  patterns: []
 };
 
 const hasTauriRuntime = (): boolean => {
@@ -45,51 +129,147 @@ const hasTauriRuntime = (): boolean => {
 
 const nowMs = (): number => Date.now();
 
-const quantizeDurationMs = (grid: QuantizeGrid, bpm: number): number => {
+const barBeats = (meter: Meter): number => (meter.numerator * 4) / meter.denominator;
+
+const isValidMeter = (meter: Meter): boolean =>
+  Number.isInteger(meter.numerator) &&
+  meter.numerator >= 1 &&
+  meter.numerator <= 32 &&
+  [1, 2, 4, 8, 16, 32].includes(meter.denominator);
+
+const quantizeDurationMs = (grid: QuantizeGrid, bpm: number, meter: Meter): number => {
   const clampedBpm = Math.max(20, Math.min(300, bpm || 120));
   const beatMs = 60000 / clampedBpm;
   switch (grid) {
     case '1n':
-      return beatMs * 4;
+      return beatMs * barBeats(meter);
+    case '1/2n.':
+      return beatMs * 3;
     case '1/2n':
       return beatMs * 2;
+    case '1/4n.':
+      return beatMs * 1.5;
     case '1/4n':
       return beatMs;
+    case '1/4t':
+      return (beatMs * 2) / 3;
+    case '1/8n.':
+      return beatMs * 0.75;
     case '1/8n':
       return beatMs / 2;
+    case '1/8t':
+      return beatMs / 3;
     case '1/16n':
       return beatMs / 4;
+    case '1/32n':
+      return beatMs / 8;
+    case '2m':
+    case '4m':
+    case '8m':
+    case '16m':
+      return beatMs * barBeats(meter) * Number.parseInt(grid, 10);
     default:
       return beatMs;
   }
 };
 
 const nextQuantizedBoundary = (grid: QuantizeGrid): number => {
-  const durationMs = quantizeDurationMs(grid, localState.tempo.bpm);
-  const elapsed = Math.max(0, nowMs() - localState.tempo.downbeatEpochMs);
-  const slot = Math.ceil(elapsed / durationMs);
-  return localState.tempo.downbeatEpochMs + slot * durationMs;
+  const { bpm, downbeatEpochMs, meter } = localState.tempo;
+  const durationMs = quantizeDurationMs(grid, bpm, meter);
+  const barMs = quantizeDurationMs('1n', bpm, meter);
+  const elapsed = Math.max(0, nowMs() - downbeatEpochMs);
+  if (grid.endsWith('m')) {
+    const originMs = downbeatEpochMs + (localState.phraseOriginBar - 1) * barMs;
+    return originMs + Math.ceil((downbeatEpochMs + elapsed - originMs) / durationMs) * durationMs;
+  }
+  const barStart = downbeatEpochMs + Math.floor(elapsed / barMs) * barMs;
+  const slot = Math.ceil((downbeatEpochMs + elapsed - barStart) / durationMs);
+  return Math.min(barStart + slot * durationMs, barStart + barMs);
 };
 
-const scheduleAction = (action: string, section: string | null, quantize?: QuantizeGrid): ScheduledAction => {
+const gridTargetAt = (timestampMs: number): GridTarget => {
+  const { bpm, downbeatEpochMs, meter } = localState.tempo;
+  const unitMs = (quantizeDurationMs('1/4n', bpm, meter) * 4) / meter.denominator;
+  const ticks = Math.round(((timestampMs - downbeatEpochMs) / unitMs) * 960);
+  const ticksPerBar = meter.numerator * 960;
+  const bar = Math.floor(ticks / ticksPerBar);
+  const inBar = ticks - bar * ticksPerBar;
+  return { bar: bar + 1, beat: Math.floor(inBar / 960) + 1, tick: inBar % 960 };
+};
+
+const originRank: Record<ActionOrigin, number> = { authored: 0, auto: 1, manual: 2 };
+
+const cueActions: CueAction[] = ['trigger_clip', 'apply_accent', 'swap_scene'];
+
+interface LocalActionRequest {
+  action: string;
+  section: string | null;
+  quantize?: QuantizeGrid;
+  priority?: number;
+  origin?: ActionOrigin;
+  markerId?: string;
+  payload?: unknown;
+  executeAtMs?: number;
+  patternId?: number;
+}
+
+const scheduleAction = ({
+  action,
+  section,
+  quantize,
+  priority = 0,
+  origin = 'authored',
+  markerId,
+  payload,
+  executeAtMs = nextQuantizedBoundary(quantize ?? localState.quantizeGrid),
+  patternId
+}: LocalActionRequest): ScheduledAction => {
   const grid = quantize ?? localState.quantizeGrid;
   const scheduled: ScheduledAction = {
     id: localState.nextActionId++,
     action,
+    cue: cueActions.find((cue) => cue === action) ?? null,
+    markerId: markerId ?? null,
+    payload: payload ?? null,
     section,
     quantize: grid,
-    executeAtMs: nextQuantizedBoundary(grid)
+    executeAtMs,
+    target: gridTargetAt(executeAtMs),
+    priority,
+    origin,
+    patternId: patternId ?? null
   };
   localState.scheduledActions.push(scheduled);
+  localState.scheduledActions.sort(
+    (a, b) =>
+      a.executeAtMs - b.executeAtMs ||
+      originRank[b.origin] - originRank[a.origin] ||
+      b.priority - a.priority ||
+      a.id - b.id
+  );
   return scheduled;
 };
 
+const positionGrids: QuantizeGrid[] = ['1n', '1/2n', '1/4n', '1/8n', '1/16n'];
+
+const positionGrid = (offsetMs: number): QuantizeGrid => {
+  const { bpm, meter } = localState.tempo;
+  return (
+    positionGrids.find((grid) => {
+      const slots = offsetMs / quantizeDurationMs(grid, bpm, meter);
+      return Math.abs(slots - Math.round(slots)) < 1e-6;
+    }) ?? '1/32n'
+  );
+};
+
 const validateLocalBundle = (bundle: TheatreExportBundle): boolean => {
   if (!bundle.version?.trim()) return false;
   if (!bundle.fps || bundle.fps <= 0) return false;
   if (!Array.isArray(bundle.sequences) || bundle.sequences.length === 0) return false;
   if (!Array.isArray(bundle.cueMarkers) || bundle.cueMarkers.length === 0) return false;
-  if (bundle.cueMarkers.some((marker) => marker.beat < 1 || marker.beat > 4)) return false;
+  const meter = bundle.meter ?? { numerator: 4, denominator: 4 };
+  if (!isValidMeter(meter)) return false;
+  if (bundle.cueMarkers.some((marker) => marker.beat < 1 || marker.beat > meter.numerator)) return false;
   return true;
 };
 
@@ -133,6 +313,7 @@ export const importTheatreBundle = (bundle: TheatreExportBundle): Promise<number
     if (!validateLocalBundle(bundle)) {
       throw new Error('Theatre bundle validation failed');
     }
+    localState.tempo = { ...localState.tempo, meter: bundle.meter ?? { numerator: 4, denominator: 4 } };
     localState.markers = [...bundle.cueMarkers].sort((a, b) => (a.bar === b.bar ? a.beat - b.beat : a.bar - b.bar));
     localState.activeSection = localState.markers[0]?.section ?? 'verse-a';
     return localState.markers.length;
@@ -154,25 +335,37 @@ export const listTimelineMarkers = (section?: string): Promise<EngineCueMarker[]
     return localState.markers.filter((marker) => marker.section === selected);
   });
 
+export const getClockTime = (): Promise<number> => invokeOrFallback('get_clock_time', {}, () => nowMs());
+
 export const getTempoState = (): Promise<TempoState> =>
   invokeOrFallback('get_tempo_state', {}, () => localState.tempo);
 
-export const setBpm = (bpm: number): Promise<TempoState> =>
-  invokeOrFallback('set_bpm', { bpm }, () => {
+const reanchorLocalTempo = (bpm: number, timestampMs: number): void => {
+  const clampedBpm = Math.max(20, Math.min(300, bpm));
+  const beats = (timestampMs - localState.tempo.downbeatEpochMs) / (60000 / localState.tempo.bpm);
+  localState.tempo = {
+    ...localState.tempo,
+    bpm: clampedBpm,
+    downbeatEpochMs: Math.round(timestampMs - beats * (60000 / clampedBpm))
+  };
+};
+
+export const setBpm = (bpm: number, timestampMs?: number): Promise<TempoState> =>
+  invokeOrFallback('set_bpm', { bpm, timestampMs: timestampMs ?? null }, () => {
+    reanchorLocalTempo(bpm, timestampMs ?? nowMs());
     localState.tempo = {
       ...localState.tempo,
-      bpm: Math.max(20, Math.min(300, bpm)),
       source: 'manual',
       confidence: 1
     };
     return localState.tempo;
   });
 
-export const nudgeBpm = (delta: number): Promise<TempoState> =>
-  invokeOrFallback('nudge_bpm', { delta }, () => {
+export const nudgeBpm = (delta: number, timestampMs?: number): Promise<TempoState> =>
+  invokeOrFallback('nudge_bpm', { delta, timestampMs: timestampMs ?? null }, () => {
+    reanchorLocalTempo(localState.tempo.bpm + delta, timestampMs ?? nowMs());
     localState.tempo = {
       ...localState.tempo,
-      bpm: Math.max(20, Math.min(300, localState.tempo.bpm + delta)),
       source: 'manual'
     };
     return localState.tempo;
@@ -193,6 +386,38 @@ export const tapBpm = (timestampMs?: number): Promise<TempoState> =>
     return localState.tempo;
   });
 
+export const getTapTempoConfig = (): Promise<TapTempoConfig> =>
+  invokeOrFallback('get_tap_tempo_config', {}, () => ({ ...localState.tapConfig }));
+
+export const setTapTempoConfig = (config: TapTempoConfig): Promise<TapTempoConfig> =>
+  invokeOrFallback('set_tap_tempo_config', { config }, () => {
+    localState.tapConfig = { ...config };
+    return localState.tapConfig;
+  });
+
+export const getTempoArbiterStatus = (): Promise<ArbiterStatus | null> =>
+  invokeOrFallback('get_tempo_arbiter_status', {}, () => null);
+
+export const getTempoArbiterPolicy = (): Promise<ArbiterPolicy | null> =>
+  invokeOrFallback('get_tempo_arbiter_policy', {}, () => null);
+
+export const setTempoArbiterPolicy = (policy: ArbiterPolicy): Promise<ArbiterStatus> =>
+  invokeOrFallback('set_tempo_arbiter_policy', { policy }, () => {
+    throw new Error('Tempo arbitration is only available in desktop runtime');
+  });
+
+export const releaseTempoSource = (source: TempoSource): Promise<ArbiterStatus> =>
+  invokeOrFallback('release_tempo_source', { source }, () => {
+    throw new Error('Tempo arbitration is only available in desktop runtime');
+  });
+
+export const exportTempoLog = (format: TempoLogFormat, reference?: DriftReference): Promise<TempoLogExport> =>
+  invokeOrFallback('export_tempo_log', { format, reference: reference ?? null }, () => {
+    throw new Error('Tempo logs are only available in desktop runtime');
+  });
+
+export const clearTempoLog = (): Promise<void> => invokeOrFallback('clear_tempo_log', {}, () => undefined);
+
 export const resyncDownbeat = (timestampMs?: number): Promise<TempoState> =>
   invokeOrFallback('resync_downbeat', { timestampMs: timestampMs ?? null }, () => {
     localState.tempo = {
@@ -202,34 +427,351 @@ export const resyncDownbeat = (timestampMs?: number): Promise<TempoState> =>
     return localState.tempo;
   });
 
+export const rampBpm = (
+  targetBpm: number,
+  bars: number,
+  ramp?: TempoRamp,
+  timestampMs?: number
+): Promise<TempoState> =>
+  invokeOrFallback('ramp_bpm', { targetBpm, bars, ramp: ramp ?? null, timestampMs: timestampMs ?? null }, () => {
+    throw new Error('Tempo ramps are only available in desktop runtime');
+  });
+
+export const setTempoMap = (events: TempoEvent[]): Promise<TempoState> =>
+  invokeOrFallback('set_tempo_map', { events }, () => {
+    throw new Error('Tempo maps are only available in desktop runtime');
+  });
+
+export const setMeter = (numerator: number, denominator: number): Promise<TempoState> =>
+  invokeOrFallback('set_meter', { numerator, denominator }, () => {
+    const meter = { numerator, denominator };
+    if (!isValidMeter(meter)) {
+      throw new Error(`invalid meter ${numerator}/${denominator}`);
+    }
+    localState.tempo = { ...localState.tempo, meter };
+    return localState.tempo;
+  });
+
+export const getBeatPosition = (timestampMs?: number): Promise<BeatPosition> =>
+  invokeOrFallback('get_beat_position', { timestampMs: timestampMs ?? null }, () => {
+    const { bpm, downbeatEpochMs, meter } = localState.tempo;
+    const beatMs = quantizeDurationMs('1/4n', bpm, meter);
+    const barMs = quantizeDurationMs('1n', bpm, meter);
+    const elapsed = (timestampMs ?? nowMs()) - downbeatEpochMs;
+    const barIndex = Math.floor(elapsed / barMs);
+    const inBar = elapsed - barIndex * barMs;
+    const unitMs = (beatMs * 4) / meter.denominator;
+    const beatIndex = Math.min(meter.numerator - 1, Math.floor(inBar / unitMs));
+    return {
+      bar: barIndex + 1,
+      beat: beatIndex + 1,
+      tick: Math.floor(((inBar - beatIndex * unitMs) / unitMs) * 960),
+      phase: inBar / barMs
+    };
+  });
+
+export const enableLink = (): Promise<LinkStatus> =>
+  invokeOrFallback('enable_link', {}, () => {
+    throw new Error('Ableton Link is only available in desktop runtime');
+  });
+
+export const disableLink = (): Promise<void> => invokeOrFallback('disable_link', {}, () => undefined);
+
+export const getLinkStatus = (): Promise<LinkStatus | null> =>
+  invokeOrFallback('get_link_status', {}, () => null);
+
+export const startMidiClockInput = (device: string): Promise<void> =>
+  invokeOrFallback('start_midi_clock_input', { device }, () => {
+    throw new Error('MIDI clock input is only available in desktop runtime');
+  });
+
+export const stopMidiClockInput = (): Promise<void> => invokeOrFallback('stop_midi_clock_input', {}, () => undefined);
+
+export const getMidiClockStatus = (): Promise<MidiClockStatus | null> =>
+  invokeOrFallback('get_midi_clock_status', {}, () => null);
+
+export const startMidiClockOutput = (device: string): Promise<void> =>
+  invokeOrFallback('start_midi_clock_output', { device }, () => {
+    throw new Error('MIDI clock output is only available in desktop runtime');
+  });
+
+export const stopMidiClockOutput = (): Promise<void> => invokeOrFallback('stop_midi_clock_output', {}, () => undefined);
+
+export const pushAutoTempoSamples = (
+  samples: Float32Array | number[],
+  sampleRate: number,
+  timestampMs?: number
+): Promise<TempoEstimate | null> =>
+  invokeOrFallback(
+    'push_auto_tempo_samples',
+    { samples: Array.from(samples), sampleRate, timestampMs: timestampMs ?? null },
+    () => null
+  );
+
+export const resetAutoTempo = (): Promise<void> => invokeOrFallback('reset_auto_tempo', {}, () => undefined);
+
+export const analyzeTempoFile = (path: string): Promise<TempoEstimate | null> =>
+  invokeOrFallback('analyze_tempo_file', { path }, () => {
+    throw new Error('Offline tempo analysis is only available in desktop runtime');
+  });
+
+export const startOscServer = (port?: number, replyPort?: number): Promise<OscStatus> =>
+  invokeOrFallback('start_osc_server', { port: port ?? null, replyPort: replyPort ?? null }, () => {
+    throw new Error('OSC control is only available in desktop runtime');
+  });
+
+export const stopOscServer = (): Promise<void> => invokeOrFallback('stop_osc_server', {}, () => undefined);
+
+export const getOscStatus = (): Promise<OscStatus | null> => invokeOrFallback('get_osc_status', {}, () => null);
+
+export const startOscOutput = (config: OscOutputConfig): Promise<OscOutputStatus> =>
+  invokeOrFallback('start_osc_output', { config }, () => {
+    throw new Error('OSC output is only available in desktop runtime');
+  });
+
+export const stopOscOutput = (): Promise<void> => invokeOrFallback('stop_osc_output', {}, () => undefined);
+
+export const getOscOutputStatus = (): Promise<OscOutputStatus | null> =>
+  invokeOrFallback('get_osc_output_status', {}, () => null);
+
 export const setQuantization = (grid: QuantizeGrid): Promise<QuantizeGrid> =>
   invokeOrFallback('set_quantization', { grid }, () => {
     localState.quantizeGrid = grid;
     return grid;
   });
 
+export const setPhraseOrigin = (bar: number): Promise<number> =>
+  invokeOrFallback('set_phrase_origin', { bar }, () => {
+    localState.phraseOriginBar = bar;
+    return bar;
+  });
+
 export const queuePreviewAction = (
   action: string,
   section?: string,
-  quantize?: QuantizeGrid
+  quantize?: QuantizeGrid,
+  priority?: number,
+  origin: ActionOrigin = 'manual',
+  payload?: unknown
 ): Promise<ScheduledAction> =>
-  invokeOrFallback('queue_preview_action', { action, section: section ?? null, quantize: quantize ?? null }, () =>
-    scheduleAction(action, section ?? localState.activeSection, quantize)
+  invokeOrFallback(
+    'queue_preview_action',
+    {
+      action,
+      section: section ?? null,
+      quantize: quantize ?? null,
+      priority: priority ?? null,
+      origin,
+      payload: payload ?? null
+    },
+    () =>
+      scheduleAction({ action, section: section ?? localState.activeSection, quantize, priority, origin, payload })
   );
 
-export const queueSectionMarkers = (section?: string): Promise<number> =>
-  invokeOrFallback('queue_section_markers', { section: section ?? null }, () => {
-    const selected = section ?? localState.activeSection;
-    const sectionMarkers = localState.markers.filter((marker) => marker.section === selected);
-    for (const marker of sectionMarkers) {
-      scheduleAction(marker.action, selected, marker.quantize);
+export const queueSectionMarkers = (
+  section?: string,
+  playback: SectionPlayback = 'sequence',
+  start?: QuantizeGrid
+): Promise<number> =>
+  invokeOrFallback(
+    'queue_section_markers',
+    { section: section ?? null, playback, start: start ?? null },
+    () => {
+      const selected = section ?? localState.activeSection;
+      const sectionMarkers = localState.markers.filter((marker) => marker.section === selected);
+      const { bpm, meter } = localState.tempo;
+      const barMs = quantizeDurationMs('1n', bpm, meter);
+      const beatUnitMs = barMs / meter.numerator;
+      const startMs = nextQuantizedBoundary(start ?? '1n');
+      for (const marker of sectionMarkers) {
+        const offsetMs = (marker.beat - 1) * beatUnitMs;
+        const sequenced = playback === 'sequence';
+        scheduleAction({
+          action: marker.action,
+          section: selected,
+          quantize: sequenced ? positionGrid(offsetMs) : marker.quantize,
+          markerId: marker.id,
+          payload: marker.payload,
+          executeAtMs: sequenced ? startMs + (marker.bar - 1) * barMs + offsetMs : undefined
+        });
+      }
+      return sectionMarkers.length;
     }
-    return sectionMarkers.length;
+  );
+
+/** Carries out the local pending section change once its boundary has passed. */
+const settleLocalSectionChange = (): SectionChange | null => {
+  const change = localState.sectionChange;
+  if (change && change.executeAtMs <= nowMs()) {
+    localState.activeSection = change.to;
+    localState.sectionChange = null;
+  }
+  return localState.sectionChange;
+};
+
+export const queueSectionChange = (section: string, quantize: QuantizeGrid = '1n'): Promise<SectionChange> =>
+  invokeOrFallback('queue_section_change', { section, quantize }, () => {
+    const executeAtMs = nextQuantizedBoundary(quantize);
+    const change: SectionChange = {
+      from: localState.activeSection,
+      to: section,
+      quantize,
+      at: gridTargetAt(executeAtMs),
+      executeAtMs
+    };
+    localState.sectionChange = change;
+    return change;
   });
 
+export const getSectionChange = (): Promise<SectionChange | null> =>
+  invokeOrFallback('get_section_change', {}, () => settleLocalSectionChange());
+
+export const cancelSectionChange = (): Promise<SectionChange | null> =>
+  invokeOrFallback('cancel_section_change', {}, () => {
+    const change = settleLocalSectionChange();
+    localState.sectionChange = null;
+    return change;
+  });
+
+/** Subscribes to queued and setlist section changes, reported just ahead of `executeAtMs`. */
+export const onSectionChanged = async (handler: (change: SectionChange) => void): Promise<UnlistenFn> => {
+  if (!hasTauriRuntime()) {
+    return () => undefined;
+  }
+  return listen<SectionChange>('timeline://section-changed', (event) => handler(event.payload));
+};
+
 export const listScheduledActions = (): Promise<ScheduledAction[]> =>
   invokeOrFallback('list_scheduled_actions', {}, () => [...localState.scheduledActions]);
 
+const removeLocalActions = (matches: (entry: ScheduledAction) => boolean): ScheduledAction[] => {
+  const removed = localState.scheduledActions.filter(matches);
+  localState.scheduledActions = localState.scheduledActions.filter((entry) => !matches(entry));
+  return removed;
+};
+
+export const cancelScheduledAction = (id: number): Promise<ScheduledAction | null> =>
+  invokeOrFallback('cancel_scheduled_action', { id }, () => removeLocalActions((entry) => entry.id === id)[0] ?? null);
+
+export const cancelScheduledActions = (filter: { section?: string; action?: string }): Promise<ScheduledAction[]> =>
+  invokeOrFallback(
+    'cancel_scheduled_actions',
+    { section: filter.section ?? null, action: filter.action ?? null },
+    () => {
+      if (filter.section === undefined && filter.action === undefined) {
+        throw new Error('cancel needs a section or an action; use flush_scheduled_actions to clear the queue');
+      }
+      return removeLocalActions(
+        (entry) =>
+          (filter.section === undefined || entry.section === filter.section) &&
+          (filter.action === undefined || entry.action === filter.action)
+      );
+    }
+  );
+
+export const replaceScheduledAction = (
+  id: number,
+  action: string,
+  section?: string,
+  payload?: unknown
+): Promise<ScheduledAction> =>
+  invokeOrFallback('replace_scheduled_action', { id, action, section: section ?? null, payload: payload ?? null }, () => {
+    const pending = localState.scheduledActions.find((entry) => entry.id === id);
+    if (!pending) {
+      throw new Error(`no pending action with id ${id}`);
+    }
+    pending.action = action;
+    pending.cue = cueActions.find((cue) => cue === action) ?? null;
+    pending.markerId = null;
+    pending.payload = payload ?? null;
+    pending.section = section ?? null;
+    pending.patternId = null;
+    return { ...pending };
+  });
+
+export const flushScheduledActions = (): Promise<ScheduledAction[]> =>
+  invokeOrFallback('flush_scheduled_actions', {}, () => removeLocalActions(() => true));
+
+/** Clears the queue and queues a `safe_state` action to run immediately. */
+export const panicScheduler = (): Promise<ScheduledAction> =>
+  invokeOrFallback('panic_scheduler', {}, () => {
+    removeLocalActions(() => true);
+    localState.patterns = [];
+    const safeState: ScheduledAction = {
+      id: localState.nextActionId++,
+      action: 'safe_state',
+      cue: null,
+      markerId: null,
+      payload: null,
+      section: null,
+      quantize: localState.quantizeGrid,
+      executeAtMs: nowMs(),
+      target: gridTargetAt(nowMs()),
+      priority: 2 ** 31 - 1,
+      origin: 'manual',
+      patternId: null
+    };
+    localState.scheduledActions.push(safeState);
+    return safeState;
+  });
+
+/** Starts a repeating action on the next `start` boundary. The browser preview queues its first cycle. */
+export const startActionPattern = (pattern: ActionPattern, start: QuantizeGrid = '1n'): Promise<RunningPattern> =>
+  invokeOrFallback('start_action_pattern', { pattern, start }, () => {
+    const { steps, mask, bars = [] } = pattern;
+    if (steps < 1 || steps > 32) throw new Error(`a pattern bar holds 1 to 32 steps, got ${steps}`);
+    const cycleBars = Math.max(pattern.cycleBars ?? Math.max(1, ...bars), 1);
+    if (cycleBars > 64) throw new Error(`a pattern cycle spans 1 to 64 bars, got ${cycleBars}`);
+    const startMs = nextQuantizedBoundary(start);
+    const running: RunningPattern = {
+      id: localState.nextActionId++,
+      pattern,
+      start: gridTargetAt(startMs),
+      end: null,
+      nextStep: steps * cycleBars
+    };
+    const stepMs = quantizeDurationMs('1n', localState.tempo.bpm, localState.tempo.meter) / steps;
+    for (let step = 0; step < steps * cycleBars; step += 1) {
+      const bar = Math.floor(step / steps) + 1;
+      if (((mask >>> step % steps) & 1) === 0 || (bars.length > 0 && !bars.includes(bar))) continue;
+      scheduleAction({
+        action: pattern.action,
+        section: pattern.section ?? null,
+        priority: pattern.priority,
+        origin: pattern.origin,
+        payload: pattern.payload,
+        executeAtMs: startMs + step * stepMs,
+        patternId: running.id
+      });
+    }
+    localState.patterns.push(running);
+    return running;
+  });
+
+export const listActionPatterns = (): Promise<RunningPattern[]> =>
+  invokeOrFallback('list_action_patterns', {}, () => [...localState.patterns]);
+
+export const cancelActionPattern = (id: number): Promise<RunningPattern | null> =>
+  invokeOrFallback('cancel_action_pattern', { id }, () => {
+    const running = localState.patterns.find((entry) => entry.id === id) ?? null;
+    localState.patterns = localState.patterns.filter((entry) => entry.id !== id);
+    removeLocalActions((entry) => entry.patternId === id);
+    return running;
+  });
+
+export const getConflictPolicy = (): Promise<ConflictPolicy> =>
+  invokeOrFallback('get_conflict_policy', {}, () => ({ ...localState.conflictPolicy }));
+
+export const setConflictPolicy = (policy: ConflictPolicy): Promise<ConflictPolicy> =>
+  invokeOrFallback('set_conflict_policy', { policy }, () => {
+    localState.conflictPolicy = { ...policy };
+    return policy;
+  });
+
+/** Recent decisions between conflicting actions; the browser preview does not arbitrate. */
+export const getConflictDecisions = (): Promise<ConflictDecision[]> =>
+  invokeOrFallback('get_conflict_decisions', {}, () => []);
+
 export const popDueActions = (timestampMs?: number): Promise<ScheduledAction[]> =>
   invokeOrFallback('pop_due_actions', { timestampMs: timestampMs ?? null }, () => {
     const now = timestampMs ?? nowMs();
@@ -238,6 +780,180 @@ export const popDueActions = (timestampMs?: number): Promise<ScheduledAction[]>
     return due;
   });
 
+export const startActionDispatch = (): Promise<DispatchStats> =>
+  invokeOrFallback('start_action_dispatch', {}, () => {
+    throw new Error('Backend action dispatch is only available in desktop runtime');
+  });
+
+export const stopActionDispatch = (): Promise<void> => invokeOrFallback('stop_action_dispatch', {}, () => undefined);
+
+const localTransportStatus = (): TransportStatus => {
+  const { state, anchorBeats, anchorMs, loopRegion } = localState.transport;
+  const { bpm, meter } = localState.tempo;
+  const bar = barBeats(meter);
+  let beats = state === 'playing' ? anchorBeats + ((nowMs() - anchorMs) * bpm) / 60000 : anchorBeats;
+  let pass = 0;
+  if (state === 'playing' && loopRegion) {
+    const start = (loopRegion.startBar - 1) * bar;
+    const end = (loopRegion.endBar - 1) * bar;
+    if (anchorBeats < end && beats >= end) {
+      pass = Math.floor((beats - end) / (end - start)) + 1;
+      beats = start + ((beats - end) % (end - start));
+    }
+  }
+  const beatUnit = 4 / meter.denominator;
+  const inBar = beats - Math.floor(beats / bar) * bar;
+  const ticks = Math.round((inBar / beatUnit) * 960);
+  return {
+    state,
+    position: { bar: Math.floor(beats / bar) + 1, beat: Math.floor(ticks / 960) + 1, tick: ticks % 960 },
+    beats,
+    pass,
+    loopRegion
+  };
+};
+
+/** Re-anchors the local transport at its current playhead, or at `beats`. */
+const anchorLocalTransport = (state: TransportState, beats = localTransportStatus().beats): TransportStatus => {
+  localState.transport = { ...localState.transport, state, anchorBeats: beats, anchorMs: nowMs() };
+  return localTransportStatus();
+};
+
+export const getTransportStatus = (): Promise<TransportStatus> =>
+  invokeOrFallback('get_transport_status', {}, () => localTransportStatus());
+
+export const transportPlay = (): Promise<TransportStatus> =>
+  invokeOrFallback('transport_play', {}, () => {
+    if (localState.transport.state === 'playing') return localTransportStatus();
+    localState.transport.returnBeats = localState.transport.anchorBeats;
+    return anchorLocalTransport('playing');
+  });
+
+export const transportPause = (): Promise<TransportStatus> =>
+  invokeOrFallback('transport_pause', {}, () =>
+    localState.transport.state === 'playing' ? anchorLocalTransport('paused') : localTransportStatus()
+  );
+
+export const transportStop = (): Promise<TransportStatus> =>
+  invokeOrFallback('transport_stop', {}, () =>
+    anchorLocalTransport('stopped', localState.transport.state === 'stopped' ? 0 : localState.transport.returnBeats)
+  );
+
+export const transportLocate = (bar: number): Promise<TransportStatus> =>
+  invokeOrFallback('transport_locate', { bar }, () => {
+    if (bar < 1) throw new Error(`bar ${bar} is before the start of the song`);
+    return anchorLocalTransport(localState.transport.state, (bar - 1) * barBeats(localState.tempo.meter));
+  });
+
+export const transportSetLoop = (region: LoopRegion | null): Promise<TransportStatus> =>
+  invokeOrFallback('transport_set_loop', { region }, () => {
+    if (region && (region.startBar < 1 || region.endBar <= region.startBar)) {
+      throw new Error(`loop region must end after it starts, got bars ${region.startBar}..${region.endBar}`);
+    }
+    const status = anchorLocalTransport(localState.transport.state);
+    localState.transport.loopRegion = region;
+    return { ...status, loopRegion: region };
+  });
+
+/** Picks the local setlist's next section and due bar the way the backend sequencer does. */
+const decideLocalSetlist = (): SetlistStatus => {
+  const status = localState.setlist;
+  const follow = status.current ? status.followActions[status.current] : undefined;
+  if (!status.overridden) {
+    const order = status.setlist.sections;
+    switch (follow?.action.type) {
+      case 'next': {
+        const index = order.indexOf(status.current ?? '');
+        status.next = index >= 0 ? (order[index + 1] ?? null) : null;
+        break;
+      }
+      case 'loop':
+        status.next = status.current;
+        break;
+      case 'random': {
+        const choices = follow.action.choices;
+        let roll = Math.random() * choices.reduce((total, choice) => total + choice.weight, 0);
+        status.next = choices.find((choice) => (roll -= choice.weight) < 0)?.section ?? null;
+        break;
+      }
+      default:
+        status.next = null;
+    }
+  }
+  const phrase = status.setlist.quantize.endsWith('m') ? Number.parseInt(status.setlist.quantize, 10) : 1;
+  status.dueBar =
+    follow && status.next && status.startedBar !== null && status.setlist.autoAdvance
+      ? status.startedBar + Math.ceil(Math.max(1, follow.afterBars) / phrase) * phrase
+      : null;
+  return { ...status };
+};
+
+export const getSetlistStatus = (): Promise<SetlistStatus> =>
+  invokeOrFallback('get_setlist_status', {}, () => ({ ...localState.setlist }));
+
+export const setSetlist = (setlist: Setlist): Promise<SetlistStatus> =>
+  invokeOrFallback('set_setlist', { setlist }, () => {
+    localState.setlist.setlist = setlist;
+    return decideLocalSetlist();
+  });
+
+export const setFollowAction = (section: string, follow: SectionFollow | null): Promise<SetlistStatus> =>
+  invokeOrFallback('set_follow_action', { section, follow }, () => {
+    const rest = Object.entries(localState.setlist.followActions).filter(([name]) => name !== section);
+    localState.setlist.followActions = Object.fromEntries(follow ? [...rest, [section, follow]] : rest);
+    return decideLocalSetlist();
+  });
+
+export const overrideNextSection = (section: string | null): Promise<SetlistStatus> =>
+  invokeOrFallback('override_next_section', { section }, () => {
+    localState.setlist.overridden = section !== null;
+    localState.setlist.next = section;
+    return decideLocalSetlist();
+  });
+
+export const startSetlist = (section?: string): Promise<SetlistStatus> =>
+  invokeOrFallback('start_setlist', { section: section ?? null }, () => {
+    const first = section ?? localState.setlist.setlist.sections[0];
+    if (!first) throw new Error('setlist is empty');
+    localState.activeSection = first;
+    const startedBar = gridTargetAt(nextQuantizedBoundary('1n')).bar;
+    Object.assign(localState.setlist, { current: first, startedBar, overridden: false });
+    return decideLocalSetlist();
+  });
+
+export const stopSetlist = (): Promise<SetlistStatus> =>
+  invokeOrFallback('stop_setlist', {}, () => {
+    Object.assign(localState.setlist, { current: null, startedBar: null, next: null, overridden: false });
+    return decideLocalSetlist();
+  });
+
+/** Subscribes to sections the setlist starts, including follow-action advances. */
+export const onSetlistSectionStarted = async (handler: (status: SetlistStatus) => void): Promise<UnlistenFn> => {
+  if (!hasTauriRuntime()) {
+    return () => undefined;
+  }
+  return listen<SetlistStatus>('setlist://section-started', (event) => handler(event.payload));
+};
+
+/** Subscribes to every transport transition, including loop wraps. */
+export const onTransportChanged = async (handler: (change: TransportChange) => void): Promise<UnlistenFn> => {
+  if (!hasTauriRuntime()) {
+    return () => undefined;
+  }
+  return listen<TransportChange>('transport://state-changed', (event) => handler(event.payload));
+};
+
+export const getDispatchStats = (): Promise<DispatchStats | null> =>
+  invokeOrFallback('get_dispatch_stats', {}, () => null);
+
+/** Subscribes to actions dispatched by the backend scheduler thread. */
+export const onActionDispatched = async (handler: (action: DispatchedAction) => void): Promise<UnlistenFn> => {
+  if (!hasTauriRuntime()) {
+    return () => undefined;
+  }
+  return listen<DispatchedAction>('scheduler://action-dispatched', (event) => handler(event.payload));
+};
+
 export const detectRuntimeCapabilities = (): Promise<RuntimeCapabilities> =>
   invokeOrFallback('detect_runtime_capabilities', {}, () => refreshBrowserCapabilities());
 
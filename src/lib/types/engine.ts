@@ -1,8 +1,9 @@
-import type { QuantizeGrid } from '$lib/types/timeline';
+import type { CueAction, Meter, QuantizeGrid } from '$lib/types/timeline';
 
 export type RendererBackend = 'webgl2' | 'webgpu';
 export type DecodeBackend = 'htmlvideo' | 'webcodecs' | 'native_ffmpeg';
 export type TempoSource = 'manual' | 'tap' | 'link' | 'midi_clock' | 'auto';
+export type TempoRamp = 'step' | 'linear' | 'ease_in' | 'ease_out';
 export type AudioInputSource = 'none' | 'file' | 'mic';
 export type ReactiveBandTarget = 'low' | 'mid' | 'high' | 'full';
 
@@ -22,6 +23,142 @@ export interface TempoState {
   downbeatEpochMs: number;
   source: TempoSource;
   tapCount: number;
+  meter: Meter;
+  tempoMap: TempoEvent[];
+}
+
+export interface TapTempoConfig {
+  preferredMinBpm: number;
+  preferredMaxBpm: number;
+  sessionTimeoutMs: number;
+  outlierTolerance: number;
+  downbeatOnFourCount: boolean;
+}
+
+export type CandidateState = 'leading' | 'outranked' | 'pending' | 'stale' | 'low_confidence' | 'disabled';
+
+export interface SourcePolicy {
+  source: TempoSource;
+  minConfidence: number;
+  staleAfterMs: number | null;
+  takeoverMs: number;
+}
+
+export interface ArbiterPolicy {
+  sources: SourcePolicy[];
+  blendToleranceBpm: number | null;
+}
+
+export interface CandidateStatus {
+  source: TempoSource;
+  bpm: number;
+  confidence: number;
+  ageMs: number;
+  state: CandidateState;
+}
+
+export interface ArbiterStatus {
+  leader: TempoSource | null;
+  reason: string;
+  candidates: CandidateStatus[];
+}
+
+export type TempoLogKind = 'set_bpm' | 'nudge' | 'ramp' | 'tempo_map' | 'tap' | 'resync' | 'external' | 'handover';
+export type TempoLogFormat = 'json' | 'csv';
+
+export interface TempoLogEntry {
+  timestampMs: number;
+  kind: TempoLogKind;
+  source: TempoSource;
+  confidence: number;
+  requestedBpm: number;
+  bpm: number;
+  downbeatEpochMs: number;
+  accepted: boolean;
+}
+
+export interface DriftReference {
+  beats: number[];
+  originMs: number;
+}
+
+export interface DriftReport {
+  comparedBeats: number;
+  skippedBeats: number;
+  meanOffsetMs: number;
+  meanAbsOffsetMs: number;
+  rmsOffsetMs: number;
+  maxAbsOffsetMs: number;
+  meanBpmError: number;
+}
+
+export interface TempoLogExport {
+  format: TempoLogFormat;
+  content: string;
+  drift: DriftReport | null;
+}
+
+export interface TempoEvent {
+  beat: number;
+  bpm: number;
+  ramp: TempoRamp;
+}
+
+export interface BeatPosition {
+  bar: number;
+  beat: number;
+  tick: number;
+  phase: number;
+}
+
+export interface GridTarget {
+  bar: number;
+  beat: number;
+  tick: number;
+}
+
+export interface LinkStatus {
+  nodeId: string;
+  sessionId: string;
+  peerCount: number;
+  bpm: number;
+}
+
+export interface MidiClockStatus {
+  bpm: number | null;
+  confidence: number;
+  downbeatEpochMs: number | null;
+  running: boolean;
+  songPositionTicks: number;
+}
+
+export interface OscStatus {
+  port: number;
+  replyPort: number | null;
+  received: number;
+  errors: number;
+}
+
+export interface OscOutputConfig {
+  targets: string[];
+  beats: boolean;
+  bars: boolean;
+  sections: boolean;
+  actions: boolean;
+  lookAheadMs: number;
+}
+
+export interface OscOutputStatus {
+  config: OscOutputConfig;
+  sent: number;
+  errors: number;
+}
+
+export interface TempoEstimate {
+  bpm: number;
+  confidence: number;
+  beatEpochMs: number;
+  beatPhase: number;
 }
 
 export interface DetectedTempoState {
@@ -31,12 +168,107 @@ export interface DetectedTempoState {
   updatedAtMs: number | null;
 }
 
+export type ActionOrigin = 'authored' | 'auto' | 'manual';
+
 export interface ScheduledAction {
   id: number;
   action: string;
+  cue: CueAction | null;
+  markerId: string | null;
+  payload: unknown;
   section: string | null;
   quantize: QuantizeGrid;
   executeAtMs: number;
+  target: GridTarget;
+  priority: number;
+  origin: ActionOrigin;
+  patternId: number | null;
+}
+
+/** Repeating action: `steps` per bar, firing where `mask` has a bit set (bit 0 first). */
+export interface ActionPattern {
+  action: string;
+  payload?: unknown;
+  /** The pattern stops when this section hands over. */
+  section?: string | null;
+  steps: number;
+  mask: number;
+  /** Bars of the cycle that play, from 1; empty plays every bar. */
+  bars?: number[];
+  /** Defaults to the highest of `bars`. */
+  cycleBars?: number | null;
+  priority?: number;
+  origin?: ActionOrigin;
+}
+
+export interface RunningPattern {
+  id: number;
+  pattern: ActionPattern;
+  start: GridTarget;
+  end: GridTarget | null;
+  nextStep: number;
+}
+
+export type SectionPlayback = 'sequence' | 'next_boundary';
+
+export interface SectionChange {
+  from: string | null;
+  to: string;
+  quantize: QuantizeGrid;
+  at: GridTarget;
+  executeAtMs: number;
+}
+
+export interface ConflictPolicy {
+  exclusiveActions: string[];
+}
+
+export type ConflictOutcome = 'preempted' | 'suppressed' | 'superseded';
+
+export interface ConflictDecision {
+  decidedAtMs: number;
+  outcome: ConflictOutcome;
+  winner: ScheduledAction;
+  loser: ScheduledAction;
+}
+
+export type TransportState = 'stopped' | 'playing' | 'paused';
+export type TransportChangeKind = 'play' | 'pause' | 'stop' | 'locate' | 'loop' | 'wrap';
+
+export interface LoopRegion {
+  startBar: number;
+  endBar: number;
+}
+
+export interface TransportStatus {
+  state: TransportState;
+  position: GridTarget;
+  beats: number;
+  pass: number;
+  loopRegion: LoopRegion | null;
+}
+
+export interface TransportChange {
+  kind: TransportChangeKind;
+  previous: TransportState;
+  status: TransportStatus;
+  atMs: number;
+}
+
+export interface DispatchedAction {
+  action: ScheduledAction;
+  dispatchedAtUs: number;
+  latencyUs: number;
+}
+
+export interface DispatchStats {
+  dispatched: number;
+  failed: number;
+  lastLatencyUs: number | null;
+  meanLatencyUs: number;
+  p95LatencyUs: number;
+  maxLatencyUs: number;
+  raisedPriority: boolean;
 }
 
 export interface AudioBandState {
@@ -1,9 +1,29 @@
 export type TimelineAuthority = 'rust_clock';
 export type TheatreUsage = 'authoring_only';
 
-export type QuantizeGrid = '1n' | '1/2n' | '1/4n' | '1/8n' | '1/16n';
+export type QuantizeGrid =
+  | '1n'
+  | '1/2n.'
+  | '1/2n'
+  | '1/4n.'
+  | '1/4n'
+  | '1/4t'
+  | '1/8n.'
+  | '1/8n'
+  | '1/8t'
+  | '1/16n'
+  | '1/32n'
+  | '2m'
+  | '4m'
+  | '8m'
+  | '16m';
 export type CueAction = 'trigger_clip' | 'apply_accent' | 'swap_scene';
 
+export interface Meter {
+  numerator: number;
+  denominator: number;
+}
+
 export interface EngineSequence {
   id: string;
   name: string;
@@ -34,11 +54,43 @@ export interface EngineEnvelopeTemplate {
 export interface TheatreExportBundle {
   version: string;
   fps: number;
+  meter?: Meter;
   sequences: EngineSequence[];
   cueMarkers: EngineCueMarker[];
   envelopeTemplates: EngineEnvelopeTemplate[];
 }
 
+export interface WeightedSection {
+  section: string;
+  weight: number;
+}
+
+export type FollowAction =
+  | { type: 'next' }
+  | { type: 'loop' }
+  | { type: 'random'; choices: WeightedSection[] };
+
+export interface SectionFollow {
+  afterBars: number;
+  action: FollowAction;
+}
+
+export interface Setlist {
+  sections: string[];
+  quantize: QuantizeGrid;
+  autoAdvance: boolean;
+}
+
+export interface SetlistStatus {
+  setlist: Setlist;
+  followActions: Record<string, SectionFollow>;
+  current: string | null;
+  startedBar: number | null;
+  next: string | null;
+  overridden: boolean;
+  dueBar: number | null;
+}
+
 export const isTheatreExportBundle = (value: unknown): value is TheatreExportBundle => {
   if (!value || typeof value !== 'object') return false;
   const candidate = value as Partial<TheatreExportBundle>;